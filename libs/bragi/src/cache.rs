@@ -0,0 +1,290 @@
+//! In memory cache of the bragi responses
+//!
+//! The cache is a LRU bounded in number of entries, and the entries expire after a TTL.
+
+use crate::model::BragiError;
+use crate::prometheus_middleware::CACHE_COUNTER;
+use actix_web::web::Bytes;
+use futures::future::{self, Future};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+struct Entry {
+    value: Bytes,
+    inserted: Instant,
+    /// last time the entry has been used (cf `Lru::tick`)
+    last_used: u64,
+}
+
+/// A LRU map with a bounded number of entries, each entry expires after `ttl`
+struct Lru {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<String, Entry>,
+    /// the keys ordered by last use
+    usage: BTreeMap<u64, String>,
+    /// logical clock incremented at each use of an entry
+    tick: u64,
+}
+
+impl Lru {
+    fn new(capacity: usize, ttl: Duration) -> Self {
+        Lru {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            usage: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str, now: Instant) -> Option<Bytes> {
+        let expired = match self.entries.get(key) {
+            None => return None,
+            Some(entry) => now.duration_since(entry.inserted) >= self.ttl,
+        };
+        if expired {
+            self.remove(key);
+            return None;
+        }
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = self.entries.get_mut(key)?;
+        self.usage.remove(&entry.last_used);
+        self.usage.insert(tick, key.to_string());
+        entry.last_used = tick;
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: String, value: Bytes, now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(&key);
+        while self.entries.len() >= self.capacity {
+            let oldest = match self.usage.keys().next() {
+                Some(&tick) => tick,
+                None => break,
+            };
+            if let Some(oldest_key) = self.usage.remove(&oldest) {
+                self.entries.remove(&oldest_key);
+            }
+        }
+        self.tick += 1;
+        self.usage.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                inserted: now,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.usage.remove(&entry.last_used);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Cache of the serialized responses of bragi, shared by all the workers
+pub struct ResponseCache {
+    lru: Mutex<Lru>,
+}
+
+impl std::fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lru = self.lru.lock().unwrap();
+        f.debug_struct("ResponseCache")
+            .field("capacity", &lru.capacity)
+            .field("ttl", &lru.ttl)
+            .field("len", &lru.len())
+            .finish()
+    }
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        ResponseCache {
+            lru: Mutex::new(Lru::new(capacity, ttl)),
+        }
+    }
+
+    fn get(&self, handler: &str, key: &str) -> Option<Bytes> {
+        let value = self.lru.lock().unwrap().get(key, Instant::now());
+        let result = if value.is_some() { "hit" } else { "miss" };
+        CACHE_COUNTER.with_label_values(&[handler, result]).inc();
+        value
+    }
+
+    fn insert(&self, key: String, value: Bytes) {
+        self.lru.lock().unwrap().insert(key, value, Instant::now());
+    }
+}
+
+/// Get the json response of a query through the cache if there is one,
+/// `compute` is called to build the response if it is not in the cache.
+///
+/// The cache key is built from the serialized `params` (cf `cache_key`),
+/// so the params must contain everything the response depends on.
+/// The errors are not cached.
+pub fn cached<P, T, F, R>(
+    cache: &Option<Arc<ResponseCache>>,
+    handler: &str,
+    params: &P,
+    compute: F,
+) -> Box<dyn Future<Item = Bytes, Error = BragiError>>
+where
+    P: Serialize,
+    T: Serialize,
+    F: FnOnce() -> R,
+    R: Future<Item = T, Error = BragiError> + 'static,
+{
+    let cache = match cache {
+        Some(cache) => cache.clone(),
+        None => return Box::new(compute().map(|response| to_json(&response))),
+    };
+    let key = cache_key(handler, params);
+    if let Some(value) = cache.get(handler, &key) {
+        return Box::new(future::ok(value));
+    }
+    // the lock is not held while computing the response, so that the queries are not serialized
+    // (2 identical queries might thus be computed at the same time, which is not a problem)
+    Box::new(compute().map(move |response| {
+        let value = to_json(&response);
+        cache.insert(key, value.clone());
+        value
+    }))
+}
+
+/// Build the cache key of a query.
+///
+/// The params are serialized with their default values, and always in the same order.
+/// The repeated params (like `type[]`) are sorted, so 2 equivalent queries share the same key.
+/// The `timeout` is part of the key: a search that timed out can give a partial response,
+/// which must not be served to the queries with a longer timeout.
+fn cache_key<P: Serialize>(handler: &str, params: &P) -> String {
+    let mut params = serde_json::to_value(params).expect("impossible to serialize the params");
+    normalize_params(&mut params);
+    format!("{}:{}", handler, params)
+}
+
+fn normalize_params(value: &mut serde_json::Value) {
+    use serde_json::Value;
+    match value {
+        Value::Object(fields) => {
+            for field in fields.values_mut() {
+                if let Value::Array(values) = field {
+                    // the repeated params are sets, their order does not matter
+                    values.sort_by_cached_key(|v| v.to_string());
+                    values.dedup();
+                } else {
+                    normalize_params(field);
+                }
+            }
+        }
+        // the params can be given in a tuple, like the id and the params of /features
+        Value::Array(values) => values.iter_mut().for_each(normalize_params),
+        _ => {}
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Bytes {
+    // the bragi responses only have string keys, their serialization cannot fail
+    Bytes::from(serde_json::to_vec(value).expect("impossible to serialize the response"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn value(s: &'static str) -> Bytes {
+        Bytes::from_static(s.as_bytes())
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let now = Instant::now();
+        let mut lru = Lru::new(2, Duration::from_secs(60));
+        lru.insert("a".into(), value("1"), now);
+        lru.insert("b".into(), value("2"), now);
+        // 'a' is used, so 'b' is the least recently used
+        assert_eq!(lru.get("a", now), Some(value("1")));
+        lru.insert("c".into(), value("3"), now);
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.get("b", now), None);
+        assert_eq!(lru.get("a", now), Some(value("1")));
+        assert_eq!(lru.get("c", now), Some(value("3")));
+    }
+
+    #[test]
+    fn lru_entries_expire() {
+        let now = Instant::now();
+        let mut lru = Lru::new(2, Duration::from_secs(60));
+        lru.insert("a".into(), value("1"), now);
+        assert_eq!(
+            lru.get("a", now + Duration::from_secs(59)),
+            Some(value("1"))
+        );
+        assert_eq!(lru.get("a", now + Duration::from_secs(60)), None);
+        assert_eq!(lru.len(), 0);
+    }
+
+    #[test]
+    fn lru_reinsert_replaces_value() {
+        let now = Instant::now();
+        let mut lru = Lru::new(2, Duration::from_secs(60));
+        lru.insert("a".into(), value("1"), now);
+        lru.insert("a".into(), value("2"), now);
+        assert_eq!(lru.len(), 1);
+        assert_eq!(lru.usage.len(), 1);
+        assert_eq!(lru.get("a", now), Some(value("2")));
+    }
+
+    #[test]
+    fn cache_key_ignores_params_order() {
+        let key = |params: serde_json::Value| cache_key("test", &params);
+        assert_eq!(
+            key(json!({"q": "paris", "timeout": 100, "type": ["poi", "street"]})),
+            key(json!({"q": "paris", "timeout": 100, "type": ["street", "poi"]}))
+        );
+        assert_eq!(
+            key(json!(["id", {"pt_dataset": ["b", "a"]}])),
+            key(json!(["id", {"pt_dataset": ["a", "b"]}]))
+        );
+        // a response computed with a short timeout can be partial
+        assert_ne!(
+            key(json!({"q": "paris", "timeout": 100})),
+            key(json!({"q": "paris", "timeout": 200}))
+        );
+        assert_ne!(
+            key(json!({"q": "paris", "type": ["poi"]})),
+            key(json!({"q": "paris", "type": ["street"]}))
+        );
+    }
+
+    #[test]
+    fn errors_are_not_cached() {
+        let cache = Some(Arc::new(ResponseCache::new(10, Duration::from_secs(60))));
+        let res = cached(&cache, "test", &"key", || {
+            future::err::<String, _>(BragiError::ObjectNotFound)
+        });
+        assert!(res.wait().is_err());
+        let res = cached(&cache, "test", &"key", || future::ok("value"));
+        assert_eq!(res.wait().unwrap(), value("\"value\""));
+        // the value is now in the cache, it is not computed again
+        let res = cached(&cache, "test", &"key", || {
+            future::err::<String, _>(BragiError::ObjectNotFound)
+        });
+        assert_eq!(res.wait().unwrap(), value("\"value\""));
+    }
+}
@@ -31,11 +31,14 @@
 #[macro_use]
 extern crate prometheus;
 
+use crate::cache::ResponseCache;
+use mimir::backend::Backend;
 use mimir::rubber::Rubber;
-use slog_scope::debug;
+use std::sync::Arc;
 use std::time::Duration;
 use structopt::StructOpt;
 
+mod cache;
 mod extractors;
 mod model;
 pub mod prometheus_middleware;
@@ -74,18 +77,18 @@ pub struct Args {
     pub max_es_timeout: Option<u64>,
 
     /// Custom timeout for the /reverse
-    /// this is bounded by `max_es_timeout` and is used because for the moment we cannot easily change the timeout of a given rubber
+    /// this is bounded by `max_es_timeout`
     #[structopt(long = "max-es-reverse-timeout", env = "BRAGI_MAX_ES_REVERSE_TIMEOUT")]
     pub max_es_reverse_timeout: Option<u64>,
     /// Custom timeout for the /autocomplete
-    /// this is bounded by `max_es_timeout` and is used because for the moment we cannot easily change the timeout of a given rubber
+    /// this is bounded by `max_es_timeout`
     #[structopt(
         long = "max-es-autocomplete-timeout",
         env = "BRAGI_MAX_ES_AUTOCOMPLETE_TIMEOUT"
     )]
     pub max_es_autocomplete_timeout: Option<u64>,
     /// Custom timeout for the /features
-    /// this is bounded by `max_es_timeout` and is used because for the moment we cannot easily change the timeout of a given rubber
+    /// this is bounded by `max_es_timeout`
     #[structopt(
         long = "max-es-features-timeout",
         env = "BRAGI_MAX_ES_FEATURES_TIMEOUT"
@@ -94,6 +97,7 @@ pub struct Args {
 
     /// Cache duration for http response served by bragi
     /// This only set the Cache-control Header, it doesn't enable cache on bragi side
+    /// (cf `cache_size` for that)
     /// The duration is in seconds
     #[structopt(
         long = "http-cache-duration",
@@ -101,72 +105,95 @@ pub struct Args {
         default_value = "3600"
     )]
     pub http_cache_duration: u32,
+
+    /// Max number of responses kept in the in memory cache of bragi
+    /// (for /autocomplete, /features and /reverse).
+    /// If not set, there is no cache on bragi side
+    #[structopt(long = "cache-size", env = "BRAGI_CACHE_SIZE")]
+    pub cache_size: Option<usize>,
+    /// Duration (in seconds) during which a response is kept in the in memory cache
+    #[structopt(long = "cache-ttl", env = "BRAGI_CACHE_TTL", default_value = "60")]
+    pub cache_ttl: u64,
 }
 
 #[derive(Clone, Debug)]
 pub struct Context {
-    reverse_rubber: Rubber,
-    features_rubber: Rubber,
-    autocomplete_rubber: Rubber,
+    reverse_backend: Arc<dyn Backend>,
+    features_backend: Arc<dyn Backend>,
+    autocomplete_backend: Arc<dyn Backend>,
     pub cnx_string: String,
     pub http_cache_duration: u32,
+    pub(crate) cache: Option<Arc<ResponseCache>>,
     // pub rubber: Rubber,
 }
 
+impl Args {
+    /// the timeout is the min between the timeout set at startup time and at query time
+    fn bounded_timeout(&self, specific_timeout: Option<u64>) -> Option<Duration> {
+        let max_es_timeout = self.max_es_timeout.map(Duration::from_millis);
+        specific_timeout
+            .map(Duration::from_millis)
+            .map(|t| match max_es_timeout {
+                Some(dt) => t.min(dt),
+                None => t,
+            })
+            .or(max_es_timeout)
+    }
+
+    fn response_cache(&self) -> Option<Arc<ResponseCache>> {
+        self.cache_size.map(|size| {
+            Arc::new(ResponseCache::new(
+                size,
+                Duration::from_secs(self.cache_ttl),
+            ))
+        })
+    }
+}
+
 impl From<&Args> for Context {
     fn from(args: &Args) -> Self {
-        let max_es_timeout = args.max_es_timeout.map(Duration::from_millis);
-
-        // the timeout is the min between the timeout set at startup time and at query time
-        let bounded_timeout = |specific_timeout: Option<u64>| {
-            specific_timeout
-                .map(Duration::from_millis)
-                .map(|t| match max_es_timeout {
-                    Some(dt) => t.min(dt),
-                    None => t,
-                })
-                .or_else(|| max_es_timeout.clone())
+        let rubber = |specific_timeout| -> Arc<dyn Backend> {
+            Arc::new(Rubber::new_with_timeout(
+                &args.connection_string,
+                args.bounded_timeout(specific_timeout),
+            ))
         };
-
         Self {
-            reverse_rubber: Rubber::new_with_timeout(
-                &args.connection_string,
-                bounded_timeout(args.max_es_reverse_timeout),
-            ),
-            features_rubber: Rubber::new_with_timeout(
-                &args.connection_string,
-                bounded_timeout(args.max_es_features_timeout),
-            ),
-            autocomplete_rubber: Rubber::new_with_timeout(
-                &args.connection_string,
-                bounded_timeout(args.max_es_autocomplete_timeout),
-            ),
+            reverse_backend: rubber(args.max_es_reverse_timeout),
+            features_backend: rubber(args.max_es_features_timeout),
+            autocomplete_backend: rubber(args.max_es_autocomplete_timeout),
             cnx_string: args.connection_string.clone(),
-            http_cache_duration: args.http_cache_duration.clone(),
+            http_cache_duration: args.http_cache_duration,
+            cache: args.response_cache(),
         }
     }
 }
 
 impl Context {
-    pub fn get_rubber_for_reverse(&self, timeout: Option<Duration>) -> Rubber {
-        clone_or_create(&self.reverse_rubber, timeout)
+    /// a context searching in `backend` instead of the elasticsearch of `args`
+    /// (like a `mimir::memory::MemoryBackend` for the tests)
+    pub fn with_backend(args: &Args, backend: Arc<dyn Backend>) -> Self {
+        Self {
+            reverse_backend: backend
+                .with_timeout(args.bounded_timeout(args.max_es_reverse_timeout)),
+            features_backend: backend
+                .with_timeout(args.bounded_timeout(args.max_es_features_timeout)),
+            autocomplete_backend: backend
+                .with_timeout(args.bounded_timeout(args.max_es_autocomplete_timeout)),
+            cnx_string: args.connection_string.clone(),
+            http_cache_duration: args.http_cache_duration,
+            cache: args.response_cache(),
+        }
     }
-    pub fn get_rubber_for_features(&self, timeout: Option<Duration>) -> Rubber {
-        clone_or_create(&self.features_rubber, timeout)
+
+    // the backends share the connections of the configured ones, only their timeout is changed
+    pub fn get_backend_for_reverse(&self, timeout: Option<Duration>) -> Arc<dyn Backend> {
+        self.reverse_backend.with_timeout(timeout)
     }
-    pub fn get_rubber_for_autocomplete(&self, timeout: Option<Duration>) -> Rubber {
-        clone_or_create(&self.autocomplete_rubber, timeout)
+    pub fn get_backend_for_features(&self, timeout: Option<Duration>) -> Arc<dyn Backend> {
+        self.features_backend.with_timeout(timeout)
     }
-}
-
-fn clone_or_create(rubber: &Rubber, timeout: Option<Duration>) -> Rubber {
-    if rubber.timeout == timeout {
-        // we clone the rs_es_client, reusing the reqwest connection pool
-        rubber.clone()
-    } else {
-        // if the timeout is different, since there as no easy way to change the timeout for the moment
-        // we build a new Rubber (and thus a new connection)
-        debug!("creating a new rubber for timeout {:?}", &timeout);
-        Rubber::new_with_timeout(&rubber.cnx_string, timeout)
+    pub fn get_backend_for_autocomplete(&self, timeout: Option<Duration>) -> Arc<dyn Backend> {
+        self.autocomplete_backend.with_timeout(timeout)
     }
 }
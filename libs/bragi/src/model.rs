@@ -55,46 +55,55 @@ pub struct ApiError {
     pub long: String,
 }
 
-// Q: It would be better to move it to ::v1 as it depends on the api interface
-// how can we do this ?
-impl actix_web::error::ResponseError for BragiError {
-    fn render_response(&self) -> actix_web::HttpResponse {
+impl BragiError {
+    /// the json error returned to the user
+    pub fn to_api_error(&self) -> ApiError {
         match *self {
-            BragiError::ObjectNotFound => actix_web::HttpResponse::NotFound().json(ApiError {
+            BragiError::ObjectNotFound | BragiError::IndexNotFound => ApiError {
                 short: "query error".to_owned(),
                 long: format!("{}", self),
-            }),
-            BragiError::InvalidShape(_) => actix_web::HttpResponse::BadRequest().json(ApiError {
-                short: "validation error".to_owned(),
-                long: format!("{}", self),
-            }),
-            BragiError::InvalidParam(_) => actix_web::HttpResponse::BadRequest().json(ApiError {
+            },
+            BragiError::InvalidShape(_) | BragiError::InvalidParam(_) => ApiError {
                 short: "validation error".to_owned(),
                 long: format!("{}", self),
-            }),
-            BragiError::IndexNotFound => actix_web::HttpResponse::NotFound().json(ApiError {
-                short: "query error".to_owned(),
-                long: format!("{}", self),
-            }),
+            },
             BragiError::Es(ref es_error) => {
                 error!("es error on query: {}", &es_error);
                 match es_error {
-                    EsError::HttpError(_) => {
-                        actix_web::HttpResponse::ServiceUnavailable().json(ApiError {
-                            short: "query error".to_owned(),
-                            long: "service unavailable".to_owned(),
-                        })
-                    }
-                    _ => actix_web::HttpResponse::InternalServerError().json(ApiError {
+                    EsError::HttpError(_) | EsError::IoError(_) => ApiError {
+                        short: "query error".to_owned(),
+                        long: "service unavailable".to_owned(),
+                    },
+                    _ => ApiError {
                         short: "query error".to_owned(),
                         long: "internal server error".to_owned(),
-                    }),
+                    },
                 }
             }
         }
     }
 }
 
+// Q: It would be better to move it to ::v1 as it depends on the api interface
+// how can we do this ?
+impl actix_web::error::ResponseError for BragiError {
+    fn render_response(&self) -> actix_web::HttpResponse {
+        let api_error = self.to_api_error();
+        match *self {
+            BragiError::ObjectNotFound | BragiError::IndexNotFound => {
+                actix_web::HttpResponse::NotFound().json(api_error)
+            }
+            BragiError::InvalidShape(_) | BragiError::InvalidParam(_) => {
+                actix_web::HttpResponse::BadRequest().json(api_error)
+            }
+            BragiError::Es(EsError::HttpError(_)) | BragiError::Es(EsError::IoError(_)) => {
+                actix_web::HttpResponse::ServiceUnavailable().json(api_error)
+            }
+            BragiError::Es(_) => actix_web::HttpResponse::InternalServerError().json(api_error),
+        }
+    }
+}
+
 impl From<EsError> for BragiError {
     fn from(e: EsError) -> Self {
         BragiError::Es(e)
@@ -105,6 +114,10 @@ impl From<EsError> for BragiError {
 pub struct Geocoding {
     version: String,
     query: Option<String>,
+    /// true if the features are not a match of the query,
+    /// but the admin containing the searched point (for a reverse geocoding)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    admin_fallback: bool,
 }
 
 #[derive(Serialize, Debug)]
@@ -218,6 +231,16 @@ pub struct GeocodingResponse {
     pub codes: Vec<mimir::Code>,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub feed_publishers: Vec<mimir::FeedPublisher>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_area: Option<mimir::ParentStopArea>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<mimir::Rgb>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_color: Option<mimir::Rgb>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<mimir::Network>,
     #[serde(
         serialize_with = "mimir::objects::serialize_rect",
         skip_serializing_if = "Option::is_none",
@@ -240,6 +263,9 @@ impl ToGeom for mimir::Place {
             mimir::Place::Addr(ref addr) => addr.coord.to_geom(),
             mimir::Place::Poi(ref poi) => poi.coord.to_geom(),
             mimir::Place::Stop(ref stop) => stop.coord.to_geom(),
+            mimir::Place::StopPoint(ref stop_point) => stop_point.coord.to_geom(),
+            mimir::Place::PtLine(ref line) => line.coord.to_geom(),
+            mimir::Place::PtNetwork(ref network) => network.coord.to_geom(),
         }
     }
 }
@@ -261,6 +287,11 @@ impl FromWithLang<mimir::Place> for Feature {
             mimir::Place::Addr(addr) => GeocodingResponse::from_with_lang(addr, lang),
             mimir::Place::Poi(poi) => GeocodingResponse::from_with_lang(poi, lang),
             mimir::Place::Stop(poi) => GeocodingResponse::from_with_lang(poi, lang),
+            mimir::Place::StopPoint(stop_point) => {
+                GeocodingResponse::from_with_lang(stop_point, lang)
+            }
+            mimir::Place::PtLine(line) => GeocodingResponse::from_with_lang(line, lang),
+            mimir::Place::PtNetwork(network) => GeocodingResponse::from_with_lang(network, lang),
         };
         Feature {
             feature_type: "Feature".to_string(),
@@ -274,6 +305,25 @@ impl FromWithLang<mimir::Place> for Feature {
     }
 }
 
+impl Feature {
+    /// the feature of the place with its whole geometry (the lines of a street)
+    /// instead of its coord
+    pub fn with_full_geometry(place: mimir::Place) -> Feature {
+        let geometry = match place {
+            mimir::Place::Street(ref street) => street
+                .geometry
+                .as_ref()
+                .map(|lines| geojson::Geometry::new(geojson::Value::from(lines))),
+            _ => None,
+        };
+        let mut feature = Feature::from_with_lang(place, None);
+        if let Some(geometry) = geometry {
+            feature.geometry = geometry;
+        }
+        feature
+    }
+}
+
 pub trait FromWithLang<T> {
     fn from_with_lang(_: T, lang: Option<&str>) -> Self;
 }
@@ -512,6 +562,81 @@ impl FromWithLang<mimir::Stop> for GeocodingResponse {
     }
 }
 
+impl FromWithLang<mimir::StopPoint> for GeocodingResponse {
+    fn from_with_lang(other: mimir::StopPoint, lang: Option<&str>) -> GeocodingResponse {
+        let type_ = "public_transport:stop_point".to_string();
+        let label = Some(other.label);
+        let name = Some(other.name);
+        let admins = other.administrative_regions;
+        let city = get_city_name(&admins);
+        let postcode = if other.zip_codes.is_empty() {
+            None
+        } else {
+            Some(other.zip_codes.join(";"))
+        };
+        let citycode = get_citycode(&admins);
+
+        let associated_admins = admins
+            .iter()
+            .map(|a| AssociatedAdmin::from_with_lang(a, lang))
+            .collect();
+
+        GeocodingResponse {
+            id: other.id,
+            citycode: citycode,
+            place_type: type_,
+            name: name,
+            postcode: postcode,
+            label: label,
+            city: city,
+            administrative_regions: associated_admins,
+            commercial_modes: other.commercial_modes,
+            physical_modes: other.physical_modes,
+            lines: other.lines,
+            comments: other.comments,
+            timezone: Some(other.timezone),
+            codes: other.codes,
+            properties: other.properties,
+            feed_publishers: other.feed_publishers,
+            stop_area: Some(other.stop_area),
+            country_codes: other.country_codes,
+            ..Default::default()
+        }
+    }
+}
+
+impl FromWithLang<mimir::PtLine> for GeocodingResponse {
+    fn from_with_lang(other: mimir::PtLine, _lang: Option<&str>) -> GeocodingResponse {
+        let type_ = "public_transport:line".to_string();
+        GeocodingResponse {
+            id: other.id,
+            place_type: type_,
+            name: Some(other.name),
+            label: Some(other.label),
+            code: other.code,
+            color: other.color,
+            text_color: other.text_color,
+            commercial_modes: other.commercial_mode.into_iter().collect(),
+            physical_modes: other.physical_modes,
+            network: other.network,
+            ..Default::default()
+        }
+    }
+}
+
+impl FromWithLang<mimir::PtNetwork> for GeocodingResponse {
+    fn from_with_lang(other: mimir::PtNetwork, _lang: Option<&str>) -> GeocodingResponse {
+        let type_ = "public_transport:network".to_string();
+        GeocodingResponse {
+            id: other.id,
+            place_type: type_,
+            name: Some(other.name),
+            label: Some(other.label),
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct Autocomplete {
     #[serde(rename = "type")]
@@ -528,10 +653,37 @@ impl Autocomplete {
             geocoding: Geocoding {
                 version: "0.1.0".to_string(),
                 query: Some(q),
+                admin_fallback: false,
             },
             features: features,
         }
     }
+
+    /// flag the response as an admin fallback of a reverse geocoding
+    pub fn with_admin_fallback(mut self) -> Autocomplete {
+        self.geocoding.admin_fallback = true;
+        self
+    }
+}
+
+/// Response of one query of a batch autocomplete,
+/// an error on a query does not fail the whole batch
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum BatchAutocompleteItem {
+    Autocomplete(Autocomplete),
+    Error { error: ApiError },
+}
+
+impl From<Result<Autocomplete, BragiError>> for BatchAutocompleteItem {
+    fn from(res: Result<Autocomplete, BragiError>) -> Self {
+        match res {
+            Ok(autocomplete) => BatchAutocompleteItem::Autocomplete(autocomplete),
+            Err(e) => BatchAutocompleteItem::Error {
+                error: e.to_api_error(),
+            },
+        }
+    }
 }
 
 impl FromWithLang<Vec<mimir::Place>> for Autocomplete {
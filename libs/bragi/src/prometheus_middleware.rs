@@ -25,6 +25,7 @@ lazy_static::lazy_static! {
         map.insert("/status", "status");
         map.insert("/reverse", "reverse");
         map.insert("/autocomplete", "autocomplete");
+        map.insert("/autocomplete/batch", "batch_autocomplete");
         map
     };
 
@@ -67,6 +68,13 @@ lazy_static::lazy_static! {
         "current number of http request being served"
     )
     .unwrap();
+
+    pub(crate) static ref CACHE_COUNTER: prometheus::CounterVec = prometheus::register_counter_vec!(
+        "bragi_cache_requests_total",
+        "Total number of requests to the response cache, by result (hit or miss).",
+        &["handler", "result"]
+    )
+    .unwrap();
 }
 
 #[derive(Clone)]
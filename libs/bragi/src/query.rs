@@ -28,14 +28,16 @@
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
 use super::model::{self, BragiError};
+use futures::future::{self, Either, Future};
 use geojson::Geometry;
 use mimir;
-use mimir::objects::{Addr, Admin, Coord, MimirObject, Poi, Stop, Street};
-use mimir::rubber::{get_indexes, read_places, Rubber};
+use mimir::backend::Backend;
+use mimir::objects::{
+    Addr, Admin, Coord, MimirObject, Poi, PtLine, PtNetwork, Stop, StopPoint, Street,
+};
+use mimir::rubber::{get_indexes, SearchRequest, PT_DATASET_TYPES};
 use prometheus::{self, exponential_buckets, histogram_opts, register_histogram_vec, HistogramVec};
 use rs_es;
-use rs_es::error::EsError;
-use rs_es::operations::search::Source;
 use rs_es::query::compound::BoostMode;
 use rs_es::query::functions::{DecayOptions, FilteredFunction, Function, Modifier};
 use rs_es::query::Query;
@@ -43,6 +45,7 @@ use rs_es::units as rs_u;
 use serde;
 use serde_json;
 use slog_scope::{debug, error, warn};
+use std::sync::Arc;
 use std::{fmt, iter};
 
 lazy_static::lazy_static! {
@@ -56,7 +59,7 @@ lazy_static::lazy_static! {
 }
 
 /// takes a ES json blob and build a Place from it
-/// it uses the _type field of ES to know which type of the Place enum to fill
+/// it uses the type of the document (cf `mimir::rubber::get_doc_type`) to know which type of the Place enum to fill
 pub fn make_place(doc_type: String, value: Option<Box<serde_json::Value>>) -> Option<mimir::Place> {
     value.and_then(|v| {
         fn convert<T>(v: serde_json::Value, f: fn(T) -> mimir::Place) -> Option<mimir::Place>
@@ -74,15 +77,18 @@ pub fn make_place(doc_type: String, value: Option<Box<serde_json::Value>>) -> Op
             "admin" => convert(*v, mimir::Place::Admin),
             "poi" => convert(*v, mimir::Place::Poi),
             "stop" => convert(*v, mimir::Place::Stop),
+            "stop_point" => convert(*v, mimir::Place::StopPoint),
+            "line" => convert(*v, mimir::Place::PtLine),
+            "network" => convert(*v, mimir::Place::PtNetwork),
             _ => {
-                warn!("unknown ES return value, _type field = {}", doc_type);
+                warn!("unknown ES return value, type field = {}", doc_type);
                 None
             }
         }
     })
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 enum MatchType {
     Prefix,
     Fuzzy,
@@ -142,11 +148,14 @@ fn build_proximity_with_boost(coord: &Coord, weight: f64) -> Query {
         .build()
 }
 
+/// Factor applied to the weight of the interpolated addresses
+const INTERPOLATED_ADDR_WEIGHT: f64 = 0.5;
+
 fn build_with_weight<A: Into<Option<f64>>>(factor: A) -> Query {
     let factor = factor.into();
     let weighted = |doc_type, weight| {
         FilteredFunction::build_filtered_function(
-            Query::build_term("_type", doc_type).build(),
+            Query::build_term("type", doc_type).build(),
             Function::build_field_value_factor("weight")
                 .with_factor(factor.unwrap_or(0.75))
                 .with_missing(0.0)
@@ -158,15 +167,184 @@ fn build_with_weight<A: Into<Option<f64>>>(factor: A) -> Query {
     Query::build_function_score()
         .with_functions(vec![
             weighted(Stop::doc_type(), 1.0),
+            weighted(StopPoint::doc_type(), 1.0),
+            weighted(PtLine::doc_type(), 1.0),
+            weighted(PtNetwork::doc_type(), 1.0),
             weighted(Addr::doc_type(), 0.5),
             weighted(Admin::doc_type(), 0.5),
             weighted(Poi::doc_type(), 0.5),
             weighted(Street::doc_type(), 0.5),
+            // the interpolated addresses are ranked below the surveyed ones
+            FilteredFunction::build_filtered_function(
+                Query::build_term("interpolated", true).build(),
+                Function::build_weight(INTERPOLATED_ADDR_WEIGHT).build(),
+                None,
+            ),
         ])
         .with_boost_mode(BoostMode::Replace)
         .build()
 }
 
+// filter to limit the search to a geographic shape
+// the stop areas, the lines and the networks are not filtered
+fn build_shape_filter(shape: Geometry) -> Query {
+    let not_filtered = || {
+        Query::build_terms("type")
+            .with_values(vec![
+                Stop::doc_type(),
+                PtLine::doc_type(),
+                PtNetwork::doc_type(),
+            ])
+            .build()
+    };
+    let filter_wo_stop = Query::build_bool()
+        .with_must(vec![
+            Query::build_bool().with_must_not(not_filtered()).build(),
+            Query::build_geo_shape("approx_coord")
+                .with_geojson(shape)
+                .build(),
+        ])
+        .build();
+    let filter_w_stop = not_filtered();
+    Query::build_bool()
+        .with_should(vec![filter_w_stop, filter_wo_stop])
+        .build()
+}
+
+/// The fields of a structured query.
+/// Each given field has to be matched by the places.
+#[derive(Debug, Default, Clone)]
+pub struct StructuredQuery<'a> {
+    pub housenumber: Option<&'a str>,
+    pub street: Option<&'a str>,
+    pub postcode: Option<&'a str>,
+    pub city: Option<&'a str>,
+    pub country: Option<&'a str>,
+}
+
+impl<'a> StructuredQuery<'a> {
+    pub fn is_empty(&self) -> bool {
+        self.housenumber.is_none()
+            && self.street.is_none()
+            && self.postcode.is_none()
+            && self.city.is_none()
+            && self.country.is_none()
+    }
+
+    /// the most precise types that can be returned for this query
+    /// (we don't want to return a city if a street is given)
+    pub fn types(&self) -> Vec<&'static str> {
+        if self.housenumber.is_some() {
+            vec!["house"]
+        } else if self.street.is_some() {
+            vec!["street"]
+        } else if self.city.is_some() || self.postcode.is_some() {
+            vec!["city"]
+        } else {
+            vec!["zone"]
+        }
+    }
+}
+
+fn build_structured_query(
+    structured: &StructuredQuery<'_>,
+    coord: Option<Coord>,
+    shape: Option<Geometry>,
+    pt_datasets: &[&str],
+    all_data: bool,
+) -> Query {
+    let match_all_words = |field: &str, value: &str| {
+        Query::build_match(field.to_string(), value.to_string())
+            .with_operator("and")
+            .build()
+    };
+    // some fields are the name of the document for a given type
+    // (eg. the street of a street is its name, not the `street.name` field used for addresses)
+    let match_own_name = |value: &str, doc_type: &str| {
+        Query::build_bool()
+            .with_must(vec![
+                Query::build_term("type", doc_type).build(),
+                match_all_words("name", value),
+            ])
+            .build()
+    };
+
+    let mut field_queries = vec![];
+    if let Some(housenumber) = structured.housenumber {
+        field_queries.push(match_all_words("house_number", housenumber));
+    }
+    if let Some(street) = structured.street {
+        field_queries.push(
+            Query::build_bool()
+                .with_should(vec![
+                    match_all_words("street.name", street),
+                    match_own_name(street, Street::doc_type()),
+                ])
+                .build(),
+        );
+    }
+    if let Some(postcode) = structured.postcode {
+        field_queries.push(match_all_words("zip_codes", postcode));
+    }
+    // the admins of an address are the ones of its street
+    let admins_fields = ["administrative_regions", "street.administrative_regions"];
+    if let Some(city) = structured.city {
+        let mut city_queries: Vec<_> = admins_fields
+            .iter()
+            .map(|admins| match_all_words(&format!("{}.name", admins), city))
+            .collect();
+        city_queries.push(match_own_name(city, Admin::doc_type()));
+        field_queries.push(Query::build_bool().with_should(city_queries).build());
+    }
+    if let Some(country) = structured.country {
+        // the country is given by its ISO code, or by the name or the label of its admin
+        let mut country_queries = vec![
+            Query::build_term("country_codes", country.to_lowercase()).build(),
+            Query::build_bool()
+                .with_must(vec![
+                    Query::build_term("zone_type", "country").build(),
+                    match_own_name(country, Admin::doc_type()),
+                ])
+                .build(),
+        ];
+        country_queries.extend(admins_fields.iter().map(|admins| {
+            Query::build_bool()
+                .with_must(vec![
+                    Query::build_term(format!("{}.zone_type", admins), "country").build(),
+                    Query::build_bool()
+                        .with_should(vec![
+                            match_all_words(&format!("{}.name", admins), country),
+                            match_all_words(&format!("{}.label", admins), country),
+                        ])
+                        .build(),
+                ])
+                .build()
+        }));
+        field_queries.push(Query::build_bool().with_should(country_queries).build());
+    }
+
+    // Priorization by importance
+    let mut importance_queries = vec![build_with_weight(None)];
+    if let Some(ref coord) = coord {
+        importance_queries.push(build_proximity_with_boost(coord, 0.4));
+    }
+
+    let mut filters = vec![];
+    // if searching through all data, no coverage filter
+    if !all_data {
+        filters.push(build_coverage_condition(pt_datasets));
+    }
+    if let Some(s) = shape {
+        filters.push(build_shape_filter(s));
+    }
+
+    Query::build_bool()
+        .with_must(field_queries)
+        .with_should(importance_queries)
+        .with_filter(Query::build_bool().with_must(filters).build())
+        .build()
+}
+
 fn build_query<'a>(
     q: &str,
     match_type: MatchType,
@@ -180,7 +358,7 @@ fn build_query<'a>(
 ) -> Query {
     // Priorization by type
     fn match_type_with_boost<T: MimirObject>(boost: f64) -> Query {
-        Query::build_term("_type", T::doc_type())
+        Query::build_term("type", T::doc_type())
             .with_boost(boost)
             .build()
     }
@@ -189,6 +367,9 @@ fn build_query<'a>(
             match_type_with_boost::<Addr>(30.),
             match_type_with_boost::<Admin>(19.),
             match_type_with_boost::<Stop>(18.),
+            match_type_with_boost::<StopPoint>(17.),
+            match_type_with_boost::<PtLine>(17.),
+            match_type_with_boost::<PtNetwork>(17.),
             match_type_with_boost::<Poi>(1.5),
             match_type_with_boost::<Street>(1.),
         ])
@@ -264,7 +445,7 @@ fn build_query<'a>(
     match match_type {
         MatchType::Prefix => {
             let admin_importance_query = Query::build_function_score()
-                .with_query(Query::build_term("_type", Admin::doc_type()).build())
+                .with_query(Query::build_term("type", Admin::doc_type()).build())
                 .with_functions(vec![
                     FilteredFunction::build_filtered_function(
                         None,
@@ -341,21 +522,7 @@ fn build_query<'a>(
     // We want to limit the search to the geographic shape given in argument,
     // except for stop areas
     if let Some(s) = shape {
-        let filter_wo_stop = Query::build_bool()
-            .with_must(vec![
-                Query::build_bool()
-                    .with_must_not(Query::build_term("_type", Stop::doc_type()).build())
-                    .build(),
-                Query::build_geo_shape("approx_coord")
-                    .with_geojson(s)
-                    .build(),
-            ])
-            .build();
-        let filter_w_stop = Query::build_term("_type", Stop::doc_type()).build();
-        let geo_filter = Query::build_bool()
-            .with_should(vec![filter_w_stop, filter_wo_stop])
-            .build();
-        filters.push(geo_filter);
+        filters.push(build_shape_filter(s));
     }
 
     let mut query = Query::build_bool()
@@ -391,12 +558,14 @@ fn build_query<'a>(
     query.build()
 }
 
-fn query(
+/// future of the places found by a search
+pub type PlacesFuture = Box<dyn Future<Item = Vec<mimir::Place>, Error = BragiError>>;
+
+fn build_autocomplete_search(
     q: &str,
     pt_datasets: &[&str],
     poi_datasets: &[&str],
     all_data: bool,
-    rubber: &mut Rubber,
     match_type: MatchType,
     offset: u64,
     limit: u64,
@@ -407,8 +576,7 @@ fn query(
     poi_types: &[&str],
     langs: &[&str],
     debug: bool,
-) -> Result<Vec<mimir::Place>, EsError> {
-    let query_type = match_type.to_string();
+) -> SearchRequest {
     let query = build_query(
         q,
         match_type,
@@ -421,53 +589,38 @@ fn query(
         poi_types,
     );
 
-    let indexes = get_indexes(all_data, &pt_datasets, &poi_datasets, types);
-    let indexes = indexes
-        .iter()
-        .map(|index| index.as_str())
-        .collect::<Vec<&str>>();
-    debug!("ES indexes: {:?}", indexes);
+    SearchRequest {
+        indexes: get_indexes(all_data, &pt_datasets, &poi_datasets, types),
+        query,
+        from: offset,
+        size: limit,
+        coord,
+        explain: debug,
+        with_geometry: false,
+    }
+}
 
-    if indexes.is_empty() {
-        // if there is no indexes, rs_es search with index "_all"
+/// send the search to the backend
+fn search(search: SearchRequest, query_type: &str, backend: &dyn Backend) -> PlacesFuture {
+    debug!("ES indexes: {:?}", search.indexes);
+
+    if search.indexes.is_empty() {
+        // if there is no indexes, ES would search on all the indexes
         // but we want to return empty response in this case.
-        return Ok(vec![]);
+        return Box::new(future::ok(vec![]));
     }
     let timer = ES_REQ_HISTOGRAM
-        .get_metric_with_label_values(&[query_type.as_str()])
+        .get_metric_with_label_values(&[query_type])
         .map(|h| h.start_timer())
         .map_err(
             |err| error!("impossible to get ES_REQ_HISTOGRAM metrics"; "err" => err.to_string()),
         )
         .ok();
 
-    let timeout = rubber.timeout.map(|t| format!("{:?}", t));
-    let mut search_query = rubber.es_client.search_query();
-
-    let search_query = search_query
-        .with_ignore_unavailable(true)
-        .with_indexes(&indexes)
-        .with_query(&query)
-        .with_from(offset)
-        .with_size(limit)
-        // No need to fetch "boundary" as it's not used in the geocoding response
-        // and is very large in some documents (countries...)
-        .with_source(Source::exclude(&["boundary"]));
-
-    // We don't want to clutter the Query URL, so we only add an explanation if the option is used
-    let search_query = match debug {
-        true => search_query.with_explain(true),
-        false => search_query,
-    };
-
-    if let Some(timeout) = &timeout {
-        search_query.with_timeout(timeout.as_str());
-    }
-    let result = search_query.send()?;
-
-    timer.map(|t| t.observe_duration());
-
-    read_places(result, coord.as_ref())
+    Box::new(backend.search_places(search).then(move |res| {
+        timer.map(|t| t.observe_duration());
+        res.map_err(model::BragiError::from)
+    }))
 }
 
 pub fn features(
@@ -475,32 +628,20 @@ pub fn features(
     poi_datasets: &[&str],
     all_data: bool,
     id: &str,
-    mut rubber: Rubber,
-) -> Result<Vec<mimir::Place>, BragiError> {
-    let val = rs_es::units::JsonVal::String(id.into());
-    let mut filters = vec![Query::build_ids(vec![val]).build()];
-
-    // if searching through all data, no coverage filter
+    full_geometry: bool,
+    backend: Arc<dyn Backend>,
+) -> PlacesFuture {
+    let mut indexes = get_indexes(all_data, &pt_datasets, &poi_datasets, &[]);
+    // the stop points, lines and networks are not searched by default,
+    // but they can be fetched by id
     if !all_data {
-        filters.push(build_coverage_condition(pt_datasets));
+        indexes.extend(get_indexes(false, &pt_datasets, &[], &PT_DATASET_TYPES));
     }
-    let filter = Query::build_bool().with_must(filters).build();
-    let query = Query::build_bool().with_filter(filter).build();
-
-    let indexes = get_indexes(all_data, &pt_datasets, &poi_datasets, &[]);
-    let indexes = indexes
-        .iter()
-        .map(|index| index.as_str())
-        .collect::<Vec<&str>>();
-
-    debug!("ES indexes: {:?}", indexes);
-
     if indexes.is_empty() {
-        // if there is no indexes, rs_es search with index "_all"
+        // if there is no indexes, ES would search on all the indexes
         // but we want to return an error in this case.
-        return Err(BragiError::ObjectNotFound);
+        return Box::new(future::err(BragiError::ObjectNotFound));
     }
-
     let timer = ES_REQ_HISTOGRAM
         .get_metric_with_label_values(&["features"])
         .map(|h| h.start_timer())
@@ -509,31 +650,264 @@ pub fn features(
         )
         .ok();
 
-    let timeout = rubber.timeout.map(|t| format!("{:?}", t));
-    let mut search_query = rubber.es_client.search_query();
-
-    let search_query = search_query
-        .with_ignore_unavailable(true)
-        .with_indexes(&indexes)
-        .with_query(&query);
+    let pt_datasets: Vec<String> = pt_datasets.iter().map(|d| d.to_string()).collect();
+    let features = backend
+        .get_places_by_id(indexes, id, FEATURES_MAX_RESULTS, full_geometry)
+        .then(move |res| {
+            timer.map(|t| t.observe_duration());
+            res.map_err(model::BragiError::from)
+        });
+    Box::new(features.and_then(move |places| {
+        let places: Vec<_> = places
+            .into_iter()
+            // if searching through all data, no coverage filter
+            .filter(|place| all_data || is_in_coverages(place, &pt_datasets))
+            .collect();
+        if places.is_empty() {
+            Err(BragiError::ObjectNotFound)
+        } else {
+            Ok(places)
+        }
+    }))
+}
 
-    if let Some(timeout) = &timeout {
-        search_query.with_timeout(timeout.as_str());
+/// same condition as `build_coverage_condition`, for an already fetched place
+fn is_in_coverages(place: &mimir::Place, pt_datasets: &[String]) -> bool {
+    match place {
+        mimir::Place::Stop(stop) => {
+            stop.coverages.is_empty() || stop.coverages.iter().any(|c| pt_datasets.contains(c))
+        }
+        mimir::Place::StopPoint(stop_point) => {
+            stop_point.coverages.is_empty()
+                || stop_point.coverages.iter().any(|c| pt_datasets.contains(c))
+        }
+        mimir::Place::PtLine(line) => {
+            line.coverages.is_empty() || line.coverages.iter().any(|c| pt_datasets.contains(c))
+        }
+        mimir::Place::PtNetwork(network) => {
+            network.coverages.is_empty()
+                || network.coverages.iter().any(|c| pt_datasets.contains(c))
+        }
+        _ => true,
     }
+}
 
-    let result = search_query.send()?;
+/// The maximum number of places returned by `features`
+/// (the same id can be found in several datasets)
+const FEATURES_MAX_RESULTS: u64 = 10;
+
+/// The maximum number of admins containing a point that can be fetched
+/// (there are only a few admin levels, so they should all be fetched)
+const MAX_CONTAINING_ADMINS: u64 = 50;
+
+/// The minimum number of places fetched by a reverse geocoding before keeping the nearest ones
+/// (the score of a street depends on the distance to its coord, not to its geometry,
+/// so the nearest street may not have the best score)
+const MIN_REVERSE_CANDIDATES: u64 = 10;
+
+/// The half side (in meters) of the square in which the streets are also searched by their
+/// geometry for a reverse geocoding: the coord of a long street can be far, so the street
+/// can be out of the candidates ranked by the distance to their coord
+const REVERSE_STREETS_RADIUS: f64 = 100.;
+
+/// Result of a reverse geocoding
+pub enum Reverse {
+    /// the places found near the coord
+    Places(Vec<mimir::Place>),
+    /// no address was found near the coord,
+    /// we give the smallest admin containing it instead
+    AdminFallback(Vec<mimir::Place>),
+}
+
+/// Search the smallest admin (the one with the highest level) whose boundary contains `coord`
+fn smallest_admin_containing(
+    coord: &Coord,
+    pt_datasets: &[&str],
+    all_data: bool,
+    backend: &dyn Backend,
+) -> impl Future<Item = Option<mimir::Place>, Error = BragiError> {
+    let point = Geometry::new(geojson::Value::Point(vec![coord.lon(), coord.lat()]));
+    let query = Query::build_bool()
+        .with_filter(
+            Query::build_geo_shape("boundary")
+                .with_geojson(point)
+                .build(),
+        )
+        .build();
+    let admins = search(
+        SearchRequest {
+            indexes: get_indexes(all_data, pt_datasets, &[], &["zone"]),
+            query,
+            from: 0,
+            size: MAX_CONTAINING_ADMINS,
+            coord: Some(*coord),
+            explain: false,
+            with_geometry: false,
+        },
+        "reverse_admin",
+        backend,
+    );
+    admins.map(|admins| {
+        admins
+            .into_iter()
+            .filter_map(|place| match place {
+                mimir::Place::Admin(admin) => Some(admin),
+                _ => None,
+            })
+            .max_by_key(|admin| admin.level)
+            .map(mimir::Place::Admin)
+    })
+}
 
-    timer.map(|t| t.observe_duration());
+/// The square of half side `radius` (in meters) centered on `coord`
+fn square_around(coord: &Coord, radius: f64) -> Geometry {
+    const METERS_BY_DEGREE: f64 = 111_320.;
+    let delta_lat = radius / METERS_BY_DEGREE;
+    let delta_lon = delta_lat / coord.lat().to_radians().cos().max(0.01);
+    let (min_lon, max_lon) = (coord.lon() - delta_lon, coord.lon() + delta_lon);
+    let (min_lat, max_lat) = (coord.lat() - delta_lat, coord.lat() + delta_lat);
+    Geometry::new(geojson::Value::Polygon(vec![vec![
+        vec![min_lon, min_lat],
+        vec![max_lon, min_lat],
+        vec![max_lon, max_lat],
+        vec![min_lon, max_lat],
+        vec![min_lon, min_lat],
+    ]]))
+}
 
-    if result.hits.total == 0 {
-        Err(BragiError::ObjectNotFound)
+/// Search the places near `coord`, the nearest first
+///
+/// If no `types` are given, only the addresses and the streets are searched.
+/// If addresses or streets are searched and none is found,
+/// the smallest admin containing `coord` is returned
+pub fn reverse(
+    coord: &Coord,
+    pt_datasets: &[&str],
+    poi_datasets: &[&str],
+    all_data: bool,
+    radius: f64,
+    limit: u64,
+    types: &[&str],
+    poi_types: &[&str],
+    backend: Arc<dyn Backend>,
+) -> Box<dyn Future<Item = Reverse, Error = BragiError>> {
+    if let Err(e) = check_autocomplete_params(types, &[], poi_types) {
+        return Box::new(future::err(e));
+    }
+    let default_types = ["house", "street"];
+    let types = if types.is_empty() {
+        &default_types[..]
     } else {
-        read_places(result, None).map_err(model::BragiError::from)
+        types
+    };
+
+    let distance = rs_u::Distance::new(radius, rs_u::DistanceUnit::Meter);
+    // the streets whose coord is far from `coord` are found by their geometry below
+    let mut filters =
+        vec![Query::build_geo_distance("coord", (coord.lat(), coord.lon()), distance).build()];
+
+    // if searching through all data, no coverage filter
+    if !all_data {
+        filters.push(build_coverage_condition(pt_datasets));
     }
+    if !poi_types.is_empty() {
+        filters.push(
+            Query::build_bool()
+                .with_should(
+                    poi_types
+                        .iter()
+                        .map(|x| Query::build_match("poi_type.id", *x).build())
+                        .collect::<Vec<_>>(),
+                )
+                .build(),
+        );
+    }
+
+    // the score only depends on the distance, so the nearest places come first
+    let query = Query::build_bool()
+        .with_should(mimir::rubber::build_proximity_with_boost(coord, 1.))
+        .with_filter(Query::build_bool().with_must(filters).build())
+        .build();
+
+    let places = search(
+        SearchRequest {
+            indexes: get_indexes(all_data, &pt_datasets, &poi_datasets, types),
+            query,
+            from: 0,
+            size: limit.max(MIN_REVERSE_CANDIDATES),
+            coord: Some(*coord),
+            explain: false,
+            with_geometry: true,
+        },
+        "reverse",
+        &*backend,
+    );
+    let streets: PlacesFuture = if types.contains(&"street") {
+        let square = square_around(coord, radius.min(REVERSE_STREETS_RADIUS));
+        let query = Query::build_bool()
+            .with_filter(
+                Query::build_geo_shape("geometry")
+                    .with_geojson(square)
+                    .build(),
+            )
+            .build();
+        search(
+            SearchRequest {
+                indexes: get_indexes(all_data, &[], &[], &["street"]),
+                query,
+                from: 0,
+                size: limit.max(MIN_REVERSE_CANDIDATES),
+                coord: Some(*coord),
+                explain: false,
+                with_geometry: true,
+            },
+            "reverse_street",
+            &*backend,
+        )
+    } else {
+        Box::new(future::ok(vec![]))
+    };
+
+    let addresses_searched = types.iter().any(|t| *t == "house" || *t == "street");
+    let coord = *coord;
+    let pt_datasets: Vec<String> = pt_datasets.iter().map(|d| d.to_string()).collect();
+    Box::new(places.join(streets).and_then(move |(mut places, streets)| {
+        for street in streets {
+            if !places.iter().any(|place| place.id() == street.id()) {
+                places.push(street);
+            }
+        }
+        // the distance of a street is the distance to its geometry,
+        // and a surveyed address comes before an interpolated one at the same distance
+        places.sort_by_key(|place| {
+            let interpolated = match place {
+                mimir::Place::Addr(addr) => addr.interpolated,
+                _ => false,
+            };
+            (place.distance(), interpolated)
+        });
+        places.retain(|place| place.distance().map_or(true, |d| f64::from(d) <= radius));
+        places.truncate(limit as usize);
+        if places.is_empty() && addresses_searched {
+            let pt_datasets: Vec<&str> = pt_datasets.iter().map(String::as_str).collect();
+            let admin = smallest_admin_containing(&coord, &pt_datasets, all_data, &*backend);
+            Either::A(admin.map(|admin| match admin {
+                Some(admin) => Reverse::AdminFallback(vec![admin]),
+                // no admin contains the point either
+                None => Reverse::Places(vec![]),
+            }))
+        } else {
+            Either::B(future::ok(Reverse::Places(places)))
+        }
+    }))
 }
 
-pub fn autocomplete(
-    q: &str,
+/// Search with a structured query (each field of the address is given separately)
+///
+/// If no `types` are given, we search only the most precise type given by the query
+/// (eg. only the addresses if a house number is given)
+pub fn structured_search(
+    structured: &StructuredQuery<'_>,
     pt_datasets: &[&str],
     poi_datasets: &[&str],
     all_data: bool,
@@ -541,14 +915,103 @@ pub fn autocomplete(
     limit: u64,
     coord: Option<Coord>,
     shape: Option<Geometry>,
+    types: &[&str],
+    backend: Arc<dyn Backend>,
+    debug: bool,
+) -> PlacesFuture {
+    let query = build_structured_query(structured, coord, shape, pt_datasets, all_data);
+    let structured_types = structured.types();
+    let types = if types.is_empty() {
+        &structured_types[..]
+    } else {
+        types
+    };
+    search(
+        SearchRequest {
+            indexes: get_indexes(all_data, &pt_datasets, &poi_datasets, types),
+            query,
+            from: offset,
+            size: limit,
+            coord,
+            explain: debug,
+            with_geometry: false,
+        },
+        "structured",
+        &*backend,
+    )
+}
+
+/// All the parameters of an autocomplete query
+/// (used to send several autocomplete in one batch, cf `batch_autocomplete`)
+#[derive(Debug)]
+pub struct AutocompleteQuery<'a> {
+    pub q: &'a str,
+    /// if not empty, the query is a structured query and `q` is not used
+    pub structured: StructuredQuery<'a>,
+    pub pt_datasets: Vec<&'a str>,
+    pub poi_datasets: Vec<&'a str>,
+    pub all_data: bool,
+    pub offset: u64,
+    pub limit: u64,
+    pub coord: Option<Coord>,
+    pub shape: Option<Geometry>,
+    pub types: Vec<&'a str>,
+    pub zone_types: Vec<&'a str>,
+    pub poi_types: Vec<&'a str>,
+    pub langs: Vec<&'a str>,
+    pub debug: bool,
+}
+
+impl<'a> AutocompleteQuery<'a> {
+    /// the search to send to elasticsearch for this query
+    fn build_search(&self, match_type: MatchType) -> SearchRequest {
+        if self.structured.is_empty() {
+            return build_autocomplete_search(
+                self.q,
+                &self.pt_datasets,
+                &self.poi_datasets,
+                self.all_data,
+                match_type,
+                self.offset,
+                self.limit,
+                self.coord,
+                self.shape.clone(),
+                &self.types,
+                &self.zone_types,
+                &self.poi_types,
+                &self.langs,
+                self.debug,
+            );
+        }
+        let query = build_structured_query(
+            &self.structured,
+            self.coord,
+            self.shape.clone(),
+            &self.pt_datasets,
+            self.all_data,
+        );
+        let types = if self.types.is_empty() {
+            self.structured.types()
+        } else {
+            self.types.clone()
+        };
+        SearchRequest {
+            indexes: get_indexes(self.all_data, &self.pt_datasets, &self.poi_datasets, &types),
+            query,
+            from: self.offset,
+            size: self.limit,
+            coord: self.coord,
+            explain: self.debug,
+            with_geometry: false,
+        }
+    }
+}
+
+fn check_autocomplete_params(
     types: &[&str],
     zone_types: &[&str],
     poi_types: &[&str],
-    langs: &[&str],
-    mut rubber: Rubber,
-    debug: bool,
-) -> Result<Vec<mimir::Place>, BragiError> {
-    // Perform parameters validation.
+) -> Result<(), BragiError> {
     if !zone_types.is_empty() && !types.iter().any(|s| *s == "zone") {
         return Err(BragiError::InvalidParam(
             "zone_type[] parameter requires to have 'type[]=zone'",
@@ -559,47 +1022,181 @@ pub fn autocomplete(
             "poi_type[] parameter requires to have 'type[]=poi'",
         ));
     }
+    Ok(())
+}
+
+/// run all the searches at once (in only one elasticsearch _msearch)
+/// the results are in the same order as the searches
+fn multi_search(
+    searches: Vec<SearchRequest>,
+    query_type: &str,
+    backend: &dyn Backend,
+) -> impl Future<Item = Vec<Result<Vec<mimir::Place>, BragiError>>, Error = BragiError> {
+    // the searches without any index are not sent to ES (they would be run on all the indexes)
+    // their results are empty
+    let is_sent: Vec<bool> = searches.iter().map(|s| !s.indexes.is_empty()).collect();
+    let sent_searches: Vec<SearchRequest> = searches
+        .into_iter()
+        .filter(|s| !s.indexes.is_empty())
+        .collect();
+
+    let timer = ES_REQ_HISTOGRAM
+        .get_metric_with_label_values(&[query_type])
+        .map(|h| h.start_timer())
+        .map_err(
+            |err| error!("impossible to get ES_REQ_HISTOGRAM metrics"; "err" => err.to_string()),
+        )
+        .ok();
+
+    backend
+        .multi_search(sent_searches)
+        .then(move |res| {
+            timer.map(|t| t.observe_duration());
+            res
+        })
+        .map_err(model::BragiError::from)
+        .map(move |es_results| {
+            let mut es_results = es_results.into_iter();
+            is_sent
+                .into_iter()
+                .map(|sent| {
+                    if sent {
+                        es_results
+                            .next()
+                            .unwrap_or_else(|| Ok(vec![]))
+                            .map_err(model::BragiError::from)
+                    } else {
+                        Ok(vec![])
+                    }
+                })
+                .collect()
+        })
+}
+
+/// Autocomplete for a batch of queries
+///
+/// Like `autocomplete`, we first try a prefix match on all the queries (in one _msearch),
+/// and we do a fuzzy search (in another _msearch) for the queries that have no results.
+/// The structured queries are only sent once (there is no fuzzy structured search).
+///
+/// The outer result is an error only if the whole batch failed,
+/// the errors of a given query (invalid parameters, ES error) are reported in its own result.
+pub fn batch_autocomplete(
+    queries: Vec<Result<AutocompleteQuery<'_>, BragiError>>,
+    backend: Arc<dyn Backend>,
+) -> impl Future<Item = Vec<Result<Vec<mimir::Place>, BragiError>>, Error = BragiError> {
+    let mut results = Vec::with_capacity(queries.len());
+    let mut positions = vec![];
+    let mut prefix_searches = vec![];
+    // the fuzzy searches are built now (the queries are borrowed)
+    // but they are only sent for the queries without results for the prefix search
+    let mut fuzzy_searches = vec![];
+    for (pos, query) in queries.into_iter().enumerate() {
+        let query = query.and_then(|q| {
+            check_autocomplete_params(&q.types, &q.zone_types, &q.poi_types)?;
+            Ok(q)
+        });
+        match query {
+            Ok(q) => {
+                results.push(Ok(vec![]));
+                positions.push(pos);
+                prefix_searches.push(q.build_search(MatchType::Prefix));
+                fuzzy_searches.push(if q.structured.is_empty() {
+                    Some(q.build_search(MatchType::Fuzzy))
+                } else {
+                    None
+                });
+            }
+            Err(e) => results.push(Err(e)),
+        }
+    }
+
+    multi_search(prefix_searches, "batch_prefix", &*backend).and_then(move |prefix_results| {
+        let mut fuzzy_positions = vec![];
+        let mut sent_fuzzy_searches = vec![];
+        for ((pos, res), fuzzy_search) in positions
+            .into_iter()
+            .zip(prefix_results)
+            .zip(fuzzy_searches)
+        {
+            let no_result = match res {
+                Ok(ref places) => places.is_empty(),
+                Err(_) => false,
+            };
+            match fuzzy_search {
+                Some(fuzzy_search) if no_result => {
+                    fuzzy_positions.push(pos);
+                    sent_fuzzy_searches.push(fuzzy_search);
+                }
+                _ => results[pos] = res,
+            }
+        }
+
+        multi_search(sent_fuzzy_searches, "batch_fuzzy", &*backend).map(move |fuzzy_results| {
+            for (pos, res) in fuzzy_positions.into_iter().zip(fuzzy_results) {
+                results[pos] = res;
+            }
+            results
+        })
+    })
+}
+
+pub fn autocomplete(
+    q: &str,
+    pt_datasets: &[&str],
+    poi_datasets: &[&str],
+    all_data: bool,
+    offset: u64,
+    limit: u64,
+    coord: Option<Coord>,
+    shape: Option<Geometry>,
+    types: &[&str],
+    zone_types: &[&str],
+    poi_types: &[&str],
+    langs: &[&str],
+    backend: Arc<dyn Backend>,
+    debug: bool,
+) -> PlacesFuture {
+    // Perform parameters validation.
+    if let Err(e) = check_autocomplete_params(types, zone_types, poi_types) {
+        return Box::new(future::err(e));
+    }
 
     // First we try a pretty exact match on the prefix.
     // If there are no results then we do a new fuzzy search (matching ngrams)
-    let results = query(
-        &q,
-        &pt_datasets,
-        &poi_datasets,
-        all_data,
-        &mut rubber,
-        MatchType::Prefix,
-        offset,
-        limit,
-        coord,
-        shape.clone(),
-        &types,
-        &zone_types,
-        &poi_types,
-        &langs,
-        debug,
-    )
-    .map_err(model::BragiError::from)?;
-    if results.is_empty() {
-        query(
+    let build_search = |match_type: MatchType| {
+        build_autocomplete_search(
             &q,
             &pt_datasets,
             &poi_datasets,
             all_data,
-            &mut rubber,
-            MatchType::Fuzzy,
+            match_type,
             offset,
             limit,
             coord,
-            shape,
+            shape.clone(),
             &types,
             &zone_types,
             &poi_types,
             &langs,
             debug,
         )
-        .map_err(model::BragiError::from)
-    } else {
-        Ok(results)
-    }
+    };
+    let prefix_search = build_search(MatchType::Prefix);
+    // the fuzzy search is built now since the parameters are borrowed,
+    // but it is only sent if needed
+    let fuzzy_search = build_search(MatchType::Fuzzy);
+
+    let results = search(prefix_search, &MatchType::Prefix.to_string(), &*backend);
+    Box::new(results.and_then(move |results| {
+        if results.is_empty() {
+            Either::A(search(
+                fuzzy_search,
+                &MatchType::Fuzzy.to_string(),
+                &*backend,
+            ))
+        } else {
+            Either::B(future::ok(results))
+        }
+    }))
 }
@@ -1,64 +1,29 @@
+use crate::cache;
 use crate::extractors::BragiQuery;
-use crate::model::{Autocomplete, BragiError, FromWithLang};
-use crate::routes::params;
+use crate::model::{Autocomplete, BatchAutocompleteItem, BragiError, FromWithLang};
+use crate::routes::params::{self, PoiType, Type};
 use crate::{model, query, Context};
 use actix_http::http::header::{CacheControl, CacheDirective};
 use actix_web::web::{Data, HttpResponse, Json};
+use futures::future::{self, Future};
 use geojson::{GeoJson, Geometry};
 use mimir::objects::Coord;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
-enum Type {
-    #[serde(rename = "city")]
-    City,
-    #[serde(rename = "house")]
-    House,
-    #[serde(rename = "poi")]
-    Poi,
-    #[serde(rename = "public_transport:stop_area")]
-    StopArea,
-    #[serde(rename = "street")]
-    Street,
-    #[serde(rename = "zone")]
-    Zone,
-}
-
-impl Type {
-    fn as_str(&self) -> &'static str {
-        match *self {
-            Type::City => "city",
-            Type::House => "house",
-            Type::Poi => "poi",
-            Type::StopArea => "public_transport:stop_area",
-            Type::Street => "street",
-            Type::Zone => "zone",
-        }
-    }
-}
-
-#[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(untagged)]
-enum PoiType {
-    Whatever(String),
-}
-
-impl PoiType {
-    fn as_str(&self) -> &str {
-        match *self {
-            PoiType::Whatever(ref s) => s,
-        }
-    }
-}
-
 fn default_limit() -> u64 {
     10u64
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Params {
-    q: String,
+    q: Option<String>,
+    // Structured query: the address is given field by field instead of in `q`.
+    housenumber: Option<String>,
+    street: Option<String>,
+    postcode: Option<String>,
+    city: Option<String>,
+    country: Option<String>,
     #[serde(default)]
     pt_dataset: Vec<String>,
     #[serde(default)]
@@ -107,6 +72,30 @@ impl Params {
     fn timeout(&self) -> Option<Duration> {
         self.timeout.map(Duration::from_millis)
     }
+    fn structured(&self) -> query::StructuredQuery<'_> {
+        query::StructuredQuery {
+            housenumber: self.housenumber.as_ref().map(String::as_str),
+            street: self.street.as_ref().map(String::as_str),
+            postcode: self.postcode.as_ref().map(String::as_str),
+            city: self.city.as_ref().map(String::as_str),
+            country: self.country.as_ref().map(String::as_str),
+        }
+    }
+    /// the free text query, empty for a structured query
+    fn q(&self) -> Result<&str, BragiError> {
+        match (&self.q, self.structured().is_empty()) {
+            (Some(q), true) => Ok(q),
+            (None, false) => Ok(""),
+            (Some(_), false) => Err(BragiError::InvalidParam(
+                "'q' cannot be used with the structured parameters \
+                 ('housenumber', 'street', 'postcode', 'city', 'country')",
+            )),
+            (None, true) => Err(BragiError::InvalidParam(
+                "you should provide a 'q' parameter or at least one of the structured parameters \
+                 ('housenumber', 'street', 'postcode', 'city', 'country')",
+            )),
+        }
+    }
     fn build_coord(lon: Option<f64>, lat: Option<f64>) -> Result<Option<Coord>, BragiError> {
         match (lon, lat) {
             (Some(lon), Some(lat)) => Ok(Some(params::make_coord(lon, lat)?)),
@@ -134,51 +123,125 @@ impl JsonParams {
     }
 }
 
-pub fn call_autocomplete(
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchParams {
+    /// timeout in milliseconds, for the whole batch
+    /// (the `timeout` of each query of the batch is not used)
+    timeout: Option<u64>,
+}
+
+impl Params {
+    fn to_query(&self) -> Result<query::AutocompleteQuery<'_>, BragiError> {
+        Ok(query::AutocompleteQuery {
+            q: self.q()?,
+            structured: self.structured(),
+            pt_datasets: self.pt_dataset.iter().map(String::as_str).collect(),
+            poi_datasets: self.poi_dataset.iter().map(String::as_str).collect(),
+            all_data: self.all_data,
+            offset: self.offset,
+            limit: self.limit,
+            coord: self.coord()?,
+            shape: None,
+            types: self.types_as_str(),
+            zone_types: self.zone_types_as_str(),
+            poi_types: self.poi_types_as_str(),
+            langs: self.langs(),
+            debug: self.debug.unwrap_or(false),
+        })
+    }
+}
+
+fn compute_autocomplete(
     params: &Params,
     state: &Context,
     shape: Option<Geometry>,
-) -> Result<HttpResponse, model::BragiError> {
+) -> Result<impl Future<Item = Autocomplete, Error = model::BragiError>, model::BragiError> {
     let langs = params.langs();
-    let rubber = state.get_rubber_for_autocomplete(params.timeout());
-    let res = query::autocomplete(
-        &params.q,
-        &params
-            .pt_dataset
-            .iter()
-            .map(String::as_str)
-            .collect::<Vec<_>>(),
-        &params
-            .poi_dataset
-            .iter()
-            .map(String::as_str)
-            .collect::<Vec<_>>(),
-        params.all_data,
-        params.offset,
-        params.limit,
-        params.coord()?,
-        shape,
-        &params.types_as_str(),
-        &params.zone_types_as_str(),
-        &params.poi_types_as_str(),
-        &langs,
-        rubber,
-        params.debug.unwrap_or(false),
-    );
-    res.map(|r| Autocomplete::from_with_lang(r, langs.into_iter().next()))
-        .map(|v| {
-            HttpResponse::Ok()
-                .set(CacheControl(vec![CacheDirective::MaxAge(
-                    state.http_cache_duration,
-                )]))
-                .json(v)
-        })
+    let lang = params.lang.clone();
+    let q = params.q()?;
+    let backend = state.get_backend_for_autocomplete(params.timeout());
+    let structured = params.structured();
+    let res = if !structured.is_empty() {
+        query::structured_search(
+            &structured,
+            &params
+                .pt_dataset
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            &params
+                .poi_dataset
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            params.all_data,
+            params.offset,
+            params.limit,
+            params.coord()?,
+            shape,
+            &params.types_as_str(),
+            backend,
+            params.debug.unwrap_or(false),
+        )
+    } else {
+        query::autocomplete(
+            q,
+            &params
+                .pt_dataset
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            &params
+                .poi_dataset
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            params.all_data,
+            params.offset,
+            params.limit,
+            params.coord()?,
+            shape,
+            &params.types_as_str(),
+            &params.zone_types_as_str(),
+            &params.poi_types_as_str(),
+            &langs,
+            backend,
+            params.debug.unwrap_or(false),
+        )
+    };
+    Ok(res.map(move |r| Autocomplete::from_with_lang(r, lang.as_ref().map(String::as_str))))
+}
+
+pub fn call_autocomplete(
+    params: &Params,
+    state: &Context,
+    shape: Option<Geometry>,
+) -> impl Future<Item = HttpResponse, Error = model::BragiError> {
+    // the queries with a shape are not cached, the shape is too big to be part of the key
+    let no_cache = None;
+    let cache = if shape.is_none() {
+        &state.cache
+    } else {
+        &no_cache
+    };
+    let http_cache_duration = state.http_cache_duration;
+    cache::cached(cache, "autocomplete", params, || {
+        future::result(compute_autocomplete(params, state, shape)).flatten()
+    })
+    .map(move |body| {
+        HttpResponse::Ok()
+            .set(CacheControl(vec![CacheDirective::MaxAge(
+                http_cache_duration,
+            )]))
+            .content_type("application/json")
+            .body(body)
+    })
 }
 
 pub fn autocomplete(
     params: BragiQuery<Params>,
     state: Data<Context>,
-) -> Result<HttpResponse, model::BragiError> {
+) -> impl Future<Item = HttpResponse, Error = model::BragiError> {
     call_autocomplete(&*params, &*state, None)
 }
 
@@ -186,10 +249,38 @@ pub fn post_autocomplete(
     params: BragiQuery<Params>,
     state: Data<Context>,
     json_params: Json<JsonParams>,
-) -> Result<HttpResponse, model::BragiError> {
-    call_autocomplete(
-        &*params,
-        &*state,
-        Some(json_params.into_inner().get_geometry()?),
-    )
+) -> impl Future<Item = HttpResponse, Error = model::BragiError> {
+    future::result(json_params.into_inner().get_geometry())
+        .and_then(move |shape| call_autocomplete(&*params, &*state, Some(shape)))
+}
+
+/// Autocomplete on a batch of queries
+///
+/// The body is a json array of queries (with the same fields as the GET /autocomplete parameters)
+/// and the response is an array with the response for each query (in the same order).
+/// The batch does not fail if one query is invalid, its error is given instead of its response.
+pub fn post_batch_autocomplete(
+    params: BragiQuery<BatchParams>,
+    state: Data<Context>,
+    batch: Json<Vec<Params>>,
+) -> impl Future<Item = HttpResponse, Error = model::BragiError> {
+    let backend = state.get_backend_for_autocomplete(params.timeout.map(Duration::from_millis));
+    let queries = batch.iter().map(Params::to_query).collect();
+    let langs: Vec<Option<String>> = batch.iter().map(|params| params.lang.clone()).collect();
+    let http_cache_duration = state.http_cache_duration;
+    query::batch_autocomplete(queries, backend).map(move |results| {
+        let responses: Vec<BatchAutocompleteItem> = results
+            .into_iter()
+            .zip(langs.iter())
+            .map(|(res, lang)| {
+                let lang = lang.as_ref().map(String::as_str);
+                BatchAutocompleteItem::from(res.map(|r| Autocomplete::from_with_lang(r, lang)))
+            })
+            .collect();
+        HttpResponse::Ok()
+            .set(CacheControl(vec![CacheDirective::MaxAge(
+                http_cache_duration,
+            )]))
+            .json(responses)
+    })
 }
@@ -1,7 +1,9 @@
+use crate::cache;
 use crate::extractors::BragiQuery;
 use crate::{model, model::FromWithLang, query, Context};
 use actix_http::http::header::{CacheControl, CacheDirective};
 use actix_web::web::{Data, HttpResponse, Path};
+use futures::future::Future;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -15,14 +17,36 @@ pub struct Params {
     all_data: bool,
     /// timeout in milliseconds
     timeout: Option<u64>,
+    /// return the whole geometry of the places (the lines of a street) instead of their coord
+    #[serde(default)]
+    full_geometry: bool,
 }
 
 pub fn features(
     params: BragiQuery<Params>,
     state: Data<Context>,
     id: Path<String>,
-) -> Result<HttpResponse, model::BragiError> {
-    let rubber = state.get_rubber_for_features(params.timeout.map(Duration::from_millis));
+) -> impl Future<Item = HttpResponse, Error = model::BragiError> {
+    let http_cache_duration = state.http_cache_duration;
+    cache::cached(&state.cache, "features", &(&*id, &*params), || {
+        compute_features(&*params, &*state, &*id)
+    })
+    .map(move |body| {
+        HttpResponse::Ok()
+            .set(CacheControl(vec![CacheDirective::MaxAge(
+                http_cache_duration,
+            )]))
+            .content_type("application/json")
+            .body(body)
+    })
+}
+
+fn compute_features(
+    params: &Params,
+    state: &Context,
+    id: &str,
+) -> impl Future<Item = model::Autocomplete, Error = model::BragiError> {
+    let backend = state.get_backend_for_features(params.timeout.map(Duration::from_millis));
     let features = query::features(
         &params
             .pt_dataset
@@ -35,16 +59,22 @@ pub fn features(
             .map(String::as_str)
             .collect::<Vec<_>>(),
         params.all_data,
-        &*id,
-        rubber,
+        id,
+        params.full_geometry,
+        backend,
     );
-    features
-        .map(|r| model::Autocomplete::from_with_lang(r, None))
-        .map(|v| {
-            HttpResponse::Ok()
-                .set(CacheControl(vec![CacheDirective::MaxAge(
-                    state.http_cache_duration,
-                )]))
-                .json(v)
-        })
+    let full_geometry = params.full_geometry;
+    features.map(move |places| {
+        if full_geometry {
+            model::Autocomplete::new(
+                "".to_string(),
+                places
+                    .into_iter()
+                    .map(model::Feature::with_full_geometry)
+                    .collect(),
+            )
+        } else {
+            model::Autocomplete::from_with_lang(places, None)
+        }
+    })
 }
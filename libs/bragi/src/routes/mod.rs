@@ -5,7 +5,9 @@ mod params;
 mod reverse;
 mod status;
 
-pub use autocomplete::{autocomplete, post_autocomplete, JsonParams};
+pub use autocomplete::{
+    autocomplete, post_autocomplete, post_batch_autocomplete, JsonParams, Params,
+};
 pub use entry_point::entry_point;
 pub use features::features;
 pub use reverse::reverse;
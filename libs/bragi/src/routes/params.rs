@@ -1,5 +1,58 @@
 use crate::model::BragiError;
 use mimir::objects::Coord;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+pub enum Type {
+    #[serde(rename = "city")]
+    City,
+    #[serde(rename = "house")]
+    House,
+    #[serde(rename = "poi")]
+    Poi,
+    #[serde(rename = "public_transport:stop_area")]
+    StopArea,
+    #[serde(rename = "public_transport:stop_point")]
+    StopPoint,
+    #[serde(rename = "public_transport:line")]
+    Line,
+    #[serde(rename = "public_transport:network")]
+    Network,
+    #[serde(rename = "street")]
+    Street,
+    #[serde(rename = "zone")]
+    Zone,
+}
+
+impl Type {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Type::City => "city",
+            Type::House => "house",
+            Type::Poi => "poi",
+            Type::StopArea => "public_transport:stop_area",
+            Type::StopPoint => "public_transport:stop_point",
+            Type::Line => "public_transport:line",
+            Type::Network => "public_transport:network",
+            Type::Street => "street",
+            Type::Zone => "zone",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum PoiType {
+    Whatever(String),
+}
+
+impl PoiType {
+    pub fn as_str(&self) -> &str {
+        match *self {
+            PoiType::Whatever(ref s) => s,
+        }
+    }
+}
 
 pub fn make_coord(lon: f64, lat: f64) -> Result<Coord, BragiError> {
     if lat < -90f64 || lat > 90f64 {
@@ -1,34 +1,100 @@
+use crate::cache;
 use crate::extractors::BragiQuery;
-use crate::routes::params;
-use crate::{model, model::FromWithLang, Context};
+use crate::routes::params::{self, PoiType, Type};
+use crate::{model, model::FromWithLang, query, Context};
 use actix_http::http::header::{CacheControl, CacheDirective};
 use actix_web::web::{Data, HttpResponse};
+use futures::future::{self, Future};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+fn default_limit() -> u64 {
+    1u64
+}
+
+fn default_radius() -> f64 {
+    1000f64
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Params {
     lat: f64,
     lon: f64,
     /// timeout in milliseconds
     timeout: Option<u64>,
+    #[serde(default)]
+    pt_dataset: Vec<String>,
+    #[serde(default)]
+    poi_dataset: Vec<String>,
+    #[serde(rename = "_all_data", default)]
+    all_data: bool,
+    #[serde(default = "default_limit")]
+    limit: u64,
+    /// search radius in meters
+    #[serde(default = "default_radius")]
+    radius: f64,
+    #[serde(default, rename = "type")]
+    types: Vec<Type>,
+    #[serde(default, rename = "poi_type")]
+    poi_types: Vec<PoiType>,
 }
 
 pub fn reverse(
     params: BragiQuery<Params>,
     state: Data<Context>,
-) -> Result<HttpResponse, model::BragiError> {
-    let mut rubber = state.get_rubber_for_reverse(params.timeout.map(Duration::from_millis));
+) -> impl Future<Item = HttpResponse, Error = model::BragiError> {
+    let http_cache_duration = state.http_cache_duration;
+    cache::cached(&state.cache, "reverse", &*params, || {
+        future::result(compute_reverse(&*params, &*state)).flatten()
+    })
+    .map(move |body| {
+        HttpResponse::Ok()
+            .set(CacheControl(vec![CacheDirective::MaxAge(
+                http_cache_duration,
+            )]))
+            .content_type("application/json")
+            .body(body)
+    })
+}
+
+fn compute_reverse(
+    params: &Params,
+    state: &Context,
+) -> Result<impl Future<Item = model::Autocomplete, Error = model::BragiError>, model::BragiError> {
+    let backend = state.get_backend_for_reverse(params.timeout.map(Duration::from_millis));
     let coord = params::make_coord(params.lon, params.lat)?;
-    rubber
-        .get_address(&coord)
-        .map_err(model::BragiError::from)
-        .map(|r| model::Autocomplete::from_with_lang(r, None))
-        .map(|v| {
-            HttpResponse::Ok()
-                .set(CacheControl(vec![CacheDirective::MaxAge(
-                    state.http_cache_duration,
-                )]))
-                .json(v)
-        })
+    if params.radius.is_nan() || params.radius <= 0f64 {
+        return Err(model::BragiError::InvalidParam(
+            "radius should be a positive number of meters",
+        ));
+    }
+    Ok(query::reverse(
+        &coord,
+        &params
+            .pt_dataset
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>(),
+        &params
+            .poi_dataset
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>(),
+        params.all_data,
+        params.radius,
+        params.limit,
+        &params.types.iter().map(Type::as_str).collect::<Vec<_>>(),
+        &params
+            .poi_types
+            .iter()
+            .map(PoiType::as_str)
+            .collect::<Vec<_>>(),
+        backend,
+    )
+    .map(|r| match r {
+        query::Reverse::Places(places) => model::Autocomplete::from_with_lang(places, None),
+        query::Reverse::AdminFallback(admins) => {
+            model::Autocomplete::from_with_lang(admins, None).with_admin_fallback()
+        }
+    }))
 }
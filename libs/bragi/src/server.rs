@@ -1,12 +1,16 @@
 use crate::extractors::ActixError;
 use crate::routes::{
-    autocomplete, entry_point, features, post_autocomplete, reverse, status, JsonParams,
+    autocomplete, entry_point, features, post_autocomplete, post_batch_autocomplete, reverse,
+    status, JsonParams, Params,
 };
 use crate::{Args, Context};
 use actix_web::FromRequest;
 use actix_web::{middleware, web, App, HttpRequest, HttpServer};
 use structopt::StructOpt;
 
+/// max size (in bytes) of the body of a batch autocomplete
+const BATCH_MAX_PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+
 pub fn default_404(req: HttpRequest) -> Result<web::Json<()>, ActixError> {
     Err(ActixError::RouteNotFound(req.path().to_string()))
 }
@@ -20,12 +24,21 @@ pub fn configure_server(cfg: &mut web::ServiceConfig) {
     .service(
         web::resource("/autocomplete")
             .name("autocomplete")
-            .route(web::get().to(autocomplete))
-            .route(web::post().to(post_autocomplete))
+            .route(web::get().to_async(autocomplete))
+            .route(web::post().to_async(post_autocomplete))
             .data(web::Json::<JsonParams>::configure(|cfg| {
                 cfg.error_handler(|err, _req| ActixError::InvalidJson(format!("{}", err)).into())
             })),
     )
+    .service(
+        web::resource("/autocomplete/batch")
+            .name("batch_autocomplete")
+            .route(web::post().to_async(post_batch_autocomplete))
+            .data(web::Json::<Vec<Params>>::configure(|cfg| {
+                cfg.limit(BATCH_MAX_PAYLOAD_SIZE)
+                    .error_handler(|err, _req| ActixError::InvalidJson(format!("{}", err)).into())
+            })),
+    )
     .service(
         web::resource("/status")
             .name("status")
@@ -34,12 +47,12 @@ pub fn configure_server(cfg: &mut web::ServiceConfig) {
     .service(
         web::resource("/features/{id}")
             .name("features")
-            .route(web::get().to(features)),
+            .route(web::get().to_async(features)),
     )
     .service(
         web::resource("/reverse")
             .name("reverse")
-            .route(web::get().to(reverse)),
+            .route(web::get().to_async(reverse)),
     );
 }
 
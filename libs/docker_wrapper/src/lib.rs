@@ -35,6 +35,12 @@ use std::error::Error;
 use std::process::Command;
 use std::time::Duration;
 
+/// ES image used by default for the tests
+const ES_IMAGE: &str = "elasticsearch:2";
+
+/// location of the snapshot repositories in the ES docker
+pub const SNAPSHOT_LOCATION: &str = "/tmp/mimir_snapshots";
+
 /// This struct wraps a docker (for the moment explicitly ElasticSearch)
 /// Allowing to setup a docker, tear it down and to provide its address and port
 pub struct DockerWrapper {
@@ -47,10 +53,27 @@ impl DockerWrapper {
     }
 
     fn setup(&mut self) -> Result<(), Box<dyn Error>> {
-        info!("Launching ES docker");
-        let status = Command::new("docker")
-            .args(&["run", "-d", "--name=mimirsbrunn_tests", "elasticsearch:2"])
-            .status()?;
+        // the ES image can be changed to run the tests against another ES version
+        let image = std::env::var("MIMIR_TEST_ES_IMAGE").unwrap_or_else(|_| ES_IMAGE.to_string());
+        info!("Launching ES docker {}", image);
+        let path_repo = format!("path.repo={}", SNAPSHOT_LOCATION);
+        let mut args = vec![
+            "run",
+            "-d",
+            "--name=mimirsbrunn_tests",
+            // needed by ES >= 7 to start without a cluster
+            "-e",
+            "discovery.type=single-node",
+            // the settings are read from the environment since ES 5, ES 2 reads them in its arguments
+            "-e",
+            path_repo.as_str(),
+            image.as_str(),
+        ];
+        let es2_path_repo = format!("-Des.{}", path_repo);
+        if image.starts_with("elasticsearch:2") {
+            args.push(es2_path_repo.as_str());
+        }
+        let status = Command::new("docker").args(&args).status()?;
         if !status.success() {
             return Err(format!("`docker run` failed {}", &status).into());
         }
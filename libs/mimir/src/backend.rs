@@ -0,0 +1,488 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use super::objects::{Admin, AliasOperation, AliasParameter, Coord, MimirObject, Place};
+use super::quality::QualityGate;
+use super::rubber::{
+    get_date_index_name, get_main_type_and_dataset_index, get_main_type_index, IndexSettings,
+    IndexVisibility, SearchRequest, TypedIndex,
+};
+use failure::{format_err, Error, ResultExt};
+use futures::future::Future;
+use rs_es::error::EsError;
+use rs_es::query::Query;
+use rs_es::units as rs_u;
+use slog_scope::{debug, info};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time;
+
+/// The places found by a search
+pub type PlacesFuture = Box<dyn Future<Item = Vec<Place>, Error = EsError>>;
+
+/// The results of several searches sent at once (cf `Backend::multi_search`)
+pub type MultiSearchFuture =
+    Box<dyn Future<Item = Vec<Result<Vec<Place>, EsError>>, Error = EsError>>;
+
+/// A search engine storing the mimir objects
+///
+/// `Rubber` is the elasticsearch backend,
+/// `MemoryBackend` keeps everything in memory (for the tests and the small deployments).
+///
+/// The importers index the objects with the generic methods, only available on a concrete backend.
+/// Bragi only needs the search methods, so it can use any backend as a `dyn Backend`.
+///
+/// Note: the searches are expressed with the elasticsearch query DSL (cf `SearchRequest`),
+/// the other backends need to interpret it.
+pub trait Backend: std::fmt::Debug + Send + Sync {
+    /// create an empty index
+    fn create_index(&self, name: &str, index_settings: &IndexSettings) -> Result<(), Error>;
+
+    fn delete_index(&mut self, index: &str) -> Result<(), Error>;
+
+    /// make all the objects added to the index visible by the searches
+    fn refresh_index(&mut self, index: &str) -> Result<(), Error>;
+
+    /// number of documents of the index (or the alias)
+    fn count_documents(&self, index: &str) -> Result<u64, Error>;
+
+    /// get all the indexes whose names start with `base_index`
+    /// return a map with each index as key and all their aliases
+    fn get_all_aliased_index(
+        &self,
+        base_index: &str,
+    ) -> Result<BTreeMap<String, Vec<String>>, Error>;
+
+    /// add a list of new indexes to the alias
+    /// remove a list of indexes from the alias
+    fn alias(&self, alias: &str, add: &[String], remove: &[String]) -> Result<(), Error>;
+
+    /// apply all the alias operations at once (none of them is applied if one fails)
+    fn update_aliases(&self, actions: Vec<AliasOperation>) -> Result<(), Error>;
+
+    /// add all the elements of `iter` to the index, return the number of added elements
+    fn bulk_index<T, I>(&mut self, index: &TypedIndex<T>, iter: I) -> Result<usize, EsError>
+    where
+        Self: Sized,
+        T: MimirObject + std::marker::Send + 'static,
+        I: Iterator<Item = T>;
+
+    /// remove the objects with the ids `ids` from the index (the unknown ids are ignored)
+    /// return the number of removed objects
+    fn delete_documents<T>(
+        &mut self,
+        index: &TypedIndex<T>,
+        ids: &[String],
+    ) -> Result<usize, EsError>
+    where
+        Self: Sized,
+        T: MimirObject;
+
+    /// read all the objects of an index (or an alias)
+    fn get_all_objects_from_index<T>(&mut self, index: &str) -> Result<Vec<T>, EsError>
+    where
+        Self: Sized,
+        for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug;
+
+    /// read all the objects of an index (or an alias) matching the query
+    fn get_objects_by_query<T>(&mut self, index: &str, query: Query) -> Result<Vec<T>, EsError>
+    where
+        Self: Sized,
+        for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug;
+
+    /// read the objects of an index (or an alias) matching the query (all of them without query)
+    /// page by page, `f` is called on each page with the backend (to write the page for instance)
+    fn for_each_page<T, F>(&mut self, index: &str, query: Option<Query>, f: F) -> Result<(), Error>
+    where
+        Self: Sized,
+        for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug,
+        F: FnMut(&mut Self, Vec<T>) -> Result<(), Error>;
+
+    /// whether the values of the field are indexed as they are in the index,
+    /// so that the objects can be found by these values with a `terms` query
+    fn is_exact_field(&self, index: &str, field: &str) -> Result<bool, Error>;
+
+    /// search places, without blocking the current thread
+    fn search_places(&self, search: SearchRequest) -> PlacesFuture;
+
+    /// send several searches at once, without blocking the current thread
+    ///
+    /// The outer result is an error if the whole request failed,
+    /// the inner results are the results of each search, in the same order as `searches`
+    fn multi_search(&self, searches: Vec<SearchRequest>) -> MultiSearchFuture;
+
+    /// search some places and wait for the results
+    ///
+    /// Unlike `search_places`, it does not need to be run in an event loop,
+    /// so it can be used by the importers
+    fn search_places_blocking(&self, search: SearchRequest) -> Result<Vec<Place>, EsError> {
+        self.search_places(search).wait()
+    }
+
+    /// get the places with the id `id` in `indexes`, without blocking the current thread
+    /// (the same id can be found in several indexes)
+    fn get_places_by_id(
+        &self,
+        indexes: Vec<String>,
+        id: &str,
+        max_results: u64,
+        with_geometry: bool,
+    ) -> PlacesFuture {
+        let query = Query::build_ids(vec![rs_u::JsonVal::String(id.into())]).build();
+        self.search_places(SearchRequest {
+            indexes,
+            query,
+            from: 0,
+            size: max_results,
+            coord: None,
+            explain: false,
+            with_geometry,
+        })
+    }
+
+    /// get the nearest address (or street) of `coord`
+    fn get_address(&mut self, coord: &Coord) -> Result<Vec<Place>, EsError>;
+
+    /// a backend on the same data, with another timeout for the searches
+    fn with_timeout(&self, timeout: Option<time::Duration>) -> Arc<dyn Backend>;
+
+    /// number of previous generations of an index kept (without alias) when a new index
+    /// of the same doc_type and dataset is published, to be able to roll back to them
+    fn keep_previous(&self) -> usize {
+        0
+    }
+
+    /// checks done on a new index before its publication
+    fn quality_gate(&self) -> Option<&QualityGate> {
+        None
+    }
+
+    /// create a new index for the objects `T` of the dataset
+    /// (the index is not visible until it is published)
+    fn make_index<T: MimirObject>(
+        &self,
+        dataset: &str,
+        index_settings: &IndexSettings,
+    ) -> Result<TypedIndex<T>, Error>
+    where
+        Self: Sized,
+    {
+        let index_name = get_date_index_name(&get_main_type_and_dataset_index::<T>(dataset));
+        info!("creating index {}", index_name);
+        self.create_index(&index_name, index_settings)?;
+        Ok(TypedIndex::new(index_name))
+    }
+
+    /// publish the index as the new index for this doc_type and this dataset
+    /// move the index alias of the doc_type and the dataset to point to this indexes
+    /// and remove the old indexes (but the `keep_previous` last published ones)
+    /// The index is not published if it does not pass the `quality_gate`.
+    /// visibility: Indicate if the index is public or private
+    fn publish_index<T: MimirObject>(
+        &mut self,
+        dataset: &str,
+        index: TypedIndex<T>,
+        visibility: IndexVisibility,
+    ) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        debug!("publishing index");
+
+        // Refresh index before publishing
+        self.refresh_index(&index.name)?;
+
+        let dataset_index = get_main_type_and_dataset_index::<T>(dataset);
+        // get the last indexes for this doc_type/dataset
+        // Note: to be resilient to ghost ES indexes, we take all indexes for this doc_type/dataset
+        // but the new index (we don't want to remove the newly created index)
+        let previous_indexes: BTreeMap<String, Vec<String>> = self
+            .get_all_aliased_index(&dataset_index)?
+            .into_iter()
+            .filter(|(i, _)| i.as_str() != index.name)
+            .collect();
+        let last_indexes: Vec<String> = previous_indexes.keys().cloned().collect();
+
+        if let Some(quality_gate) = self.quality_gate() {
+            let published = previous_indexes
+                .iter()
+                .filter(|(_, aliases)| aliases.contains(&dataset_index))
+                .map(|(i, _)| i.as_str())
+                .last();
+            quality_gate
+                .check::<T, Self>(&*self, dataset, &index.name, published)
+                .with_context(|_| format!("the index {} is not published", index.name))?;
+        }
+
+        self.alias(&dataset_index, &vec![index.name.clone()], &last_indexes)
+            .with_context(|_| format!("Error occurred when making alias: {}", dataset_index))?;
+
+        let type_index = get_main_type_index::<T>();
+        if let IndexVisibility::Public = visibility {
+            self.alias(&type_index, &vec![dataset_index.clone()], &last_indexes)
+                .with_context(|_| format!("Error occurred when making alias: {}", type_index))?;
+        }
+
+        if let IndexVisibility::Public = visibility {
+            if T::is_geo_data() {
+                self.alias("munin_geo_data", &vec![type_index.to_string()], &vec![])
+                    .context("Error occurred when making alias: munin_geo_data")?;
+                self.alias("munin", &vec!["munin_geo_data".to_string()], &vec![])
+                    .context("Error occurred when making alias: munin")?;
+            } else {
+                self.alias("munin", &vec![type_index.to_string()], &vec![])
+                    .context("Error occurred when making alias: munin")?;
+            }
+        }
+
+        for i in outdated_indexes(&previous_indexes, &dataset_index, self.keep_previous()) {
+            self.delete_index(&i)
+                .with_context(|_| format!("Error occurred when deleting index: {}", i))?;
+        }
+        Ok(())
+    }
+
+    /// move all the aliases of the published index for this doc_type and this dataset
+    /// to the previous generation of the index (cf `keep_previous`)
+    ///
+    /// The rolled back index is kept without alias, it will be removed by the next publication.
+    /// Return the name of the index now published, None if there is nothing to roll back.
+    fn rollback_index<T: MimirObject>(&mut self, dataset: &str) -> Result<Option<String>, Error>
+    where
+        Self: Sized,
+    {
+        let dataset_index = get_main_type_and_dataset_index::<T>(dataset);
+        let indexes = self.get_all_aliased_index(&dataset_index)?;
+        let (current, aliases) = match indexes
+            .iter()
+            .filter(|(_, aliases)| aliases.contains(&dataset_index))
+            .last()
+        {
+            Some(published) => published,
+            None => {
+                info!("no index published for {}", dataset_index);
+                return Ok(None);
+            }
+        };
+        let previous = match indexes.range::<String, _>(..current).next_back() {
+            Some((previous, _)) => previous,
+            None => return Ok(None),
+        };
+        info!(
+            "rolling back {} from {} to {}",
+            dataset_index, current, previous
+        );
+
+        // all the aliases are moved at once, so that the dataset is never half rolled back
+        let actions = aliases
+            .iter()
+            .flat_map(|alias| {
+                let add = AliasOperation {
+                    add: Some(AliasParameter {
+                        index: previous.clone(),
+                        alias: alias.clone(),
+                    }),
+                    remove: None,
+                };
+                let remove = AliasOperation {
+                    add: None,
+                    remove: Some(AliasParameter {
+                        index: current.clone(),
+                        alias: alias.clone(),
+                    }),
+                };
+                vec![add, remove]
+            })
+            .collect();
+        self.update_aliases(actions)
+            .with_context(|_| format!("Error occurred when moving the aliases of {}", current))?;
+        Ok(Some(previous.clone()))
+    }
+
+    /// delete all the indexes of this doc_type and this dataset,
+    /// when the dataset has no such document anymore
+    ///
+    /// Return the number of deleted indexes.
+    fn unpublish_index<T: MimirObject>(&mut self, dataset: &str) -> Result<usize, Error>
+    where
+        Self: Sized,
+    {
+        let dataset_index = get_main_type_and_dataset_index::<T>(dataset);
+        // the indexes of the datasets whose names start with this one are kept
+        let prefix = format!("{}_", dataset_index);
+        let indexes: Vec<String> = self
+            .get_all_aliased_index(&dataset_index)?
+            .into_iter()
+            .map(|(index, _)| index)
+            .filter(|index| {
+                index.starts_with(&prefix)
+                    && index[prefix.len()..].starts_with(|c: char| c.is_ascii_digit())
+            })
+            .collect();
+        for index in &indexes {
+            info!(
+                "deleting {}, the dataset has no {} anymore",
+                index,
+                T::doc_type()
+            );
+            self.delete_index(index)
+                .with_context(|_| format!("Error occurred when deleting index: {}", index))?;
+        }
+        Ok(indexes.len())
+    }
+
+    /// Shortcut to `index` for a public index
+    fn public_index<T, I>(
+        &mut self,
+        dataset: &str,
+        index_settings: &IndexSettings,
+        iter: I,
+    ) -> Result<usize, Error>
+    where
+        Self: Sized,
+        T: MimirObject + std::marker::Send + 'static,
+        I: Iterator<Item = T>,
+    {
+        self.index(dataset, IndexVisibility::Public, index_settings, iter)
+    }
+
+    /// Shortcut to `index` for a private index
+    fn private_index<T, I>(
+        &mut self,
+        dataset: &str,
+        index_settings: &IndexSettings,
+        iter: I,
+    ) -> Result<usize, Error>
+    where
+        Self: Sized,
+        T: MimirObject + std::marker::Send + 'static,
+        I: Iterator<Item = T>,
+    {
+        self.index(dataset, IndexVisibility::Private, index_settings, iter)
+    }
+
+    /// add all the element of 'iter' into the backend
+    ///
+    /// To have zero downtime:
+    /// first all the elements are added in a temporary index and when all has been indexed
+    /// the index is published and the old index is removed
+    fn index<T, I>(
+        &mut self,
+        dataset: &str,
+        visibility: IndexVisibility,
+        index_settings: &IndexSettings,
+        iter: I,
+    ) -> Result<usize, Error>
+    where
+        Self: Sized,
+        T: MimirObject + std::marker::Send + 'static,
+        I: Iterator<Item = T>,
+    {
+        // TODO better error handling
+        let index = self
+            .make_index(dataset, index_settings)
+            .with_context(|_| format!("Error occurred when making index: {}", dataset))?;
+        let nb_elements = self.bulk_index(&index, iter)?;
+        self.publish_index(dataset, index, visibility)?;
+        Ok(nb_elements)
+    }
+
+    /// apply some changes to the published index of the objects `T` of the dataset
+    ///
+    /// The objects of `upserted` are added (or replaced if there is already an object with the same id)
+    /// and the objects with the ids `deleted` are removed.
+    /// Return the number of upserted and removed objects
+    fn update_index<T, I>(
+        &mut self,
+        dataset: &str,
+        upserted: I,
+        deleted: &[String],
+    ) -> Result<(usize, usize), Error>
+    where
+        Self: Sized,
+        T: MimirObject + std::marker::Send + 'static,
+        I: Iterator<Item = T>,
+    {
+        let dataset_index = get_main_type_and_dataset_index::<T>(dataset);
+        let index = self
+            .get_all_aliased_index(&dataset_index)?
+            .into_iter()
+            .find(|(_, aliases)| aliases.contains(&dataset_index))
+            .map(|(index, _)| TypedIndex::<T>::new(index))
+            .ok_or_else(|| {
+                format_err!(
+                    "no index published for {}, the dataset must be fully imported first",
+                    dataset_index
+                )
+            })?;
+        info!("updating index {}", index.name);
+        let nb_upserted = self.bulk_index(&index, upserted)?;
+        let nb_deleted = self.delete_documents(&index, deleted)?;
+        self.refresh_index(&index.name)?;
+        Ok((nb_upserted, nb_deleted))
+    }
+
+    fn get_all_admins(&mut self) -> Result<Vec<Admin>, EsError>
+    where
+        Self: Sized,
+    {
+        self.get_all_objects_from_index(&get_main_type_index::<Admin>())
+    }
+}
+
+/// the previous indexes of a dataset to delete when a new index is published
+///
+/// Only the `keep_previous` last published generations are kept.
+/// The indexes more recent than the published one have never been published
+/// (their import has been stopped, or they have been rolled back), they are always deleted.
+/// Note: the names of the indexes end with their creation date, so they are sorted by date.
+fn outdated_indexes(
+    previous_indexes: &BTreeMap<String, Vec<String>>,
+    dataset_index: &str,
+    keep_previous: usize,
+) -> Vec<String> {
+    let generations: Vec<&String> = match previous_indexes
+        .iter()
+        .filter(|(_, aliases)| aliases.iter().any(|a| a == dataset_index))
+        .last()
+    {
+        Some((published, _)) => previous_indexes
+            .range::<String, _>(..=published)
+            .map(|(i, _)| i)
+            .collect(),
+        None => vec![],
+    };
+    let kept = &generations[generations.len().saturating_sub(keep_previous)..];
+    previous_indexes
+        .keys()
+        .filter(|i| !kept.contains(i))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,457 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! A `Backend` writing the indexed documents to a file instead of elasticsearch
+//!
+//! It is used to run the importers without writing in any cluster (to compare the outputs
+//! of two versions for example), or to load their documents later on a cluster.
+//! Nothing can be searched in the file: the data needed by the importers (the admins
+//! for example) are read from a source cluster if any, otherwise they are not found.
+//!
+//! Only the documents are exported: the bulk output has no alias action, so the loaded
+//! indexes must be published by hand (with the `_aliases` api of elasticsearch).
+
+use super::backend::{Backend, MultiSearchFuture, PlacesFuture};
+use super::objects::{AliasOperation, Coord, MimirObject, Place};
+use super::rubber::{Document, IndexSettings, Rubber, SearchRequest, TypedIndex};
+use failure::{bail, format_err, Error, ResultExt};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::future;
+use rs_es::error::EsError;
+use rs_es::query::Query;
+use serde_json::json;
+use slog_scope::info;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time;
+
+/// The format of the exported documents
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// one json document by line, with its type in the `type` field
+    /// (the `_source` of the elasticsearch documents)
+    NdJson,
+    /// an elasticsearch bulk request, with the mapping types of the ES 2 to 6 templates
+    Bulk,
+    /// an elasticsearch bulk request, without mapping types for ES 7
+    TypelessBulk,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ndjson" => Ok(ExportFormat::NdJson),
+            "bulk" => Ok(ExportFormat::Bulk),
+            "bulk-typeless" => Ok(ExportFormat::TypelessBulk),
+            _ => bail!(
+                "unknown export format {}, it should be ndjson, bulk or bulk-typeless",
+                s
+            ),
+        }
+    }
+}
+
+/// the file where the documents are written
+enum Output {
+    Plain(Box<dyn Write + Send>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(w) => w.write(buf),
+            Output::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(w) => w.flush(),
+            Output::Gzip(w) => w.flush(),
+        }
+    }
+}
+
+impl Output {
+    /// write everything still buffered, with the end of the gzip stream
+    fn finish(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(w) => w.flush(),
+            Output::Gzip(w) => {
+                w.try_finish()?;
+                w.get_mut().flush()
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ExportBackend {
+    format: ExportFormat,
+    writer: Arc<Mutex<Output>>,
+    /// the cluster where the existing data are read
+    source: Option<Rubber>,
+}
+
+impl fmt::Debug for ExportBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExportBackend")
+            .field("format", &self.format)
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+impl ExportBackend {
+    /// write the documents in `writer`
+    pub fn new(writer: Box<dyn Write + Send>, format: ExportFormat) -> ExportBackend {
+        ExportBackend::with_output(Output::Plain(writer), format)
+    }
+
+    fn with_output(output: Output, format: ExportFormat) -> ExportBackend {
+        ExportBackend {
+            format,
+            writer: Arc::new(Mutex::new(output)),
+            source: None,
+        }
+    }
+
+    /// read the existing data (admins, addresses, ...) in `source` instead of finding nothing
+    pub fn with_source(self, source: Rubber) -> ExportBackend {
+        ExportBackend {
+            source: Some(source),
+            ..self
+        }
+    }
+
+    /// write the documents in a new file, compressed with gzip if its extension is `gz`
+    pub fn create(path: &Path, format: ExportFormat) -> Result<ExportBackend, Error> {
+        let file = BufWriter::new(
+            File::create(path).with_context(|_| format!("impossible to create {:?}", path))?,
+        );
+        info!("exporting the documents in {:?}", path);
+        let output = if path.extension().map_or(false, |e| e == "gz") {
+            Output::Gzip(GzEncoder::new(file, Compression::default()))
+        } else {
+            Output::Plain(Box::new(file))
+        };
+        Ok(ExportBackend::with_output(output, format))
+    }
+
+    /// write the end of the file, once all the documents are exported
+    ///
+    /// The file is also finished when the last clone of the backend is dropped,
+    /// but the errors are then lost.
+    pub fn finish(self) -> Result<(), Error> {
+        self.writer()
+            .finish()
+            .map_err(|e| format_err!("impossible to export the documents: {}", e))
+    }
+
+    fn writer(&self) -> MutexGuard<Output> {
+        // the writer is still usable even if another thread panicked while writing
+        self.writer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// the action line of a bulk request on a document
+    fn action<T: MimirObject>(&self, action: &str, index: &str, id: Option<String>) -> String {
+        let mut meta = json!({ "_index": index });
+        if self.format == ExportFormat::Bulk {
+            meta["_type"] = T::doc_type().into();
+        }
+        if let Some(id) = id {
+            meta["_id"] = id.into();
+        }
+        let mut line = serde_json::Map::new();
+        line.insert(action.to_string(), meta);
+        serde_json::Value::Object(line).to_string()
+    }
+}
+
+impl Drop for ExportBackend {
+    fn drop(&mut self) {
+        // the last clone finishes the file if `finish` has not been called
+        if Arc::strong_count(&self.writer) == 1 {
+            let _ = self.writer().finish();
+        }
+    }
+}
+
+fn not_available(what: &str) -> EsError {
+    EsError::EsError(format!(
+        "{} is not available when exporting to a file",
+        what
+    ))
+}
+
+fn write_error(e: std::io::Error) -> EsError {
+    EsError::EsError(format!("impossible to export the documents: {}", e))
+}
+
+impl Backend for ExportBackend {
+    /// the indexes are created when the documents are loaded in elasticsearch
+    fn create_index(&self, _name: &str, _index_settings: &IndexSettings) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn delete_index(&mut self, _index: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn refresh_index(&mut self, _index: &str) -> Result<(), Error> {
+        self.writer()
+            .flush()
+            .map_err(|e| format_err!("impossible to export the documents: {}", e))
+    }
+
+    fn count_documents(&self, index: &str) -> Result<u64, Error> {
+        match self.source {
+            Some(ref source) => source.count_documents(index),
+            None => Err(not_available(&format!("counting the documents of {}", index)).into()),
+        }
+    }
+
+    /// nothing is published in the file, so there is no previous index to replace
+    fn get_all_aliased_index(
+        &self,
+        _base_index: &str,
+    ) -> Result<BTreeMap<String, Vec<String>>, Error> {
+        Ok(BTreeMap::new())
+    }
+
+    /// the aliases are not exported, they are set when the documents are loaded
+    fn alias(&self, _alias: &str, _add: &[String], _remove: &[String]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn update_aliases(&self, _actions: Vec<AliasOperation>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn bulk_index<T, I>(&mut self, index: &TypedIndex<T>, iter: I) -> Result<usize, EsError>
+    where
+        T: MimirObject + std::marker::Send + 'static,
+        I: Iterator<Item = T>,
+    {
+        let mut writer = self.writer();
+        let mut nb = 0;
+        for object in iter {
+            if self.format != ExportFormat::NdJson {
+                let action = self.action::<T>("index", index.name(), object.es_id());
+                writeln!(writer, "{}", action).map_err(write_error)?;
+            }
+            let document = serde_json::to_string(&Document {
+                doc_type: T::doc_type(),
+                object,
+            })
+            .map_err(|e| EsError::EsError(format!("impossible to export a document: {}", e)))?;
+            writeln!(writer, "{}", document).map_err(write_error)?;
+            nb += 1;
+        }
+        Ok(nb)
+    }
+
+    /// the deletions can only be exported in a bulk request
+    fn delete_documents<T>(
+        &mut self,
+        index: &TypedIndex<T>,
+        ids: &[String],
+    ) -> Result<usize, EsError>
+    where
+        T: MimirObject,
+    {
+        if self.format == ExportFormat::NdJson {
+            return Err(not_available("the deletion of documents in ndjson"));
+        }
+        let mut writer = self.writer();
+        for id in ids {
+            let action = self.action::<T>("delete", index.name(), Some(id.clone()));
+            writeln!(writer, "{}", action).map_err(write_error)?;
+        }
+        Ok(ids.len())
+    }
+
+    fn get_all_objects_from_index<T>(&mut self, index: &str) -> Result<Vec<T>, EsError>
+    where
+        for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug,
+    {
+        match self.source {
+            Some(ref mut source) => source.get_all_objects_from_index(index),
+            None => Err(not_available(&format!("reading {}", index))),
+        }
+    }
+
+    fn get_objects_by_query<T>(&mut self, index: &str, query: Query) -> Result<Vec<T>, EsError>
+    where
+        for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug,
+    {
+        match self.source {
+            Some(ref mut source) => source.get_objects_by_query(index, query),
+            None => Err(not_available(&format!("reading {}", index))),
+        }
+    }
+
+    /// the objects are read in the source, the pages are written in the file
+    fn for_each_page<T, F>(
+        &mut self,
+        index: &str,
+        query: Option<Query>,
+        mut f: F,
+    ) -> Result<(), Error>
+    where
+        for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug,
+        F: FnMut(&mut Self, Vec<T>) -> Result<(), Error>,
+    {
+        match self.source.clone() {
+            Some(source) => source.scroll_objects(index, query, |page| f(self, page)),
+            None => Err(not_available(&format!("reading {}", index)).into()),
+        }
+    }
+
+    fn is_exact_field(&self, index: &str, field: &str) -> Result<bool, Error> {
+        match self.source {
+            Some(ref source) => source.is_exact_field(index, field),
+            None => Err(not_available(&format!("reading the mapping of {}", index)).into()),
+        }
+    }
+
+    fn search_places(&self, search: SearchRequest) -> PlacesFuture {
+        match self.source {
+            Some(ref source) => source.search_places(search),
+            None => Box::new(future::err(not_available("searching"))),
+        }
+    }
+
+    fn search_places_blocking(&self, search: SearchRequest) -> Result<Vec<Place>, EsError> {
+        match self.source {
+            Some(ref source) => source.search_places_blocking(search),
+            None => Err(not_available("searching")),
+        }
+    }
+
+    fn multi_search(&self, searches: Vec<SearchRequest>) -> MultiSearchFuture {
+        match self.source {
+            Some(ref source) => source.multi_search(searches),
+            None => Box::new(future::err(not_available("searching"))),
+        }
+    }
+
+    fn get_address(&mut self, coord: &Coord) -> Result<Vec<Place>, EsError> {
+        match self.source {
+            Some(ref mut source) => source.get_address(coord),
+            None => Err(not_available("searching an address")),
+        }
+    }
+
+    fn with_timeout(&self, _timeout: Option<time::Duration>) -> Arc<dyn Backend> {
+        Arc::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Street;
+    use std::io;
+
+    /// a writer whose content can still be read once the backend is dropped
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn export(format: ExportFormat) -> Vec<serde_json::Value> {
+        let buffer = SharedBuffer::default();
+        let mut backend = ExportBackend::new(Box::new(buffer.clone()), format);
+        let index = TypedIndex::<Street>::new("munin_street_fr_20191001_120000_000".to_string());
+        let street = Street {
+            id: "street:1".to_string(),
+            name: "Rue Hector Malot".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            backend.bulk_index(&index, std::iter::once(street)).unwrap(),
+            1
+        );
+        if format != ExportFormat::NdJson {
+            backend
+                .delete_documents(&index, &["street:2".to_string()])
+                .unwrap();
+        }
+        backend.finish().unwrap();
+        let content = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn export_ndjson() {
+        let lines = export(ExportFormat::NdJson);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["type"], "street");
+        assert_eq!(lines[0]["id"], "street:1");
+    }
+
+    #[test]
+    fn export_bulk() {
+        let lines = export(ExportFormat::Bulk);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            json!({"index": {
+                "_index": "munin_street_fr_20191001_120000_000",
+                "_type": "street",
+                "_id": "street:1"
+            }})
+        );
+        assert_eq!(lines[1]["name"], "Rue Hector Malot");
+        assert_eq!(lines[2]["delete"]["_id"], "street:2");
+
+        let lines = export(ExportFormat::TypelessBulk);
+        assert!(lines[0]["index"].get("_type").is_none());
+    }
+}
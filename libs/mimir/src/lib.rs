@@ -35,7 +35,11 @@
 // #[macro_use]
 // extern crate failure;
 
+pub mod backend;
+pub mod export;
+pub mod memory;
 pub mod objects;
+pub mod quality;
 pub mod rubber;
 
 pub use crate::objects::*;
@@ -0,0 +1,1412 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! An in-process `Backend`, without elasticsearch
+//!
+//! The searches are expressed with the elasticsearch query DSL,
+//! the `MemoryBackend` interprets the subset of it used by mimir and bragi:
+//! `bool`, `term`, `terms`, `match`, `multi_match`, `ids`, `exists`,
+//! `geo_distance`, `geo_shape` and `function_score`.
+//!
+//! The analysis is much simpler than the elasticsearch one
+//! (lowercase and ascii folding only, no synonyms),
+//! and the scores are only an approximation of the elasticsearch ones,
+//! but the matching places should be the same.
+
+use super::backend::{Backend, MultiSearchFuture, PlacesFuture};
+use super::objects::{AliasOperation, Coord, MimirObject, Place};
+use super::quality::QualityGate;
+use super::rubber::{
+    build_address_search, get_doc_type, make_places, Document, IndexSettings, SearchRequest,
+    TypedIndex,
+};
+use failure::{bail, Error};
+use futures::future;
+use geo::algorithm::contains::Contains;
+use geo::algorithm::haversine_distance::HaversineDistance;
+use rs_es::error::EsError;
+use rs_es::query::Query;
+use serde_json::Value;
+use slog_scope::{debug, info};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryInto;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time;
+
+#[derive(Debug, Default)]
+struct Indexes {
+    /// the documents of each index, by id
+    documents: BTreeMap<String, BTreeMap<String, Value>>,
+    /// the indexes of each alias
+    aliases: BTreeMap<String, BTreeSet<String>>,
+    /// used to give an id to the objects without `es_id`
+    next_id: u64,
+}
+
+impl Indexes {
+    /// the indexes behind a name, like elasticsearch does
+    /// (an index, an alias, a pattern with '*' or `_all`)
+    fn resolve(&self, name: &str) -> BTreeSet<String> {
+        if name == "_all" {
+            self.documents.keys().cloned().collect()
+        } else if name.contains('*') {
+            let indexes = self
+                .documents
+                .keys()
+                .filter(|index| matches_pattern(name, index))
+                .cloned();
+            let aliased = self
+                .aliases
+                .iter()
+                .filter(|(alias, _)| matches_pattern(name, alias))
+                .flat_map(|(_, indexes)| indexes.iter().cloned());
+            indexes.chain(aliased).collect()
+        } else if self.documents.contains_key(name) {
+            std::iter::once(name.to_string()).collect()
+        } else {
+            self.aliases.get(name).cloned().unwrap_or_default()
+        }
+    }
+
+    /// like in elasticsearch, an alias added to an alias points to the indexes behind it
+    fn alias(&mut self, alias: &str, add: &[String], remove: &[String]) -> Result<(), Error> {
+        let mut added = BTreeSet::new();
+        for name in add {
+            let resolved = self.resolve(name);
+            if resolved.is_empty() {
+                bail!(
+                    "failed to add {} to the alias {}: no such index",
+                    name,
+                    alias
+                );
+            }
+            added.extend(resolved);
+        }
+        let removed: BTreeSet<String> = remove.iter().flat_map(|name| self.resolve(name)).collect();
+
+        let previous = self.aliases.remove(alias).unwrap_or_default();
+        let aliased = &(&previous | &added) - &removed;
+        if !aliased.is_empty() {
+            self.aliases.insert(alias.to_string(), aliased);
+        }
+        Ok(())
+    }
+}
+
+/// A backend keeping all the documents in memory
+///
+/// The clones share the same documents.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    indexes: Arc<RwLock<Indexes>>,
+    keep_previous: usize,
+    quality_gate: Option<QualityGate>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> MemoryBackend {
+        MemoryBackend::default()
+    }
+
+    /// keep the `keep_previous` last generations of the indexes when publishing a new one
+    pub fn with_keep_previous(self, keep_previous: usize) -> MemoryBackend {
+        MemoryBackend {
+            keep_previous,
+            ..self
+        }
+    }
+
+    /// check the new indexes with the quality gate before publishing them
+    pub fn with_quality_gate(self, quality_gate: QualityGate) -> MemoryBackend {
+        MemoryBackend {
+            quality_gate: Some(quality_gate),
+            ..self
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Indexes> {
+        self.indexes.read().expect("poisoned memory backend")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Indexes> {
+        self.indexes.write().expect("poisoned memory backend")
+    }
+
+    fn search(&self, search: &SearchRequest) -> Result<Vec<Place>, EsError> {
+        let query = serde_json::to_value(&search.query)
+            .map_err(|e| EsError::EsError(format!("invalid query: {}", e)))?;
+        let indexes = self.read();
+        let names: BTreeSet<String> = search
+            .indexes
+            .iter()
+            .flat_map(|name| indexes.resolve(name))
+            .collect();
+
+        let mut hits: Vec<(f64, &Value)> = names
+            .iter()
+            .filter_map(|name| indexes.documents.get(name))
+            .flat_map(|documents| documents.iter())
+            .filter_map(|(id, source)| {
+                score(&query, &Hit { id, source }).map(|score| (score, source))
+            })
+            .collect();
+        // the sort is stable, the ties stay in the index order
+        hits.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+
+        let hits = hits
+            .into_iter()
+            .skip(search.from as usize)
+            .take(search.size as usize)
+            .filter_map(|(score, source)| {
+                let mut source = source.clone();
+                // like in elasticsearch, the boundaries are not fetched,
+                // and the geometries only when asked
+                if let Some(source) = source.as_object_mut() {
+                    source.remove("boundary");
+                    if !search.with_geometry {
+                        source.remove("geometry");
+                    }
+                }
+                let explanation = if search.explain {
+                    Some(serde_json::json!({
+                        "value": score,
+                        "description": "score computed by the memory backend",
+                        "details": [],
+                    }))
+                } else {
+                    None
+                };
+                get_doc_type(Some(&source), None)
+                    .map(|doc_type| (doc_type, Some(Box::new(source)), explanation))
+            });
+        Ok(make_places(hits, search.coord.as_ref()))
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn create_index(&self, name: &str, _index_settings: &IndexSettings) -> Result<(), Error> {
+        debug!("creating index {}", name);
+        let mut indexes = self.write();
+        if indexes.documents.contains_key(name) {
+            bail!("Error while creating new index {}: it already exists", name);
+        }
+        indexes.documents.insert(name.to_string(), BTreeMap::new());
+        Ok(())
+    }
+
+    fn delete_index(&mut self, index: &str) -> Result<(), Error> {
+        debug!("deleting index {}", index);
+        let mut indexes = self.write();
+        let deleted = indexes.resolve(index);
+        // like elasticsearch, only a missing index is an error, not an empty pattern
+        if deleted.is_empty() && index != "_all" && !index.contains('*') {
+            bail!("Error deleting index {}", index);
+        }
+        for name in &deleted {
+            indexes.documents.remove(name);
+        }
+        // an alias is removed with its last index
+        let aliases = std::mem::replace(&mut indexes.aliases, BTreeMap::new());
+        indexes.aliases = aliases
+            .into_iter()
+            .map(|(alias, aliased)| (alias, &aliased - &deleted))
+            .filter(|(_, aliased)| !aliased.is_empty())
+            .collect();
+        Ok(())
+    }
+
+    fn keep_previous(&self) -> usize {
+        self.keep_previous
+    }
+
+    fn quality_gate(&self) -> Option<&QualityGate> {
+        self.quality_gate.as_ref()
+    }
+
+    /// the documents are visible as soon as they are indexed
+    fn refresh_index(&mut self, _index: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn count_documents(&self, index: &str) -> Result<u64, Error> {
+        let indexes = self.read();
+        Ok(indexes
+            .resolve(index)
+            .iter()
+            .filter_map(|name| indexes.documents.get(name))
+            .map(|documents| documents.len() as u64)
+            .sum())
+    }
+
+    fn get_all_aliased_index(
+        &self,
+        base_index: &str,
+    ) -> Result<BTreeMap<String, Vec<String>>, Error> {
+        let indexes = self.read();
+        Ok(indexes
+            .documents
+            .keys()
+            .filter(|index| index.starts_with(base_index))
+            .map(|index| {
+                let aliases = indexes
+                    .aliases
+                    .iter()
+                    .filter(|(_, aliased)| aliased.contains(index))
+                    .map(|(alias, _)| alias.clone())
+                    .collect();
+                (index.clone(), aliases)
+            })
+            .collect())
+    }
+
+    fn alias(&self, alias: &str, add: &[String], remove: &[String]) -> Result<(), Error> {
+        info!(
+            "for {}, adding alias {:?}, removing {:?}",
+            alias, add, remove
+        );
+        self.write().alias(alias, add, remove)
+    }
+
+    /// the aliases are restored if an operation fails
+    fn update_aliases(&self, actions: Vec<AliasOperation>) -> Result<(), Error> {
+        let mut indexes = self.write();
+        let previous = indexes.aliases.clone();
+        for action in actions {
+            let result = match (action.add, action.remove) {
+                (Some(add), _) => indexes.alias(&add.alias, &[add.index], &[]),
+                (None, Some(remove)) => indexes.alias(&remove.alias, &[], &[remove.index]),
+                (None, None) => Ok(()),
+            };
+            if let Err(e) = result {
+                indexes.aliases = previous;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn bulk_index<T, I>(&mut self, index: &TypedIndex<T>, iter: I) -> Result<usize, EsError>
+    where
+        T: MimirObject + std::marker::Send + 'static,
+        I: Iterator<Item = T>,
+    {
+        let mut indexes = self.write();
+        let Indexes {
+            documents, next_id, ..
+        } = &mut *indexes;
+        // like elasticsearch, the index is created if needed
+        let documents = documents.entry(index.name.clone()).or_default();
+        let mut nb = 0;
+        for object in iter {
+            let id = object.es_id().unwrap_or_else(|| {
+                *next_id += 1;
+                next_id.to_string()
+            });
+            let document = serde_json::to_value(Document {
+                doc_type: T::doc_type(),
+                object,
+            })
+            .map_err(|e| EsError::EsError(format!("impossible to index {}: {}", id, e)))?;
+            documents.insert(id, document);
+            nb += 1;
+        }
+        Ok(nb)
+    }
+
+    fn delete_documents<T>(
+        &mut self,
+        index: &TypedIndex<T>,
+        ids: &[String],
+    ) -> Result<usize, EsError>
+    where
+        T: MimirObject,
+    {
+        let mut indexes = self.write();
+        let documents = indexes
+            .documents
+            .get_mut(&index.name)
+            .ok_or_else(|| EsError::EsError(format!("no such index: {}", index.name)))?;
+        Ok(ids
+            .iter()
+            .filter(|id| documents.remove(id.as_str()).is_some())
+            .count())
+    }
+
+    fn get_all_objects_from_index<T>(&mut self, index: &str) -> Result<Vec<T>, EsError>
+    where
+        for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug,
+    {
+        let indexes = self.read();
+        let names = indexes.resolve(index);
+        if names.is_empty() {
+            return Err(EsError::EsError(format!("no such index: {}", index)));
+        }
+        names
+            .iter()
+            .filter_map(|name| indexes.documents.get(name))
+            .flat_map(|documents| documents.values())
+            .map(|document| {
+                serde_json::from_value(document.clone())
+                    .map_err(|e| EsError::EsError(format!("invalid document in {}: {}", index, e)))
+            })
+            .collect()
+    }
+
+    fn get_objects_by_query<T>(&mut self, index: &str, query: Query) -> Result<Vec<T>, EsError>
+    where
+        for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug,
+    {
+        let query = serde_json::to_value(&query)
+            .map_err(|e| EsError::EsError(format!("invalid query: {}", e)))?;
+        let indexes = self.read();
+        indexes
+            .resolve(index)
+            .iter()
+            .filter_map(|name| indexes.documents.get(name))
+            .flat_map(|documents| documents.iter())
+            .filter(|&(id, source)| score(&query, &Hit { id, source }).is_some())
+            .map(|(_, document)| {
+                serde_json::from_value(document.clone())
+                    .map_err(|e| EsError::EsError(format!("invalid document in {}: {}", index, e)))
+            })
+            .collect()
+    }
+
+    /// all the objects are in a single page
+    fn for_each_page<T, F>(
+        &mut self,
+        index: &str,
+        query: Option<Query>,
+        mut f: F,
+    ) -> Result<(), Error>
+    where
+        for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug,
+        F: FnMut(&mut Self, Vec<T>) -> Result<(), Error>,
+    {
+        let objects = match query {
+            Some(query) => self.get_objects_by_query(index, query)?,
+            None => self.get_all_objects_from_index(index)?,
+        };
+        f(self, objects)
+    }
+
+    /// all the fields are searched with their exact values
+    fn is_exact_field(&self, _index: &str, _field: &str) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    fn search_places(&self, search: SearchRequest) -> PlacesFuture {
+        Box::new(future::result(self.search(&search)))
+    }
+
+    fn multi_search(&self, searches: Vec<SearchRequest>) -> MultiSearchFuture {
+        Box::new(future::ok(
+            searches.iter().map(|search| self.search(search)).collect(),
+        ))
+    }
+
+    fn get_address(&mut self, coord: &Coord) -> Result<Vec<Place>, EsError> {
+        self.search(&build_address_search(coord))
+    }
+
+    /// there is no timeout, the searches do not wait for anything
+    fn with_timeout(&self, _timeout: Option<time::Duration>) -> Arc<dyn Backend> {
+        Arc::new(self.clone())
+    }
+}
+
+/// match a name on a pattern where '*' can be anything
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+    if name.len() < first.len() + last.len() || !name.starts_with(first) || !name.ends_with(last) {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// An indexed document
+struct Hit<'a> {
+    id: &'a str,
+    source: &'a Value,
+}
+
+/// the score of a document for an elasticsearch query,
+/// `None` if the document does not match the query
+fn score(query: &Value, hit: &Hit<'_>) -> Option<f64> {
+    let (kind, params) = match query.as_object().and_then(|query| query.iter().next()) {
+        Some(query) => query,
+        // an empty query matches everything
+        None => return Some(1.),
+    };
+    match kind.as_str() {
+        "match_all" => Some(boost(params)),
+        "bool" => score_bool(params, hit),
+        "function_score" => score_function_score(params, hit),
+        "term" => {
+            let (field, value) = field_param(params)?;
+            let expected = value.get("value").unwrap_or(value);
+            if field_values(hit.source, field)
+                .iter()
+                .any(|v| same_value(v, expected))
+            {
+                Some(boost(value))
+            } else {
+                None
+            }
+        }
+        "terms" => {
+            let (field, values) = field_param(params)?;
+            let values = values.as_array()?;
+            if field_values(hit.source, field)
+                .iter()
+                .any(|v| values.iter().any(|expected| same_value(v, expected)))
+            {
+                Some(boost(params))
+            } else {
+                None
+            }
+        }
+        "ids" => {
+            let ids = params.get("values").and_then(Value::as_array)?;
+            if ids.iter().any(|id| id.as_str() == Some(hit.id)) {
+                Some(boost(params))
+            } else {
+                None
+            }
+        }
+        "exists" => {
+            let field = params.get("field").and_then(Value::as_str)?;
+            if field_values(hit.source, field).is_empty() {
+                None
+            } else {
+                Some(boost(params))
+            }
+        }
+        "match" => {
+            let (field, options) = field_param(params)?;
+            let text = options.get("query").unwrap_or(options);
+            score_text(field, &value_to_string(text)?, options, hit).map(|s| s * boost(options))
+        }
+        "multi_match" => {
+            let text = value_to_string(params.get("query")?)?;
+            params
+                .get("fields")
+                .and_then(Value::as_array)?
+                .iter()
+                .filter_map(Value::as_str)
+                .filter_map(|field| score_text(field, &text, params, hit))
+                .fold(None, |max: Option<f64>, s| {
+                    Some(max.map_or(s, |max| max.max(s)))
+                })
+                .map(|s| s * boost(params))
+        }
+        "geo_distance" => {
+            let max_distance = params.get("distance").and_then(parse_distance)?;
+            let (field, origin) = params
+                .as_object()?
+                .iter()
+                .find(|(k, _)| !GEO_DISTANCE_OPTIONS.contains(&k.as_str()))?;
+            let origin = parse_location(origin)?;
+            let coord = field_values(hit.source, field)
+                .into_iter()
+                .find_map(parse_location)?;
+            if distance(&origin, &coord) <= max_distance {
+                Some(boost(params))
+            } else {
+                None
+            }
+        }
+        "geo_shape" => {
+            let (field, options) = field_param(params)?;
+            let shape = parse_shape(options.get("shape")?)?;
+            if field_values(hit.source, field)
+                .into_iter()
+                .filter_map(parse_shape)
+                .any(|document_shape| document_shape.intersects(&shape))
+            {
+                Some(boost(params))
+            } else {
+                None
+            }
+        }
+        _ => {
+            debug!("the query {} is not handled by the memory backend", kind);
+            None
+        }
+    }
+}
+
+const GEO_DISTANCE_OPTIONS: [&str; 6] = [
+    "distance",
+    "distance_type",
+    "optimize_bbox",
+    "validation_method",
+    "boost",
+    "_name",
+];
+
+fn boost(params: &Value) -> f64 {
+    params.get("boost").and_then(Value::as_f64).unwrap_or(1.)
+}
+
+/// the clauses of a bool query, given as one query or as an array
+fn clauses(clauses: Option<&Value>) -> Vec<&Value> {
+    match clauses {
+        Some(Value::Array(clauses)) => clauses.iter().collect(),
+        Some(clause) => vec![clause],
+        None => vec![],
+    }
+}
+
+/// the field of the queries written as `{"field": params}`
+fn field_param(params: &Value) -> Option<(&String, &Value)> {
+    params
+        .as_object()?
+        .iter()
+        .find(|(k, _)| k.as_str() != "boost" && !k.starts_with('_'))
+}
+
+fn score_bool(params: &Value, hit: &Hit<'_>) -> Option<f64> {
+    let must = clauses(params.get("must"));
+    let filter = clauses(params.get("filter"));
+    let should = clauses(params.get("should"));
+
+    let mut total = 0.;
+    for query in &must {
+        total += score(query, hit)?;
+    }
+    for query in &filter {
+        score(query, hit)?;
+    }
+    if clauses(params.get("must_not"))
+        .iter()
+        .any(|query| score(query, hit).is_some())
+    {
+        return None;
+    }
+
+    let should_scores: Vec<f64> = should.iter().filter_map(|q| score(q, hit)).collect();
+    let required = match params.get("minimum_should_match") {
+        Some(spec) => minimum_should_match(spec, should.len()),
+        // without must or filter clauses, at least one should clause must match
+        None if must.is_empty() && filter.is_empty() && !should.is_empty() => 1,
+        None => 0,
+    };
+    if should_scores.len() < required {
+        return None;
+    }
+    total += should_scores.iter().sum::<f64>();
+    Some(total * boost(params))
+}
+
+fn score_function_score(params: &Value, hit: &Hit<'_>) -> Option<f64> {
+    let query_score = match params.get("query") {
+        Some(query) => score(query, hit)?,
+        None => 1.,
+    };
+    let values: Vec<f64> = clauses(params.get("functions"))
+        .into_iter()
+        .filter(|function| {
+            function
+                .get("filter")
+                .map_or(true, |filter| score(filter, hit).is_some())
+        })
+        .map(|function| function_value(function, hit))
+        .collect();
+    let function_score = if values.is_empty() {
+        1.
+    } else {
+        match params.get("score_mode").and_then(Value::as_str) {
+            Some("sum") => values.iter().sum(),
+            Some("avg") => values.iter().sum::<f64>() / values.len() as f64,
+            Some("max") => values.iter().cloned().fold(std::f64::MIN, f64::max),
+            Some("min") => values.iter().cloned().fold(std::f64::MAX, f64::min),
+            Some("first") => values[0],
+            _ => values.iter().product(),
+        }
+    };
+    let total = match params.get("boost_mode").and_then(Value::as_str) {
+        Some("replace") => function_score,
+        Some("sum") => query_score + function_score,
+        Some("avg") => (query_score + function_score) / 2.,
+        Some("max") => query_score.max(function_score),
+        Some("min") => query_score.min(function_score),
+        _ => query_score * function_score,
+    };
+    Some(total * boost(params))
+}
+
+fn function_value(function: &Value, hit: &Hit<'_>) -> f64 {
+    let value = if let Some(factor) = function.get("field_value_factor") {
+        Some(field_value_factor(factor, hit))
+    } else {
+        ["exp", "gauss", "linear"]
+            .iter()
+            .find_map(|kind| function.get(*kind).map(|decay| (*kind, decay)))
+            .map(|(kind, decay)| decay_value(kind, decay, hit))
+    };
+    let weight = function.get("weight").and_then(Value::as_f64);
+    value.unwrap_or(1.) * weight.unwrap_or(1.)
+}
+
+fn field_value_factor(params: &Value, hit: &Hit<'_>) -> f64 {
+    let value = params
+        .get("field")
+        .and_then(Value::as_str)
+        .and_then(|field| field_values(hit.source, field).into_iter().next())
+        .and_then(Value::as_f64)
+        .or_else(|| params.get("missing").and_then(Value::as_f64))
+        .unwrap_or(1.);
+    let value = value * params.get("factor").and_then(Value::as_f64).unwrap_or(1.);
+    match params.get("modifier").and_then(Value::as_str) {
+        Some("log") => value.log10(),
+        Some("log1p") => (1. + value).log10(),
+        Some("log2p") => (2. + value).log10(),
+        Some("ln") => value.ln(),
+        Some("ln1p") => value.ln_1p(),
+        Some("ln2p") => (2. + value).ln(),
+        Some("square") => value * value,
+        Some("sqrt") => value.sqrt(),
+        Some("reciprocal") => 1. / value,
+        _ => value,
+    }
+}
+
+fn decay_value(kind: &str, params: &Value, hit: &Hit<'_>) -> f64 {
+    let compute = || {
+        let (field, params) = field_param(params)?;
+        let origin = parse_location(params.get("origin")?)?;
+        let scale = parse_distance(params.get("scale")?)?;
+        let offset = params.get("offset").and_then(parse_distance).unwrap_or(0.);
+        let decay = params.get("decay").and_then(Value::as_f64).unwrap_or(0.5);
+        let coord = field_values(hit.source, field)
+            .into_iter()
+            .find_map(parse_location)?;
+        let d = (distance(&origin, &coord) - offset).max(0.);
+        Some(match kind {
+            "gauss" => (decay.ln() * d * d / (scale * scale)).exp(),
+            "linear" => (1. - (1. - decay) * d / scale).max(0.),
+            _ => (decay.ln() * d / scale).exp(),
+        })
+    };
+    // like in elasticsearch, the documents without the field are not penalized
+    compute().unwrap_or(1.)
+}
+
+/// The matching of the tokens of a text field
+enum MatchMode {
+    /// the `.prefix` sub fields
+    Prefix,
+    /// the `.ngram` sub fields
+    Fuzzy,
+    Exact,
+}
+
+/// the score (between 0 and 1) of the text fields `field` for `text`
+fn score_text(field: &str, text: &str, options: &Value, hit: &Hit<'_>) -> Option<f64> {
+    // the multi_match fields can be boosted with '^'
+    let field = field.split('^').next().unwrap_or(field);
+    let (field, mode) = if field.ends_with(".prefix") {
+        (&field[..field.len() - ".prefix".len()], MatchMode::Prefix)
+    } else if field.ends_with(".ngram") {
+        (&field[..field.len() - ".ngram".len()], MatchMode::Fuzzy)
+    } else {
+        (field, MatchMode::Exact)
+    };
+
+    let mut texts = vec![];
+    // full_label is filled by the `copy_to` of the templates
+    let fields = if field == "full_label" {
+        vec!["label", "labels", "name", "zip_codes"]
+    } else {
+        vec![field]
+    };
+    for field in fields {
+        for value in field_values(hit.source, field) {
+            collect_strings(value, &mut texts);
+        }
+    }
+    let document_tokens: BTreeSet<String> = texts.iter().flat_map(|t| tokenize(t)).collect();
+
+    let query_tokens = tokenize(text);
+    if query_tokens.is_empty() {
+        return None;
+    }
+    let nb_matched = query_tokens
+        .iter()
+        .filter(|token| {
+            document_tokens.iter().any(|document_token| match mode {
+                MatchMode::Prefix => document_token.starts_with(token.as_str()),
+                MatchMode::Fuzzy => fuzzy_matches(token, document_token),
+                MatchMode::Exact => document_token == *token,
+            })
+        })
+        .count();
+
+    let required = if options.get("operator").and_then(Value::as_str) == Some("and") {
+        query_tokens.len()
+    } else {
+        options
+            .get("minimum_should_match")
+            .map_or(1, |spec| minimum_should_match(spec, query_tokens.len()))
+            .max(1)
+    };
+    if nb_matched < required {
+        None
+    } else {
+        Some(nb_matched as f64 / query_tokens.len() as f64)
+    }
+}
+
+/// an approximation of the ngram matching:
+/// the tokens share at least half of the trigrams of the query token
+fn fuzzy_matches(query_token: &str, document_token: &str) -> bool {
+    fn trigrams(token: &str) -> BTreeSet<String> {
+        let chars: Vec<char> = token.chars().collect();
+        chars.windows(3).map(|w| w.iter().collect()).collect()
+    }
+    if document_token.starts_with(query_token) {
+        return true;
+    }
+    let query_trigrams = trigrams(query_token);
+    if query_trigrams.is_empty() {
+        return false;
+    }
+    let document_trigrams = trigrams(document_token);
+    let nb_common = query_trigrams.intersection(&document_trigrams).count();
+    nb_common * 2 >= query_trigrams.len()
+}
+
+/// the number of optional clauses that must match,
+/// cf https://www.elastic.co/guide/en/elasticsearch/reference/2.4/query-dsl-minimum-should-match.html
+fn minimum_should_match(spec: &Value, nb_clauses: usize) -> usize {
+    fn simple(spec: &str, nb_clauses: usize) -> Option<usize> {
+        let nb = nb_clauses as i64;
+        let required = if spec.ends_with('%') {
+            let percent: i64 = spec[..spec.len() - 1].parse().ok()?;
+            if percent < 0 {
+                nb - nb * -percent / 100
+            } else {
+                nb * percent / 100
+            }
+        } else {
+            let value: i64 = spec.parse().ok()?;
+            if value < 0 {
+                nb + value
+            } else {
+                value
+            }
+        };
+        Some(required.max(0).min(nb) as usize)
+    }
+
+    let spec = match spec {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        _ => return 1,
+    };
+    if !spec.contains('<') {
+        return simple(spec.trim(), nb_clauses).unwrap_or(1);
+    }
+    // combinations like "3<-2 9<25%": all the clauses are required below the first bound
+    let mut required = nb_clauses;
+    for combination in spec.split_whitespace() {
+        let mut parts = combination.splitn(2, '<');
+        let bound = parts.next().and_then(|b| b.parse::<usize>().ok());
+        let value = parts.next().and_then(|v| simple(v, nb_clauses));
+        match (bound, value) {
+            (Some(bound), Some(value)) if nb_clauses > bound => required = value,
+            _ => (),
+        }
+    }
+    required
+}
+
+/// the values of a field of a document, the field can be a path in the sub objects
+/// (like `administrative_regions.name`), the arrays are flattened
+fn field_values<'a>(value: &'a Value, field: &str) -> Vec<&'a Value> {
+    fn collect<'a>(value: &'a Value, path: &[&str], values: &mut Vec<&'a Value>) {
+        match value {
+            Value::Null => (),
+            Value::Array(array) => array.iter().for_each(|v| collect(v, path, values)),
+            _ => match path.split_first() {
+                None => values.push(value),
+                Some((first, rest)) => {
+                    if let Some(v) = value.get(first) {
+                        collect(v, rest, values)
+                    }
+                }
+            },
+        }
+    }
+    let path: Vec<&str> = field.split('.').collect();
+    let mut values = vec![];
+    collect(value, &path, &mut values);
+    values
+}
+
+/// all the strings of a value (and of its sub objects)
+fn collect_strings(value: &Value, strings: &mut Vec<String>) {
+    match value {
+        Value::Array(array) => array.iter().for_each(|v| collect_strings(v, strings)),
+        Value::Object(object) => object.values().for_each(|v| collect_strings(v, strings)),
+        Value::Null => (),
+        _ => strings.extend(value_to_string(value)),
+    }
+}
+
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn same_value(value: &Value, expected: &Value) -> bool {
+    match (value.as_f64(), expected.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => value_to_string(value).map_or(false, |v| Some(v) == value_to_string(expected)),
+    }
+}
+
+/// lowercase, ascii folded words of a text
+fn tokenize(text: &str) -> Vec<String> {
+    text.chars()
+        .flat_map(char::to_lowercase)
+        .map(fold)
+        .collect::<String>()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn fold(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+        'ç' => 'c',
+        'è' | 'é' | 'ê' | 'ë' => 'e',
+        'ì' | 'í' | 'î' | 'ï' => 'i',
+        'ñ' => 'n',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' => 'o',
+        'ù' | 'ú' | 'û' | 'ü' => 'u',
+        'ý' | 'ÿ' => 'y',
+        _ => c,
+    }
+}
+
+/// a distance in meters, like "130km" or "1000m"
+fn parse_distance(value: &Value) -> Option<f64> {
+    let distance = match value {
+        Value::Number(n) => return n.as_f64(),
+        Value::String(s) => s.trim(),
+        _ => return None,
+    };
+    let split = distance
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or_else(|| distance.len());
+    let amount: f64 = distance[..split].parse().ok()?;
+    let factor = match distance[split..].trim() {
+        "" | "m" | "meters" => 1.,
+        "km" | "kilometers" => 1000.,
+        "cm" | "centimeters" => 0.01,
+        "mm" | "millimeters" => 0.001,
+        "mi" | "miles" => 1609.344,
+        "yd" | "yards" => 0.9144,
+        "ft" | "feet" => 0.3048,
+        "in" | "inch" => 0.0254,
+        "NM" | "nmi" | "nauticalmiles" => 1852.,
+        _ => return None,
+    };
+    Some(amount * factor)
+}
+
+/// a location given as `{"lat": .., "lon": ..}`, `[lon, lat]`, "lat,lon" or a geojson point
+fn parse_location(value: &Value) -> Option<Coord> {
+    match value {
+        Value::Object(object) => match (object.get("lat"), object.get("lon")) {
+            (Some(lat), Some(lon)) => Some(Coord::new(lon.as_f64()?, lat.as_f64()?)),
+            _ => object.get("coordinates").and_then(parse_location),
+        },
+        Value::Array(array) if array.len() == 2 => {
+            Some(Coord::new(array[0].as_f64()?, array[1].as_f64()?))
+        }
+        Value::String(s) => {
+            let mut parts = s.split(',').map(|part| part.trim().parse::<f64>());
+            match (parts.next(), parts.next()) {
+                (Some(Ok(lat)), Some(Ok(lon))) => Some(Coord::new(lon, lat)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// distance in meters
+fn distance(a: &Coord, b: &Coord) -> f64 {
+    let a: geo_types::Point<f64> = a.0.into();
+    a.haversine_distance(&b.0.into())
+}
+
+/// The geometries handled by the `geo_shape` queries
+enum Shape {
+    Point(geo_types::Point<f64>),
+    Lines(geo_types::MultiLineString<f64>),
+    Polygons(geo_types::MultiPolygon<f64>),
+}
+
+impl Shape {
+    /// Note: two polygons intersect if a vertex of one is in the other,
+    /// which is enough for the boundaries of the admins,
+    /// and a point is never on a line
+    fn intersects(&self, other: &Shape) -> bool {
+        match (self, other) {
+            (Shape::Point(a), Shape::Point(b)) => a == b,
+            (Shape::Point(_), Shape::Lines(_)) | (Shape::Lines(_), Shape::Point(_)) => false,
+            (Shape::Lines(a), Shape::Lines(b)) => {
+                segments(a).any(|s| segments(b).any(|other| segments_intersect(&s, &other)))
+            }
+            (Shape::Lines(lines), Shape::Polygons(polygons))
+            | (Shape::Polygons(polygons), Shape::Lines(lines)) => {
+                let edges: Vec<_> = polygons
+                    .0
+                    .iter()
+                    .flat_map(|polygon| polygon.exterior().lines())
+                    .collect();
+                lines
+                    .0
+                    .iter()
+                    .flat_map(|line| line.points_iter())
+                    .any(|p| polygons.contains(&p))
+                    || segments(lines).any(|s| edges.iter().any(|e| segments_intersect(&s, e)))
+            }
+            (Shape::Point(p), Shape::Polygons(polygons))
+            | (Shape::Polygons(polygons), Shape::Point(p)) => polygons.contains(p),
+            (Shape::Polygons(a), Shape::Polygons(b)) => {
+                let has_vertex_in =
+                    |a: &geo_types::MultiPolygon<f64>, b: &geo_types::MultiPolygon<f64>| {
+                        a.0.iter()
+                            .flat_map(|polygon| polygon.exterior().points_iter())
+                            .any(|p| b.contains(&p))
+                    };
+                has_vertex_in(a, b) || has_vertex_in(b, a)
+            }
+        }
+    }
+}
+
+fn segments<'a>(
+    lines: &'a geo_types::MultiLineString<f64>,
+) -> impl Iterator<Item = geo_types::Line<f64>> + 'a {
+    lines.0.iter().flat_map(|line| line.lines())
+}
+
+/// true if the two segments cross (or touch)
+fn segments_intersect(a: &geo_types::Line<f64>, b: &geo_types::Line<f64>) -> bool {
+    let orientation = |p: geo_types::Coordinate<f64>,
+                       q: geo_types::Coordinate<f64>,
+                       r: geo_types::Coordinate<f64>| {
+        let cross = (q.x - p.x) * (r.y - p.y) - (q.y - p.y) * (r.x - p.x);
+        if cross > 0. {
+            1
+        } else if cross < 0. {
+            -1
+        } else {
+            0
+        }
+    };
+    let on_segment = |p: geo_types::Coordinate<f64>, l: &geo_types::Line<f64>| {
+        p.x >= l.start.x.min(l.end.x)
+            && p.x <= l.start.x.max(l.end.x)
+            && p.y >= l.start.y.min(l.end.y)
+            && p.y <= l.start.y.max(l.end.y)
+    };
+    let (o1, o2) = (
+        orientation(a.start, a.end, b.start),
+        orientation(a.start, a.end, b.end),
+    );
+    let (o3, o4) = (
+        orientation(b.start, b.end, a.start),
+        orientation(b.start, b.end, a.end),
+    );
+    (o1 != o2 && o3 != o4)
+        || (o1 == 0 && on_segment(b.start, a))
+        || (o2 == 0 && on_segment(b.end, a))
+        || (o3 == 0 && on_segment(a.start, b))
+        || (o4 == 0 && on_segment(a.end, b))
+}
+
+fn parse_shape(value: &Value) -> Option<Shape> {
+    let geometry: geojson::Geometry = serde_json::from_value(value.clone()).ok()?;
+    match geometry.value {
+        geojson::Value::Point(point) if point.len() >= 2 => {
+            Some(Shape::Point(geo_types::Point::new(point[0], point[1])))
+        }
+        line @ geojson::Value::LineString(_) => {
+            let line: geo_types::LineString<f64> = line.try_into().ok()?;
+            Some(Shape::Lines(geo_types::MultiLineString(vec![line])))
+        }
+        lines @ geojson::Value::MultiLineString(_) => Some(Shape::Lines(lines.try_into().ok()?)),
+        polygon @ geojson::Value::Polygon(_) => {
+            let polygon: geo_types::Polygon<f64> = polygon.try_into().ok()?;
+            Some(Shape::Polygons(geo_types::MultiPolygon(vec![polygon])))
+        }
+        multi_polygon @ geojson::Value::MultiPolygon(_) => {
+            Some(Shape::Polygons(multi_polygon.try_into().ok()?))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Admin, Stop};
+    use rs_es::query::Query;
+    use rs_es::units as rs_u;
+
+    fn search(backend: &MemoryBackend, indexes: &[&str], query: Query) -> Vec<Place> {
+        backend
+            .search(&SearchRequest {
+                indexes: indexes.iter().map(|i| i.to_string()).collect(),
+                query,
+                from: 0,
+                size: 10,
+                coord: None,
+                explain: false,
+                with_geometry: false,
+            })
+            .unwrap()
+    }
+
+    fn ids(places: &[Place]) -> Vec<String> {
+        places
+            .iter()
+            .map(|place| match place {
+                Place::Admin(admin) => admin.id.clone(),
+                Place::Stop(stop) => stop.id.clone(),
+                Place::Poi(poi) => poi.id.clone(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    fn make_backend() -> MemoryBackend {
+        let mut backend = MemoryBackend::new();
+        let settings = IndexSettings {
+            nb_shards: 1,
+            nb_replicas: 0,
+        };
+        let admin = |id: &str, label: &str, lon, lat| Admin {
+            id: id.to_string(),
+            label: label.to_string(),
+            name: label.to_string(),
+            coord: Coord::new(lon, lat),
+            ..Default::default()
+        };
+        let admins = vec![
+            admin("admin:paris", "Paris", 2.35, 48.85),
+            admin("admin:pantin", "Pantin", 2.40, 48.89),
+            admin("admin:lyon", "Lyon", 4.83, 45.76),
+        ];
+        backend
+            .public_index("fr", &settings, admins.into_iter())
+            .unwrap();
+        let stops = vec![Stop {
+            id: "stop_area:gare".to_string(),
+            label: "Gare de Lyon (Paris)".to_string(),
+            coord: Coord::new(2.37, 48.84),
+            coverages: vec!["idf".to_string()],
+            ..Default::default()
+        }];
+        backend
+            .public_index("idf", &settings, stops.into_iter())
+            .unwrap();
+        backend
+    }
+
+    #[test]
+    fn test_prefix_on_label() {
+        let backend = make_backend();
+        let query = |q: &str| {
+            Query::build_match("label.prefix", q)
+                .with_operator("and")
+                .build()
+        };
+        assert_eq!(
+            ids(&search(&backend, &["munin_admin"], query("pa"))),
+            vec!["admin:pantin", "admin:paris"]
+        );
+        assert_eq!(
+            ids(&search(&backend, &["munin"], query("gare lyo"))),
+            vec!["stop_area:gare"]
+        );
+        assert!(search(&backend, &["munin"], query("pax")).is_empty());
+    }
+
+    #[test]
+    fn test_type_and_dataset_filter() {
+        let backend = make_backend();
+        let lyon = || Query::build_match("label", "lyon").build();
+        assert_eq!(
+            ids(&search(&backend, &["munin"], lyon())).len(),
+            2,
+            "the admin and the stop should be found"
+        );
+        let only_stops = Query::build_bool()
+            .with_must(lyon())
+            .with_filter(Query::build_term("type", Stop::doc_type()).build())
+            .build();
+        assert_eq!(
+            ids(&search(&backend, &["munin"], only_stops)),
+            vec!["stop_area:gare"]
+        );
+        // the stops of the dataset are only in their own index
+        assert_eq!(
+            ids(&search(&backend, &["munin_stop_idf"], lyon())),
+            vec!["stop_area:gare"]
+        );
+        assert!(search(&backend, &["munin_stop_other"], lyon()).is_empty());
+        let datasets: &[&str] = &["idf"];
+        let coverage = Query::build_terms("coverages")
+            .with_values(datasets)
+            .build();
+        assert_eq!(
+            ids(&search(&backend, &["munin_*"], coverage)),
+            vec!["stop_area:gare"]
+        );
+        assert!(search(&backend, &["munin_poi"], lyon()).is_empty());
+    }
+
+    #[test]
+    fn test_geo_distance() {
+        let backend = make_backend();
+        let near = |meters| {
+            Query::build_geo_distance(
+                "coord",
+                (48.85, 2.35),
+                rs_u::Distance::new(meters, rs_u::DistanceUnit::Meter),
+            )
+            .build()
+        };
+        assert_eq!(
+            ids(&search(&backend, &["munin_admin"], near(100.))),
+            vec!["admin:paris"]
+        );
+        assert_eq!(
+            ids(&search(&backend, &["munin_admin"], near(10_000.))),
+            vec!["admin:pantin", "admin:paris"]
+        );
+        let nearest_first = Query::build_bool()
+            .with_should(crate::rubber::build_proximity_with_boost(
+                &Coord::new(2.35, 48.85),
+                1.,
+            ))
+            .with_filter(near(10_000.))
+            .build();
+        assert_eq!(
+            ids(&search(&backend, &["munin"], nearest_first)),
+            vec!["admin:paris", "stop_area:gare", "admin:pantin"]
+        );
+    }
+
+    #[test]
+    fn test_publish_replaces_index() {
+        let mut backend = make_backend();
+        let settings = IndexSettings {
+            nb_shards: 1,
+            nb_replicas: 0,
+        };
+        let admins = vec![Admin {
+            id: "admin:marseille".to_string(),
+            label: "Marseille".to_string(),
+            ..Default::default()
+        }];
+        backend
+            .public_index("fr", &settings, admins.into_iter())
+            .unwrap();
+        let all_admins = backend.get_all_admins().unwrap();
+        assert_eq!(all_admins.len(), 1);
+        assert_eq!(all_admins[0].id, "admin:marseille");
+        assert_eq!(
+            backend
+                .get_all_aliased_index("munin_admin_fr")
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_update_index() {
+        let mut backend = make_backend();
+        let marseille = Admin {
+            id: "admin:marseille".to_string(),
+            label: "Marseille".to_string(),
+            ..Default::default()
+        };
+        let deleted = vec!["admin:lyon".to_string(), "admin:unknown".to_string()];
+        assert_eq!(
+            backend
+                .update_index("fr", std::iter::once(marseille), &deleted)
+                .unwrap(),
+            (1, 1)
+        );
+        let mut all_admins: Vec<String> = backend
+            .get_all_admins()
+            .unwrap()
+            .into_iter()
+            .map(|admin| admin.id)
+            .collect();
+        all_admins.sort();
+        assert_eq!(
+            all_admins,
+            vec!["admin:marseille", "admin:pantin", "admin:paris"]
+        );
+        // only the published datasets can be updated
+        assert!(backend
+            .update_index("other", std::iter::empty::<Admin>(), &[])
+            .is_err());
+    }
+
+    #[test]
+    fn test_get_objects_by_query() {
+        let mut backend = make_backend();
+        let query = Query::build_terms("id")
+            .with_values(vec!["admin:lyon", "admin:paris", "admin:unknown"])
+            .build();
+        let mut admins: Vec<String> = backend
+            .get_objects_by_query::<Admin>("munin_admin", query)
+            .unwrap()
+            .into_iter()
+            .map(|admin| admin.id)
+            .collect();
+        admins.sort();
+        assert_eq!(admins, vec!["admin:lyon", "admin:paris"]);
+    }
+
+    #[test]
+    fn test_keep_previous_and_rollback() {
+        let mut backend = make_backend().with_keep_previous(1);
+        let settings = IndexSettings {
+            nb_shards: 1,
+            nb_replicas: 0,
+        };
+        let publish = |backend: &mut MemoryBackend, id: &str| {
+            let admin = Admin {
+                id: id.to_string(),
+                label: id.to_string(),
+                ..Default::default()
+            };
+            backend
+                .public_index("fr", &settings, std::iter::once(admin))
+                .unwrap();
+        };
+        let admin_ids = |backend: &mut MemoryBackend| -> Vec<String> {
+            backend
+                .get_all_admins()
+                .unwrap()
+                .into_iter()
+                .map(|admin| admin.id)
+                .collect()
+        };
+        publish(&mut backend, "admin:v2");
+        publish(&mut backend, "admin:v3");
+        // the first generation is deleted, the second one is kept without alias
+        let indexes = backend.get_all_aliased_index("munin_admin_fr").unwrap();
+        assert_eq!(indexes.len(), 2);
+        assert!(indexes.values().next().unwrap().is_empty());
+        assert_eq!(admin_ids(&mut backend), vec!["admin:v3"]);
+
+        assert!(backend.rollback_index::<Admin>("fr").unwrap().is_some());
+        assert_eq!(admin_ids(&mut backend), vec!["admin:v2"]);
+        assert!(backend.rollback_index::<Admin>("fr").unwrap().is_none());
+
+        // the rolled back generation is removed by the next publication
+        publish(&mut backend, "admin:v4");
+        assert_eq!(
+            backend
+                .get_all_aliased_index("munin_admin_fr")
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(admin_ids(&mut backend), vec!["admin:v4"]);
+        backend.rollback_index::<Admin>("fr").unwrap();
+        assert_eq!(admin_ids(&mut backend), vec!["admin:v2"]);
+
+        // no alias is moved if one of the operations fails
+        let operation = |index: &str| AliasOperation {
+            add: Some(crate::objects::AliasParameter {
+                index: index.to_string(),
+                alias: "munin_admin_fr".to_string(),
+            }),
+            remove: None,
+        };
+        let published = backend.get_all_aliased_index("munin_admin_fr").unwrap();
+        let v4 = published.keys().last().unwrap();
+        assert!(backend
+            .update_aliases(vec![operation(v4), operation("unknown")])
+            .is_err());
+        assert_eq!(admin_ids(&mut backend), vec!["admin:v2"]);
+    }
+
+    #[test]
+    fn test_minimum_should_match() {
+        let spec = Value::from("1<-1 3<-2 9<-4 20<25%");
+        assert_eq!(minimum_should_match(&spec, 1), 1);
+        assert_eq!(minimum_should_match(&spec, 3), 2);
+        assert_eq!(minimum_should_match(&spec, 5), 3);
+        assert_eq!(minimum_should_match(&spec, 40), 10);
+        assert_eq!(minimum_should_match(&Value::from(2), 3), 2);
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("munin_poi_*", "munin_poi_fr"));
+        assert!(!matches_pattern("munin_poi_*", "munin_addr_fr"));
+        assert!(matches_pattern("munin_*_fr", "munin_addr_fr"));
+        assert!(matches_pattern("munin", "munin"));
+    }
+}
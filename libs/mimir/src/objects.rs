@@ -28,7 +28,7 @@
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
 use cosmogony::ZoneType;
-use geo_types::{Coordinate, MultiPolygon, Rect};
+use geo_types::{Coordinate, MultiLineString, MultiPolygon, Rect};
 use geojson::Geometry;
 use navitia_poi_model;
 use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
@@ -41,7 +41,7 @@ use std::fmt;
 use std::iter::FromIterator;
 use std::rc::Rc;
 use std::sync::Arc;
-use transit_model::objects::Rgb;
+pub use transit_model::objects::Rgb;
 use transit_model_collection::Idx;
 
 pub trait Incr: Clone {
@@ -57,6 +57,9 @@ pub enum Place {
     Addr(Addr),
     Poi(Poi),
     Stop(Stop),
+    StopPoint(StopPoint),
+    PtLine(PtLine),
+    PtNetwork(PtNetwork),
 }
 
 /// Object stored in elastic search
@@ -98,6 +101,24 @@ impl Place {
             _ => false,
         }
     }
+    pub fn is_stop_point(&self) -> bool {
+        match *self {
+            Place::StopPoint(_) => true,
+            _ => false,
+        }
+    }
+    pub fn is_pt_line(&self) -> bool {
+        match *self {
+            Place::PtLine(_) => true,
+            _ => false,
+        }
+    }
+    pub fn is_pt_network(&self) -> bool {
+        match *self {
+            Place::PtNetwork(_) => true,
+            _ => false,
+        }
+    }
     pub fn poi(&self) -> Option<&Poi> {
         match *self {
             Place::Poi(ref poi) => Some(poi),
@@ -105,6 +126,19 @@ impl Place {
         }
     }
 
+    pub fn id(&self) -> &str {
+        match *self {
+            Place::Admin(ref o) => &o.id,
+            Place::Street(ref o) => &o.id,
+            Place::Addr(ref o) => &o.id,
+            Place::Poi(ref o) => &o.id,
+            Place::Stop(ref o) => &o.id,
+            Place::StopPoint(ref o) => &o.id,
+            Place::PtLine(ref o) => &o.id,
+            Place::PtNetwork(ref o) => &o.id,
+        }
+    }
+
     pub fn label(&self) -> &str {
         match *self {
             Place::Admin(ref o) => o.label(),
@@ -112,6 +146,9 @@ impl Place {
             Place::Addr(ref o) => o.label(),
             Place::Poi(ref o) => o.label(),
             Place::Stop(ref o) => o.label(),
+            Place::StopPoint(ref o) => o.label(),
+            Place::PtLine(ref o) => o.label(),
+            Place::PtNetwork(ref o) => o.label(),
         }
     }
 
@@ -122,16 +159,32 @@ impl Place {
             Place::Addr(ref o) => o.admins(),
             Place::Poi(ref o) => o.admins(),
             Place::Stop(ref o) => o.admins(),
+            Place::StopPoint(ref o) => o.admins(),
+            Place::PtLine(ref o) => o.admins(),
+            Place::PtNetwork(ref o) => o.admins(),
         }
     }
 
+    /// the address of the place, to be embedded in another object (like a poi)
+    ///
+    /// The geometry of the street is not kept, it is large and never used in an address.
     pub fn address(&self) -> Option<Address> {
+        let without_geometry = |street: &Street| Street {
+            geometry: None,
+            ..street.clone()
+        };
         match *self {
             Place::Admin(_) => None,
-            Place::Street(ref o) => Some(Address::Street(o.clone())),
-            Place::Addr(ref o) => Some(Address::Addr(o.clone())),
+            Place::Street(ref o) => Some(Address::Street(without_geometry(o))),
+            Place::Addr(ref o) => Some(Address::Addr(Addr {
+                street: without_geometry(&o.street),
+                ..o.clone()
+            })),
             Place::Poi(_) => None,
             Place::Stop(_) => None,
+            Place::StopPoint(_) => None,
+            Place::PtLine(_) => None,
+            Place::PtNetwork(_) => None,
         }
     }
 
@@ -142,6 +195,9 @@ impl Place {
             Place::Addr(ref o) => o.distance,
             Place::Poi(ref o) => o.distance,
             Place::Stop(ref o) => o.distance,
+            Place::StopPoint(ref o) => o.distance,
+            Place::PtLine(ref o) => o.distance,
+            Place::PtNetwork(ref o) => o.distance,
         }
     }
 
@@ -152,9 +208,23 @@ impl Place {
             Place::Addr(ref mut o) => o.distance = Some(d),
             Place::Poi(ref mut o) => o.distance = Some(d),
             Place::Stop(ref mut o) => o.distance = Some(d),
+            Place::StopPoint(ref mut o) => o.distance = Some(d),
+            Place::PtLine(ref mut o) => o.distance = Some(d),
+            Place::PtNetwork(ref mut o) => o.distance = Some(d),
         }
     }
 
+    /// the distance in meters between `point` and the place
+    /// (for a street, the distance to the nearest point of its geometry)
+    pub fn distance_to(&self, point: &geo_types::Point<f64>) -> u32 {
+        use geo::algorithm::haversine_distance::HaversineDistance;
+        let nearest = match self {
+            Place::Street(ref street) => street.closest_point(point),
+            _ => self.coord().0.into(),
+        };
+        point.haversine_distance(&nearest) as u32
+    }
+
     pub fn coord(&self) -> &Coord {
         match self {
             Place::Admin(ref o) => &o.coord,
@@ -162,6 +232,9 @@ impl Place {
             Place::Addr(ref o) => &o.coord,
             Place::Poi(ref o) => &o.coord,
             Place::Stop(ref o) => &o.coord,
+            Place::StopPoint(ref o) => &o.coord,
+            Place::PtLine(ref o) => &o.coord,
+            Place::PtNetwork(ref o) => &o.coord,
         }
     }
 
@@ -172,6 +245,9 @@ impl Place {
             Place::Addr(ref mut o) => o.context = Some(context),
             Place::Poi(ref mut o) => o.context = Some(context),
             Place::Stop(ref mut o) => o.context = Some(context),
+            Place::StopPoint(ref mut o) => o.context = Some(context),
+            Place::PtLine(ref mut o) => o.context = Some(context),
+            Place::PtNetwork(ref mut o) => o.context = Some(context),
         }
     }
 
@@ -184,6 +260,9 @@ impl Place {
             Place::Addr(ref o) => o.context.clone(),
             Place::Poi(ref o) => o.context.clone(),
             Place::Stop(ref o) => o.context.clone(),
+            Place::StopPoint(ref o) => o.context.clone(),
+            Place::PtLine(ref o) => o.context.clone(),
+            Place::PtNetwork(ref o) => o.context.clone(),
         }
     }
 }
@@ -424,6 +503,154 @@ impl humanesort::HumaneOrder for Line {
     }
 }
 
+/// the mean of the coordinates of some stop areas,
+/// used to locate the lines and the networks
+fn stop_areas_centroid(
+    stop_areas: std::collections::BTreeSet<Idx<transit_model::objects::StopArea>>,
+    navitia: &transit_model::Model,
+) -> Coord {
+    if stop_areas.is_empty() {
+        return Coord::default();
+    }
+    let nb = stop_areas.len() as f64;
+    let (lon, lat) = stop_areas
+        .into_iter()
+        .map(|sa_idx| &navitia.stop_areas[sa_idx].coord)
+        .fold((0., 0.), |(lon, lat), c| (lon + c.lon, lat + c.lat));
+    Coord::new(lon / nb, lat / nb)
+}
+
+/// A public transport line, indexed to be searched by its name or code
+/// (the lines of the stops are described by `Line`)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PtLine {
+    pub id: String,
+    pub label: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub color: Option<Rgb>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub text_color: Option<Rgb>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub commercial_mode: Option<CommercialMode>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub network: Option<Network>,
+    #[serde(default)]
+    pub physical_modes: Vec<PhysicalMode>,
+    /// the centroid of the stop areas of the line
+    pub coord: Coord,
+    pub weight: f64,
+    #[serde(default)]
+    pub coverages: Vec<String>,
+    /// Distance to the coord in query.
+    /// Not serialized as is because it is returned in the `Feature` object
+    #[serde(default, skip)]
+    pub distance: Option<u32>,
+
+    pub context: Option<Context>,
+}
+
+impl FromTransitModel<transit_model::objects::Line> for PtLine {
+    fn from_transit_model(
+        l_idx: Idx<transit_model::objects::Line>,
+        navitia: &transit_model::Model,
+    ) -> Self {
+        let line = Line::from_transit_model(l_idx, navitia);
+        Self {
+            id: line.id,
+            label: line.name.clone(),
+            name: line.name,
+            code: line.code,
+            color: line.color,
+            text_color: line.text_color,
+            commercial_mode: line.commercial_mode,
+            network: line.network,
+            physical_modes: line.physical_modes,
+            coord: stop_areas_centroid(navitia.get_corresponding_from_idx(l_idx), navitia),
+            ..Default::default()
+        }
+    }
+}
+
+impl MimirObject for PtLine {
+    fn is_geo_data() -> bool {
+        false
+    }
+    fn doc_type() -> &'static str {
+        "line"
+    }
+    fn es_id(&self) -> Option<String> {
+        Some(self.id.clone())
+    }
+}
+
+impl Members for PtLine {
+    fn label(&self) -> &str {
+        &self.label
+    }
+    fn admins(&self) -> Vec<Arc<Admin>> {
+        vec![]
+    }
+}
+
+/// A public transport network, indexed to be searched by its name
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PtNetwork {
+    pub id: String,
+    pub label: String,
+    pub name: String,
+    /// the centroid of the stop areas of the network
+    pub coord: Coord,
+    pub weight: f64,
+    #[serde(default)]
+    pub coverages: Vec<String>,
+    /// Distance to the coord in query.
+    /// Not serialized as is because it is returned in the `Feature` object
+    #[serde(default, skip)]
+    pub distance: Option<u32>,
+
+    pub context: Option<Context>,
+}
+
+impl FromTransitModel<transit_model::objects::Network> for PtNetwork {
+    fn from_transit_model(
+        n_idx: Idx<transit_model::objects::Network>,
+        navitia: &transit_model::Model,
+    ) -> Self {
+        let network = &navitia.networks[n_idx];
+        Self {
+            id: normalize_id("network", &network.id),
+            label: network.name.clone(),
+            name: network.name.clone(),
+            coord: stop_areas_centroid(navitia.get_corresponding_from_idx(n_idx), navitia),
+            ..Default::default()
+        }
+    }
+}
+
+impl MimirObject for PtNetwork {
+    fn is_geo_data() -> bool {
+        false
+    }
+    fn doc_type() -> &'static str {
+        "network"
+    }
+    fn es_id(&self) -> Option<String> {
+        Some(self.id.clone())
+    }
+}
+
+impl Members for PtNetwork {
+    fn label(&self) -> &str {
+        &self.label
+    }
+    fn admins(&self) -> Vec<Arc<Admin>> {
+        vec![]
+    }
+}
+
 #[derive(Default, Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct I18nProperties(pub Vec<Property>);
 
@@ -544,6 +771,76 @@ impl Members for Stop {
     }
 }
 
+/// The stop area a stop point belongs to
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ParentStopArea {
+    pub id: String,
+    pub name: String,
+}
+
+/// A stop point (eg. a platform) of a stop area
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StopPoint {
+    pub id: String,
+    pub label: String,
+    pub name: String,
+    /// coord used for some geograhic queries in ES, less precise but  faster than `coord`
+    /// https://www.elastic.co/guide/en/elasticsearch/reference/2.4/geo-shape.html
+    #[serde(skip_deserializing)]
+    pub approx_coord: Option<Geometry>,
+    pub coord: Coord,
+    pub stop_area: ParentStopArea,
+    pub administrative_regions: Vec<Arc<Admin>>,
+    pub weight: f64,
+    pub zip_codes: Vec<String>,
+    #[serde(default)]
+    pub commercial_modes: Vec<CommercialMode>,
+    #[serde(default)]
+    pub physical_modes: Vec<PhysicalMode>,
+    #[serde(default)]
+    pub coverages: Vec<String>,
+    #[serde(default)]
+    pub comments: Vec<Comment>,
+    #[serde(default)]
+    pub timezone: String,
+    #[serde(default)]
+    pub codes: Vec<Code>,
+    #[serde(default)]
+    pub properties: Vec<Property>,
+    #[serde(default)]
+    pub feed_publishers: Vec<FeedPublisher>,
+    /// Distance to the coord in query.
+    /// Not serialized as is because it is returned in the `Feature` object
+    #[serde(default, skip)]
+    pub distance: Option<u32>,
+    #[serde(default)]
+    pub lines: Vec<Line>,
+    #[serde(default)]
+    pub country_codes: Vec<String>,
+
+    pub context: Option<Context>,
+}
+
+impl MimirObject for StopPoint {
+    fn is_geo_data() -> bool {
+        false
+    }
+    fn doc_type() -> &'static str {
+        "stop_point"
+    }
+    fn es_id(&self) -> Option<String> {
+        Some(self.id.clone())
+    }
+}
+impl Members for StopPoint {
+    fn label(&self) -> &str {
+        &self.label
+    }
+    fn admins(&self) -> Vec<Arc<Admin>> {
+        self.administrative_regions.clone()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Admin {
     pub id: String,
@@ -729,6 +1026,14 @@ pub struct Street {
     #[serde(skip_deserializing)]
     pub approx_coord: Option<Geometry>,
     pub coord: Coord,
+    /// geometry of the street (the lines of all the ways merged in the street)
+    #[serde(
+        serialize_with = "custom_multi_line_string_serialize",
+        deserialize_with = "custom_multi_line_string_deserialize",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub geometry: Option<MultiLineString<f64>>,
     pub zip_codes: Vec<String>,
     #[serde(default)]
     pub country_codes: Vec<String>,
@@ -739,6 +1044,98 @@ pub struct Street {
 
     pub context: Option<Context>,
 }
+
+impl Street {
+    /// the point of the street the nearest to `point`
+    /// (the coord of the street if it has no geometry)
+    pub fn closest_point(&self, point: &geo_types::Point<f64>) -> geo_types::Point<f64> {
+        self.geometry
+            .iter()
+            .flat_map(|lines| lines.0.iter())
+            .flat_map(|line| line.lines())
+            .map(|segment| closest_point_on_segment(point, &segment))
+            .min_by(|a, b| {
+                squared_distance(point, a)
+                    .partial_cmp(&squared_distance(point, b))
+                    .unwrap_or(Ordering::Equal)
+            })
+            .unwrap_or_else(|| self.coord.0.into())
+    }
+}
+
+/// squared distance in an equirectangular projection around `origin`,
+/// precise enough to compare distances at the scale of a street
+fn squared_distance(origin: &geo_types::Point<f64>, p: &geo_types::Point<f64>) -> f64 {
+    let dx = (p.x() - origin.x()) * origin.y().to_radians().cos();
+    let dy = p.y() - origin.y();
+    dx * dx + dy * dy
+}
+
+/// the point of the segment the nearest to `point` (in an equirectangular projection)
+fn closest_point_on_segment(
+    point: &geo_types::Point<f64>,
+    segment: &geo_types::Line<f64>,
+) -> geo_types::Point<f64> {
+    let cos_lat = point.y().to_radians().cos();
+    let (ax, ay) = (segment.start.x * cos_lat, segment.start.y);
+    let (bx, by) = (segment.end.x * cos_lat, segment.end.y);
+    let (px, py) = (point.x() * cos_lat, point.y());
+    let length = (bx - ax) * (bx - ax) + (by - ay) * (by - ay);
+    let t = if length == 0. {
+        0.
+    } else {
+        (((px - ax) * (bx - ax) + (py - ay) * (by - ay)) / length)
+            .max(0.)
+            .min(1.)
+    };
+    geo_types::Point::new(
+        segment.start.x + t * (segment.end.x - segment.start.x),
+        segment.start.y + t * (segment.end.y - segment.start.y),
+    )
+}
+
+fn custom_multi_line_string_serialize<S>(
+    multi_line_string_option: &Option<MultiLineString<f64>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    use geojson::{GeoJson, Value};
+
+    match *multi_line_string_option {
+        Some(ref multi_line_string) => {
+            GeoJson::Geometry(Geometry::new(Value::from(multi_line_string))).serialize(serializer)
+        }
+        None => serializer.serialize_none(),
+    }
+}
+
+fn custom_multi_line_string_deserialize<'de, D>(
+    d: D,
+) -> Result<Option<MultiLineString<f64>>, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    use std::convert::TryInto;
+
+    Option::<geojson::GeoJson>::deserialize(d).map(|option| {
+        option.and_then(|geojson| match geojson {
+            geojson::GeoJson::Geometry(geojson_geometry) => {
+                let res: Result<MultiLineString<f64>, _> = geojson_geometry.value.try_into();
+                match res {
+                    Ok(multi_line_string) => Some(multi_line_string),
+                    Err(err) => {
+                        warn!("Cannot deserialize into MultiLineString: {}", err);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        })
+    })
+}
+
 impl Incr for Street {
     fn id(&self) -> &str {
         &self.id
@@ -792,6 +1189,10 @@ pub struct Addr {
     pub distance: Option<u32>,
 
     pub context: Option<Context>,
+    /// true if the position of the address is interpolated along a street
+    /// (and not surveyed)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interpolated: bool,
 }
 
 impl MimirObject for Addr {
@@ -0,0 +1,259 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Checks of the new indexes before their publication
+//!
+//! An import from a truncated or broken input must not replace a good index:
+//! the new index is compared to the published one, and some queries must still
+//! give their expected results in it. If a check fails the index is not published.
+
+use super::backend::Backend;
+use super::objects::MimirObject;
+use super::rubber::SearchRequest;
+use failure::{bail, Error, ResultExt};
+use rs_es::query::compound::BoostMode;
+use rs_es::query::functions::{FilteredFunction, Function};
+use rs_es::query::Query;
+use serde::{Deserialize, Serialize};
+use slog_scope::info;
+use std::fs::File;
+use std::path::Path;
+
+/// A query whose first result must be a given object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmokeQuery {
+    /// type of the objects searched (admin, street, addr, poi or stop),
+    /// the query is only checked on the indexes of this type
+    #[serde(rename = "type")]
+    pub doc_type: String,
+    /// if given, the query is only checked on the indexes of this dataset
+    #[serde(default)]
+    pub dataset: Option<String>,
+    pub query: String,
+    /// id of the expected first result
+    pub id: String,
+}
+
+impl SmokeQuery {
+    /// the search of the query in an index
+    ///
+    /// Note: it is simpler than the bragi autocomplete, the objects matching all the words
+    /// of the query are ranked by their text score and their weight
+    fn search(&self, index: &str) -> SearchRequest {
+        let query = Query::build_function_score()
+            .with_query(
+                Query::build_match("label", self.query.clone())
+                    .with_operator("and")
+                    .build(),
+            )
+            .with_functions(vec![FilteredFunction::build_filtered_function(
+                None,
+                Function::build_field_value_factor("weight")
+                    .with_factor(10.)
+                    .with_missing(0.)
+                    .build(),
+                None,
+            )])
+            .with_boost_mode(BoostMode::Sum)
+            .build();
+        SearchRequest {
+            indexes: vec![index.to_string()],
+            query,
+            from: 0,
+            size: 1,
+            coord: None,
+            explain: false,
+            with_geometry: false,
+        }
+    }
+}
+
+/// The checks done on a new index before its publication (cf `Backend::publish_index`)
+#[derive(Debug, Clone, Default)]
+pub struct QualityGate {
+    /// maximum drop of the number of documents compared to the published index, in percent
+    pub max_drop_percent: Option<f64>,
+    pub smoke_queries: Vec<SmokeQuery>,
+}
+
+impl QualityGate {
+    /// read the smoke queries from a json file (a list of `SmokeQuery`)
+    pub fn read_smoke_queries(path: &Path) -> Result<Vec<SmokeQuery>, Error> {
+        let file = File::open(path).with_context(|_| format!("impossible to open {:?}", path))?;
+        let queries = serde_json::from_reader(file)
+            .with_context(|_| format!("invalid smoke queries in {:?}", path))?;
+        Ok(queries)
+    }
+
+    /// check the new index of the objects `T` of the dataset,
+    /// before it replaces `published`, the currently published index if any
+    pub fn check<T, B>(
+        &self,
+        backend: &B,
+        dataset: &str,
+        index: &str,
+        published: Option<&str>,
+    ) -> Result<(), Error>
+    where
+        T: MimirObject,
+        B: Backend,
+    {
+        if let (Some(max_drop_percent), Some(published)) = (self.max_drop_percent, published) {
+            let nb_published = backend.count_documents(published)?;
+            let nb = backend.count_documents(index)?;
+            if nb_published > 0 && nb < nb_published {
+                let drop_percent = 100. * (nb_published - nb) as f64 / nb_published as f64;
+                if drop_percent > max_drop_percent {
+                    bail!(
+                        "{} has {} documents, {:.1}% less than the published index {}, \
+                         the maximum drop is {}%",
+                        index,
+                        nb,
+                        drop_percent,
+                        published,
+                        max_drop_percent
+                    );
+                }
+            }
+            info!(
+                "{} has {} documents ({} in {})",
+                index, nb, nb_published, published
+            );
+        }
+
+        let smoke_queries = self.smoke_queries.iter().filter(|q| {
+            q.doc_type == T::doc_type() && q.dataset.as_ref().map_or(true, |d| d == dataset)
+        });
+        for smoke_query in smoke_queries {
+            let places = backend
+                .search_places_blocking(smoke_query.search(index))
+                .with_context(|_| format!("impossible to search \"{}\"", smoke_query.query))?;
+            match places.first() {
+                Some(place) if place.id() == smoke_query.id => {
+                    info!(
+                        "\"{}\" gives {} in {}",
+                        smoke_query.query, smoke_query.id, index
+                    )
+                }
+                Some(place) => bail!(
+                    "the first result of \"{}\" in {} is {} instead of {}",
+                    smoke_query.query,
+                    index,
+                    place.id(),
+                    smoke_query.id
+                ),
+                None => bail!(
+                    "no result for \"{}\" in {}, {} was expected",
+                    smoke_query.query,
+                    index,
+                    smoke_query.id
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryBackend;
+    use crate::objects::Admin;
+    use crate::rubber::IndexSettings;
+
+    fn admin(id: &str, label: &str, weight: f64) -> Admin {
+        Admin {
+            id: id.to_string(),
+            name: label.to_string(),
+            label: label.to_string(),
+            weight,
+            ..Default::default()
+        }
+    }
+
+    fn publish(backend: &mut MemoryBackend, admins: Vec<Admin>) -> Result<usize, Error> {
+        let settings = IndexSettings {
+            nb_shards: 1,
+            nb_replicas: 0,
+        };
+        backend.public_index("fr", &settings, admins.into_iter())
+    }
+
+    #[test]
+    fn max_drop_percent() {
+        let mut backend = MemoryBackend::new().with_quality_gate(QualityGate {
+            max_drop_percent: Some(20.),
+            smoke_queries: vec![],
+        });
+        let admins = |nb: usize| -> Vec<Admin> {
+            (0..nb)
+                .map(|i| admin(&format!("admin:{}", i), &format!("City {}", i), 0.))
+                .collect()
+        };
+        // nothing to compare the first index with
+        publish(&mut backend, admins(10)).unwrap();
+        publish(&mut backend, admins(8)).unwrap();
+        assert!(publish(&mut backend, admins(6)).is_err());
+        // the previous index is still published
+        assert_eq!(backend.get_all_admins().unwrap().len(), 8);
+        publish(&mut backend, admins(12)).unwrap();
+    }
+
+    #[test]
+    fn smoke_queries() {
+        let smoke_query = |query: &str, id: &str| SmokeQuery {
+            doc_type: "admin".to_string(),
+            dataset: None,
+            query: query.to_string(),
+            id: id.to_string(),
+        };
+        let mut backend = MemoryBackend::new().with_quality_gate(QualityGate {
+            max_drop_percent: None,
+            smoke_queries: vec![
+                smoke_query("Paris", "admin:fr:75056"),
+                // only checked on the belgian dataset
+                SmokeQuery {
+                    dataset: Some("be".to_string()),
+                    ..smoke_query("Bruxelles", "admin:be:21004")
+                },
+            ],
+        });
+        let paris = admin("admin:fr:75056", "Paris", 1.);
+        let arrondissement = admin("admin:fr:75101", "Paris 1er Arrondissement", 0.1);
+        publish(&mut backend, vec![paris.clone(), arrondissement.clone()]).unwrap();
+
+        let error = publish(&mut backend, vec![arrondissement]).unwrap_err();
+        let cause = error.iter_chain().last().unwrap().to_string();
+        assert!(cause.starts_with("the first result of \"Paris\""));
+        assert!(cause.ends_with("is admin:fr:75101 instead of admin:fr:75056"));
+        assert!(publish(&mut backend, vec![admin("admin:fr:69123", "Lyon", 1.)]).is_err());
+        publish(&mut backend, vec![paris]).unwrap();
+    }
+}
@@ -28,22 +28,26 @@
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
 
-use super::objects::{Admin, Context, Explanation, MimirObject};
+use super::backend::{Backend, MultiSearchFuture, PlacesFuture};
 use super::objects::{AliasOperation, AliasOperations, AliasParameter, Coord, Place};
+use super::objects::{Context, Explanation, MimirObject};
+use super::quality::QualityGate;
 use failure::{bail, format_err, Error, ResultExt};
+use futures::future::{self, Future};
+use futures::Stream;
 use prometheus::{exponential_buckets, histogram_opts, register_histogram, Histogram};
 use reqwest::StatusCode;
 use rs_es::error::EsError;
-use rs_es::operations::search::ScanResult;
-use rs_es::operations::search::SearchResult;
 use rs_es::query::functions::{FilteredFunction, Function};
 use rs_es::query::Query;
 use rs_es::units as rs_u;
-use rs_es::units::Duration;
 use rs_es::EsResponse;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use slog_scope::{debug, info, warn};
 use std::collections::BTreeMap;
 use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
 use std::time;
 
 const SYNONYMS: [&'static str; 17] = [
@@ -66,6 +70,77 @@ const SYNONYMS: [&'static str; 17] = [
     "anpe,pole emploi",
 ];
 
+/// first elasticsearch version without mapping types,
+/// for those versions the typeless templates are used and the documents are indexed as `_doc`
+const TYPELESS_ES_VERSION: u64 = 7;
+
+/// templates of the indexes, by template name
+const TEMPLATES: [(&str, &str); 7] = [
+    (
+        "template_addr",
+        include_str!("../../../json/addr_settings.json"),
+    ),
+    (
+        "template_stop",
+        include_str!("../../../json/stop_settings.json"),
+    ),
+    (
+        "template_admin",
+        include_str!("../../../json/admin_settings.json"),
+    ),
+    (
+        "template_street",
+        include_str!("../../../json/street_settings.json"),
+    ),
+    (
+        "template_poi",
+        include_str!("../../../json/poi_settings.json"),
+    ),
+    (
+        "template_line",
+        include_str!("../../../json/line_settings.json"),
+    ),
+    (
+        "template_network",
+        include_str!("../../../json/network_settings.json"),
+    ),
+];
+
+/// templates of the indexes for ES >= 7, without mapping types
+const TYPELESS_TEMPLATES: [(&str, &str); 7] = [
+    (
+        "template_addr",
+        include_str!("../../../json/es7/addr_settings.json"),
+    ),
+    (
+        "template_stop",
+        include_str!("../../../json/es7/stop_settings.json"),
+    ),
+    (
+        "template_admin",
+        include_str!("../../../json/es7/admin_settings.json"),
+    ),
+    (
+        "template_street",
+        include_str!("../../../json/es7/street_settings.json"),
+    ),
+    (
+        "template_poi",
+        include_str!("../../../json/es7/poi_settings.json"),
+    ),
+    (
+        "template_line",
+        include_str!("../../../json/es7/line_settings.json"),
+    ),
+    (
+        "template_network",
+        include_str!("../../../json/es7/network_settings.json"),
+    ),
+];
+
+/// duration during which a scroll context is kept alive between 2 pages
+const SCROLL_DURATION: &str = "1m";
+
 lazy_static::lazy_static! {
     static ref ES_REQ_HISTOGRAM: Histogram = register_histogram!(
         "bragi_elasticsearch_reverse_duration_seconds",
@@ -90,14 +165,24 @@ pub struct Rubber {
     pub es_client: rs_es::Client,
     // some operation are not implemented in rs_es, we need to use a raw http client
     http_client: reqwest::Client,
+    // used for the searches, to not block the bragi workers while waiting for ES
+    // Note: this client has no timeout, the timeout is applied on each search,
+    // so that all the rubbers built with `with_timeout` share the same connection pool
+    async_http_client: reqwest::r#async::Client,
     // Note: The timeout is used for the http client AND for the ES internal query
     pub timeout: Option<time::Duration>,
     pub cnx_string: String,
+    // number of previous generations of the indexes kept on publication (cf `Backend::keep_previous`)
+    keep_previous: usize,
+    quality_gate: Option<QualityGate>,
+    // cache of `is_typeless`, the cluster version is only asked once
+    // (shared by the clones, they use the same cluster)
+    typeless: Arc<Mutex<Option<bool>>>,
 }
 
 #[derive(Clone, Debug)]
 pub struct TypedIndex<T> {
-    name: String,
+    pub(crate) name: String,
     _type: PhantomData<T>,
 }
 
@@ -108,9 +193,15 @@ impl<T> TypedIndex<T> {
             _type: PhantomData,
         }
     }
+
+    /// the name of the elasticsearch index
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 /// Index Visibility
+#[derive(Clone, Copy, Debug)]
 pub enum IndexVisibility {
     /// Public means the index is aliased to the global indices
     Public,
@@ -148,6 +239,9 @@ pub fn get_date_index_name(base_index_name: &str) -> String {
 pub fn get_indexes_by_type(a_type: &str) -> String {
     let doc_type = match a_type {
         "public_transport:stop_area" => "stop",
+        "public_transport:stop_point" => "stop_point",
+        "public_transport:line" => "line",
+        "public_transport:network" => "network",
         "city" | "zone" => "admin",
         "house" => "addr",
         _ => a_type,
@@ -156,35 +250,253 @@ pub fn get_indexes_by_type(a_type: &str) -> String {
     format!("munin_{}", doc_type)
 }
 
-pub fn read_places(
-    result: SearchResult<serde_json::Value>,
-    coord: Option<&Coord>, // coord used to compute the distance of the place to the object
-) -> Result<Vec<Place>, EsError> {
-    debug!(
-        "{} documents found in {} ms",
-        result.hits.total, result.took
-    );
+/// read the type of a document from its `type` field
+///
+/// the mapping type (`_type`) of the hit is only used for the documents
+/// indexed before the `type` field was added
+pub fn get_doc_type(
+    source: Option<&serde_json::Value>,
+    mapping_type: Option<&str>,
+) -> Option<String> {
+    source
+        .and_then(|source| source.get("type"))
+        .and_then(|doc_type| doc_type.as_str())
+        .or(mapping_type)
+        .map(str::to_owned)
+}
+
+/// A MimirObject as stored in elasticsearch
+///
+/// The type of the object is stored in the document itself,
+/// since there are no more mapping types in ES 7
+#[derive(Serialize, Debug)]
+pub(crate) struct Document<T> {
+    #[serde(rename = "type")]
+    pub(crate) doc_type: &'static str,
+    #[serde(flatten)]
+    pub(crate) object: T,
+}
+
+/// the body of a _bulk request indexing the objects, with their ids if they have one
+fn bulk_body<T: MimirObject>(objects: Vec<T>) -> Result<String, serde_json::Error> {
+    let mut body = String::new();
+    for object in objects {
+        let action = match object.es_id() {
+            Some(id) => serde_json::json!({ "index": { "_id": id } }),
+            None => serde_json::json!({ "index": {} }),
+        };
+        let document = serde_json::to_string(&Document {
+            doc_type: T::doc_type(),
+            object,
+        })?;
+        // the _bulk body is newline delimited json, each line must end with a '\n'
+        body.push_str(&format!("{}\n{}\n", action, document));
+    }
+    Ok(body)
+}
+
+/// build the places from the raw ES hits (type, _source and _explanation)
+/// and compute their distance to `coord` if any
+pub(crate) fn make_places<I>(hits: I, coord: Option<&Coord>) -> Vec<Place>
+where
+    I: Iterator<
+        Item = (
+            String,
+            Option<Box<serde_json::Value>>,
+            Option<serde_json::Value>,
+        ),
+    >,
+{
     let point: Option<geo_types::Point<f64>> = coord.map(|c| c.0.into());
     // for the moment rs-es does not handle enum Document,
     // so we need to convert the ES glob to a Place
-    Ok(result
-        .hits
-        .hits
-        .into_iter()
-        .filter_map(|hit| make_place(hit.doc_type, hit.source, hit.explanation))
+    hits.filter_map(|(doc_type, source, explanation)| make_place(doc_type, source, explanation))
         .map(|mut place| {
             if let Some(ref p) = point {
-                use geo::algorithm::haversine_distance::HaversineDistance;
-                let distance = p.haversine_distance(&place.coord().0.into()) as u32;
+                let distance = place.distance_to(p);
                 place.set_distance(distance);
             }
             place
         })
-        .collect())
+        .collect()
+}
+
+/// One search sent with the async http client (cf `Rubber::search_places` and `Rubber::multi_search`)
+#[derive(Debug)]
+pub struct SearchRequest {
+    pub indexes: Vec<String>,
+    pub query: Query,
+    pub from: u64,
+    pub size: u64,
+    /// coord used to compute the distance of the places to the object
+    pub coord: Option<Coord>,
+    pub explain: bool,
+    /// fetch the geometry of the streets, it is large and only needed to compute
+    /// the distance to the streets or to return their whole geometry
+    pub with_geometry: bool,
+}
+
+impl SearchRequest {
+    fn body(&self, timeout: Option<&str>) -> serde_json::Value {
+        // No need to fetch "boundary" as it's not used in the geocoding response
+        // and is very large in some documents (countries...),
+        // the geometries of the embedded streets are never used
+        let mut excludes = vec!["boundary", "*.geometry"];
+        if !self.with_geometry {
+            excludes.push("geometry");
+        }
+        let mut body = serde_json::json!({
+            "query": self.query,
+            "from": self.from,
+            "size": self.size,
+            "_source": { "excludes": excludes },
+        });
+        if self.explain {
+            body["explain"] = serde_json::Value::Bool(true);
+        }
+        if let Some(timeout) = timeout {
+            body["timeout"] = serde_json::Value::from(timeout);
+        }
+        body
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ScrollResponse<T> {
+    #[serde(rename = "_scroll_id")]
+    scroll_id: String,
+    #[serde(rename = "_shards")]
+    shards: ScrollShards,
+    hits: ScrollHits<T>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScrollShards {
+    failed: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScrollHits<T> {
+    hits: Vec<ScrollHit<T>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScrollHit<T> {
+    #[serde(rename = "_source")]
+    source: T,
+}
+
+#[derive(Deserialize, Debug)]
+struct MultiSearchResponses {
+    responses: Vec<SearchResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+    #[serde(default)]
+    hits: Option<SearchHits>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchHits {
+    hits: Vec<SearchHit>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchHit {
+    #[serde(rename = "_type", default)]
+    mapping_type: Option<String>,
+    #[serde(rename = "_source")]
+    source: Option<Box<serde_json::Value>>,
+    #[serde(rename = "_explanation")]
+    explanation: Option<serde_json::Value>,
+}
+
+impl SearchResponse {
+    fn into_places(self, coord: Option<&Coord>) -> Result<Vec<Place>, EsError> {
+        match (self.error, self.hits) {
+            (Some(error), _) => Err(EsError::EsServerError(error.to_string())),
+            (None, Some(hits)) => Ok(make_places(
+                hits.hits.into_iter().filter_map(|hit| {
+                    let doc_type = get_doc_type(
+                        hit.source.as_ref().map(|source| &**source),
+                        hit.mapping_type.as_ref().map(String::as_str),
+                    );
+                    match doc_type {
+                        Some(doc_type) => Some((doc_type, hit.source, hit.explanation)),
+                        None => {
+                            warn!("ES hit without type, ignoring it");
+                            None
+                        }
+                    }
+                }),
+                coord,
+            )),
+            (None, None) => Err(EsError::EsError("no hits in search response".to_owned())),
+        }
+    }
+}
+
+/// bound the duration of a future, the future fails if it is not resolved before `timeout`
+fn with_deadline<F>(
+    future: F,
+    timeout: Option<time::Duration>,
+) -> Box<dyn Future<Item = F::Item, Error = EsError>>
+where
+    F: Future<Error = EsError> + 'static,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Box::new(future),
+    };
+    Box::new(
+        tokio_timer::Timeout::new(future, timeout).map_err(move |e| {
+            if e.is_elapsed() {
+                EsError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("elasticsearch request timed out after {:?}", timeout),
+                ))
+            } else if e.is_timer() {
+                EsError::EsError(format!("impossible to set elasticsearch timeout: {}", e))
+            } else {
+                e.into_inner()
+                    .expect("the error is neither a timeout nor a timer error")
+            }
+        }),
+    )
+}
+
+/// send a request with the async http client and read its json response
+fn read_async_response<T>(
+    request: reqwest::r#async::RequestBuilder,
+) -> impl Future<Item = T, Error = EsError>
+where
+    T: DeserializeOwned,
+{
+    request.send().map_err(EsError::from).and_then(|response| {
+        let status = response.status();
+        response
+            .into_body()
+            .concat2()
+            .map_err(EsError::from)
+            .and_then(move |body| {
+                if status != StatusCode::OK {
+                    return Err(EsError::EsServerError(format!(
+                        "{}: {}",
+                        status,
+                        String::from_utf8_lossy(&body)
+                    )));
+                }
+                serde_json::from_slice(&body)
+                    .map_err(|e| EsError::EsError(format!("invalid elasticsearch response: {}", e)))
+            })
+    })
 }
 
 /// takes a ES json blob and build a Place from it
-/// it uses the _type field of ES to know which type of the Place enum to fill
+/// it uses the type of the document (cf `get_doc_type`) to know which type of the Place enum to fill
 pub fn make_place<'a>(
     doc_type: String,
     value: Option<Box<serde_json::Value>>,
@@ -206,8 +518,11 @@ pub fn make_place<'a>(
             "admin" => convert(*v, Place::Admin),
             "poi" => convert(*v, Place::Poi),
             "stop" => convert(*v, Place::Stop),
+            "stop_point" => convert(*v, Place::StopPoint),
+            "line" => convert(*v, Place::PtLine),
+            "network" => convert(*v, Place::PtNetwork),
             _ => {
-                warn!("unknown ES return value, _type field = {}", doc_type);
+                warn!("unknown ES return value, type field = {}", doc_type);
                 None
             }
         }
@@ -229,6 +544,29 @@ pub fn make_place<'a>(
     }
 }
 
+/// the search of the nearest address (or street) of `coord` (cf `Backend::get_address`)
+pub(crate) fn build_address_search(coord: &Coord) -> SearchRequest {
+    let types = vec!["house".into(), "street".into()];
+    let indexes = get_indexes(false, &[], &[], &types);
+
+    let distance = rs_u::Distance::new(1000., rs_u::DistanceUnit::Meter);
+    let geo_distance =
+        Query::build_geo_distance("coord", (coord.lat(), coord.lon()), distance).build();
+    let query = Query::build_bool()
+        .with_should(build_proximity_with_boost(coord, 1.))
+        .with_must(geo_distance)
+        .build();
+    SearchRequest {
+        indexes,
+        query,
+        from: 0,
+        size: 1,
+        coord: Some(*coord),
+        explain: false,
+        with_geometry: false,
+    }
+}
+
 /// Create a `rs_es::Query` that boosts results according to the
 /// distance to `coord`.
 pub fn build_proximity_with_boost(coord: &Coord, boost: f64) -> Query {
@@ -247,6 +585,14 @@ pub fn build_proximity_with_boost(coord: &Coord, boost: f64) -> Query {
         .build()
 }
 
+/// the public transport types searched in the indexes of the `pt_datasets`
+/// only when they are explicitly asked (cf `get_indexes`)
+pub const PT_DATASET_TYPES: [&str; 3] = [
+    "public_transport:stop_point",
+    "public_transport:line",
+    "public_transport:network",
+];
+
 pub fn get_indexes(
     all_data: bool,
     pt_datasets: &[&str],
@@ -261,11 +607,13 @@ pub fn get_indexes(
 
     let mut result: Vec<String> = vec![];
 
+    // the public transport objects have one index by dataset
     let select_type = |t: &str| -> bool {
+        let is_pt = t == "public_transport:stop_area" || PT_DATASET_TYPES.contains(&t);
         if poi_datasets.is_empty() {
-            t != "public_transport:stop_area"
+            !is_pt
         } else {
-            t != "public_transport:stop_area" && t != "poi"
+            !is_pt && t != "poi"
         }
     };
 
@@ -287,6 +635,15 @@ pub fn get_indexes(
         };
     }
 
+    // the other public transport objects are only searched when explicitly asked,
+    // and there is no global index for them
+    for type_ in types.iter().filter(|t| PT_DATASET_TYPES.contains(*t)) {
+        let type_index = get_indexes_by_type(type_);
+        pt_datasets
+            .iter()
+            .for_each(|dataset| result.push(format!("{}_{}", type_index, dataset)));
+    }
+
     if types.is_empty() || types.contains(&"poi") {
         poi_datasets
             .iter()
@@ -304,8 +661,12 @@ impl Rubber {
         Rubber {
             es_client: rs_es::Client::init(&cnx).unwrap(),
             http_client: reqwest::Client::new(),
+            async_http_client: reqwest::r#async::Client::new(),
             timeout: None,
             cnx_string: cnx.to_owned(),
+            keep_previous: 0,
+            quality_gate: None,
+            typeless: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -317,9 +678,51 @@ impl Rubber {
         Rubber {
             es_client: rs_es::Client::init_with_timeout(&cnx, timeout).unwrap(),
             http_client: reqwest::Client::builder().timeout(timeout).build().unwrap(),
+            async_http_client: reqwest::r#async::Client::new(),
             cnx_string: cnx.to_owned(),
             timeout,
+            keep_previous: 0,
+            quality_gate: None,
+            typeless: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// keep the `keep_previous` last generations of the indexes when publishing a new one,
+    /// to be able to roll back to them with `Backend::rollback_index`
+    pub fn with_keep_previous(self, keep_previous: usize) -> Rubber {
+        Rubber {
+            keep_previous,
+            ..self
+        }
+    }
+
+    /// check the new indexes with the quality gate before publishing them
+    pub fn with_quality_gate(self, quality_gate: QualityGate) -> Rubber {
+        Rubber {
+            quality_gate: Some(quality_gate),
+            ..self
+        }
+    }
+
+    /// major version of the elasticsearch cluster (2 for ES 2.4.6)
+    pub fn major_version(&self) -> Result<u64, EsError> {
+        let info: serde_json::Value = self.get("")?.json()?;
+        info.pointer("/version/number")
+            .and_then(|version| version.as_str())
+            .and_then(|version| version.split('.').next())
+            .and_then(|major| major.parse().ok())
+            .ok_or_else(|| EsError::EsError(format!("invalid elasticsearch version in {}", info)))
+    }
+
+    /// true if the elasticsearch cluster does not handle mapping types anymore (ES >= 7)
+    fn is_typeless(&self) -> Result<bool, EsError> {
+        let mut typeless = self.typeless.lock().unwrap();
+        if let Some(typeless) = *typeless {
+            return Ok(typeless);
         }
+        let is_typeless = self.major_version()? >= TYPELESS_ES_VERSION;
+        *typeless = Some(is_typeless);
+        Ok(is_typeless)
     }
 
     pub fn get(&self, path: &str) -> Result<reqwest::Response, EsError> {
@@ -344,18 +747,214 @@ impl Rubber {
         check_response(result)
     }
 
-    pub fn make_index<T: MimirObject>(
+    /// read the documents of an index matching the query (all of them without query)
+    /// with the scroll api, `f` is called on each page of documents
+    pub fn scroll_objects<T, E, F>(
         &self,
-        dataset: &str,
-        index_settings: &IndexSettings,
-    ) -> Result<TypedIndex<T>, Error> {
-        let index_name = get_date_index_name(&get_main_type_and_dataset_index::<T>(dataset));
-        info!("creating index {}", index_name);
-        self.create_index(&index_name.to_string(), index_settings)?;
-        Ok(TypedIndex::new(index_name))
+        index: &str,
+        query: Option<Query>,
+        mut f: F,
+    ) -> Result<(), E>
+    where
+        for<'de> T: serde::de::Deserialize<'de> + std::fmt::Debug,
+        E: From<EsError>,
+        F: FnMut(Vec<T>) -> Result<(), E>,
+    {
+        // the `_doc` order is the most efficient one
+        let query = match query {
+            Some(query) => serde_json::json!({ "size": 1000, "sort": ["_doc"], "query": query }),
+            None => serde_json::json!({ "size": 1000, "sort": ["_doc"] }),
+        };
+        let mut page: ScrollResponse<T> = self.scroll_page(
+            &format!("{}/_search?scroll={}", index, SCROLL_DURATION),
+            &query.to_string(),
+        )?;
+        while !page.hits.hits.is_empty() {
+            f(page.hits.hits.into_iter().map(|hit| hit.source).collect())?;
+            let scroll = serde_json::json!({
+                "scroll": SCROLL_DURATION,
+                "scroll_id": page.scroll_id,
+            });
+            page = self.scroll_page("_search/scroll", &scroll.to_string())?;
+        }
+        // the scroll context is freed, without waiting for its expiration
+        let clear_scroll = serde_json::json!({ "scroll_id": [page.scroll_id] });
+        let url = self.es_client.full_url("_search/scroll");
+        let res = self
+            .http_client
+            .delete(&url)
+            .body(clear_scroll.to_string())
+            .send()
+            .map_err(EsError::from)?;
+        check_response(res)?;
+        Ok(())
+    }
+
+    /// read all the documents of an index matching the query (all of them without query)
+    fn scroll<T>(&self, index: &str, query: Option<Query>) -> Result<Vec<T>, EsError>
+    where
+        for<'de> T: serde::de::Deserialize<'de> + std::fmt::Debug,
+    {
+        let mut objects = vec![];
+        self.scroll_objects(index, query, |page| -> Result<(), EsError> {
+            objects.extend(page);
+            Ok(())
+        })?;
+        Ok(objects)
+    }
+
+    /// read a page of a scroll, an error is returned if the page is not complete
+    /// (a missing index or an expired scroll context are not considered as empty pages)
+    fn scroll_page<T>(&self, path: &str, body: &str) -> Result<ScrollResponse<T>, EsError>
+    where
+        for<'de> T: serde::de::Deserialize<'de> + std::fmt::Debug,
+    {
+        debug!("reading a scroll page on {} with {}", path, body);
+        let url = self.es_client.full_url(path);
+        let mut res = self.http_client.post(url).body(body.to_owned()).send()?;
+        if res.status() != StatusCode::OK {
+            return Err(EsError::from(&mut res));
+        }
+        let page: ScrollResponse<T> = res.json()?;
+        if page.shards.failed > 0 {
+            return Err(EsError::EsError(format!(
+                "scroll on {} failed on {} shards",
+                path, page.shards.failed
+            )));
+        }
+        Ok(page)
+    }
+
+    pub fn create_template(&self, name: &str, settings: &str) -> Result<(), Error> {
+        debug!("creating template");
+        self.put(&format!("_template/{}", name), settings)
+            .map_err(|e| {
+                info!("Error while creating template {}", name);
+                format_err!("Error: {} while creating template {}", e.to_string(), name)
+            })
+            .and_then(|res| {
+                if res.status() == StatusCode::OK {
+                    Ok(())
+                } else {
+                    Err(format_err!("cannot create template: {:?}", res))
+                }
+            })
+    }
+
+    pub fn initialize_templates(&self) -> Result<(), Error> {
+        let templates = if self.is_typeless()? {
+            &TYPELESS_TEMPLATES
+        } else {
+            &TEMPLATES
+        };
+        for (name, settings) in templates.iter() {
+            self.create_template(name, settings)?;
+        }
+        Ok(())
+    }
+
+    /// register a shared file system repository for the snapshots
+    ///
+    /// Note: the location has to be listed in the `path.repo` setting of all the ES nodes
+    pub fn create_snapshot_repository(
+        &self,
+        repository: &str,
+        location: &str,
+    ) -> Result<(), Error> {
+        let body = serde_json::json!({
+            "type": "fs",
+            "settings": { "location": location }
+        });
+        let res = self
+            .put(&format!("_snapshot/{}", repository), &body.to_string())
+            .with_context(|_| format!("Error occurred when creating repository {}", repository))?;
+        match res.status() {
+            StatusCode::OK => Ok(()),
+            _ => bail!(
+                "cannot create snapshot repository {}: {:?}",
+                repository,
+                res
+            ),
+        }
+    }
+
+    /// snapshot the indexes in the repository and wait for the end of the snapshot
+    pub fn create_snapshot(
+        &self,
+        repository: &str,
+        snapshot: &str,
+        indexes: &[String],
+    ) -> Result<(), Error> {
+        let body = serde_json::json!({
+            "indices": indexes.join(","),
+            "include_global_state": false,
+        });
+        let res = self
+            .put(
+                &format!(
+                    "_snapshot/{}/{}?wait_for_completion=true",
+                    repository, snapshot
+                ),
+                &body.to_string(),
+            )
+            .with_context(|_| format!("Error occurred when creating snapshot {}", snapshot))?;
+        if res.status() != StatusCode::OK {
+            bail!("cannot create snapshot {}: {:?}", snapshot, res);
+        }
+        let value: serde_json::Value = res.read_response()?;
+        match value.pointer("/snapshot/state").and_then(|s| s.as_str()) {
+            Some("SUCCESS") => Ok(()),
+            state => bail!("snapshot {} is not complete, state: {:?}", snapshot, state),
+        }
     }
 
-    pub fn create_index(&self, name: &String, index_settings: &IndexSettings) -> Result<(), Error> {
+    /// restore the indexes of a snapshot, without their aliases, and wait for the end of the restore
+    ///
+    /// The restored indexes are renamed with `rename_replacement` (in which `$1` is
+    /// the name of the snapshotted index), to not conflict with the existing ones.
+    pub fn restore_snapshot(
+        &self,
+        repository: &str,
+        snapshot: &str,
+        indexes: &[String],
+        rename_replacement: &str,
+    ) -> Result<(), Error> {
+        let body = serde_json::json!({
+            "indices": indexes.join(","),
+            "include_global_state": false,
+            "include_aliases": false,
+            "rename_pattern": "(.+)",
+            "rename_replacement": rename_replacement,
+        });
+        let res = self
+            .post(
+                &format!(
+                    "_snapshot/{}/{}/_restore?wait_for_completion=true",
+                    repository, snapshot
+                ),
+                &body.to_string(),
+            )
+            .with_context(|_| format!("Error occurred when restoring snapshot {}", snapshot))?;
+        if res.status() != StatusCode::OK {
+            bail!("cannot restore snapshot {}: {:?}", snapshot, res);
+        }
+        let value: serde_json::Value = res.read_response()?;
+        match value
+            .pointer("/snapshot/shards/failed")
+            .and_then(|f| f.as_u64())
+        {
+            Some(0) => Ok(()),
+            failed => bail!(
+                "snapshot {} not restored, failed shards: {:?}",
+                snapshot,
+                failed
+            ),
+        }
+    }
+}
+
+impl Backend for Rubber {
+    fn create_index(&self, name: &str, index_settings: &IndexSettings) -> Result<(), Error> {
         debug!("creating index");
         // Note: in rs_es it can be done with MappingOperation but for the moment I think
         // storing the mapping in json is more convenient
@@ -399,49 +998,7 @@ impl Rubber {
             })
     }
 
-    pub fn create_template(&self, name: &str, settings: &str) -> Result<(), Error> {
-        debug!("creating template");
-        self.put(&format!("_template/{}", name), settings)
-            .map_err(|e| {
-                info!("Error while creating template {}", name);
-                format_err!("Error: {} while creating template {}", e.to_string(), name)
-            })
-            .and_then(|res| {
-                if res.status() == StatusCode::OK {
-                    Ok(())
-                } else {
-                    Err(format_err!("cannot create template: {:?}", res))
-                }
-            })
-    }
-
-    pub fn initialize_templates(&self) -> Result<(), Error> {
-        self.create_template(
-            &"template_addr",
-            include_str!("../../../json/addr_settings.json"),
-        )?;
-        self.create_template(
-            &"template_stop",
-            include_str!("../../../json/stop_settings.json"),
-        )?;
-        self.create_template(
-            &"template_admin",
-            include_str!("../../../json/admin_settings.json"),
-        )?;
-        self.create_template(
-            &"template_street",
-            include_str!("../../../json/street_settings.json"),
-        )?;
-        self.create_template(
-            &"template_poi",
-            include_str!("../../../json/poi_settings.json"),
-        )?;
-        Ok(())
-    }
-
-    // get all aliases for a doc_type/dataset
-    // return a map with each index as key and all their aliases
-    pub fn get_all_aliased_index(
+    fn get_all_aliased_index(
         &self,
         base_index: &str,
     ) -> Result<BTreeMap<String, Vec<String>>, Error> {
@@ -476,111 +1033,104 @@ impl Rubber {
         }
     }
 
-    // get the last indexes for this doc_type/dataset
-    // Note: to be resilient to ghost ES indexes, we return all indexes for this doc_type/dataset
-    // but the new index
-    fn get_last_index<T: MimirObject>(
-        &self,
-        new_index: &TypedIndex<T>,
-        dataset: &str,
-    ) -> Result<Vec<String>, Error> {
-        let base_index = get_main_type_and_dataset_index::<T>(dataset);
-        // we don't want to remove the newly created index
-        Ok(self
-            .get_all_aliased_index(&base_index)?
-            .into_iter()
-            .map(|(k, _)| k)
-            .filter(|i| i.as_str() != new_index.name)
-            .collect())
-    }
-
-    pub fn get_address(&mut self, coord: &Coord) -> Result<Vec<Place>, EsError> {
-        let types = vec!["house".into(), "street".into()];
-        let indexes = get_indexes(false, &[], &[], &types);
-        let indexes = indexes
-            .iter()
-            .map(|index| index.as_str())
-            .collect::<Vec<&str>>();
+    /// the rubber shares the connections of this one
+    ///
+    /// Note: the timeout is only changed for the ES internal query and for the searches
+    /// done with the async http client (`search_places` and `multi_search`),
+    /// the other operations keep the network timeout given at the creation of the rubber
+    fn with_timeout(&self, timeout: Option<time::Duration>) -> Arc<dyn Backend> {
+        Arc::new(Rubber {
+            timeout,
+            ..self.clone()
+        })
+    }
 
-        let distance = rs_u::Distance::new(1000., rs_u::DistanceUnit::Meter);
-        let geo_distance =
-            Query::build_geo_distance("coord", (coord.lat(), coord.lon()), distance).build();
-        let query = Query::build_bool()
-            .with_should(build_proximity_with_boost(coord, 1.))
-            .with_must(geo_distance)
-            .build();
+    fn keep_previous(&self) -> usize {
+        self.keep_previous
+    }
 
-        let timer = ES_REQ_HISTOGRAM.start_timer();
+    fn quality_gate(&self) -> Option<&QualityGate> {
+        self.quality_gate.as_ref()
+    }
 
+    /// the search is done with the blocking http client
+    fn search_places_blocking(&self, search: SearchRequest) -> Result<Vec<Place>, EsError> {
         let timeout = self.timeout.map(|t| format!("{:?}", t));
-        let mut search_query = self.es_client.search_query();
-
-        let search_query = search_query
-            .with_ignore_unavailable(true)
-            .with_indexes(&indexes)
-            .with_query(&query)
-            .with_size(1);
-
-        if let Some(timeout) = &timeout {
-            search_query.with_timeout(timeout.as_str());
-        }
-        let result = search_query.send()?;
+        let url = self.es_client.full_url(&format!(
+            "{}/_search?ignore_unavailable=true",
+            search.indexes.join(",")
+        ));
+        let result = self
+            .http_client
+            .post(&url)
+            .json(&search.body(timeout.as_ref().map(String::as_str)))
+            .send()?;
+        let response: SearchResponse = check_response(result)?.json()?;
+        response.into_places(search.coord.as_ref())
+    }
 
+    fn get_address(&mut self, coord: &Coord) -> Result<Vec<Place>, EsError> {
+        let timer = ES_REQ_HISTOGRAM.start_timer();
+        let places = self.search_places_blocking(build_address_search(coord));
         timer.observe_duration();
-        read_places(result, Some(coord))
+        places
     }
 
-    /// publish the index as the new index for this doc_type and this dataset
-    /// move the index alias of the doc_type and the dataset to point to this indexes
-    /// and remove the old index
-    /// visibility: Indicate if the index is public or private
-    pub fn publish_index<T: MimirObject>(
-        &mut self,
-        dataset: &str,
-        index: TypedIndex<T>,
-        visibility: IndexVisibility,
-    ) -> Result<(), Error> {
-        debug!("publishing index");
-
-        // Refresh index before publishing
-        self.es_client
-            .refresh()
-            .with_indexes(&[&index.name])
-            .send()?;
-        let last_indexes = self.get_last_index(&index, dataset)?;
-
-        let dataset_index = get_main_type_and_dataset_index::<T>(dataset);
-        self.alias(&dataset_index, &vec![index.name.clone()], &last_indexes)
-            .with_context(|_| format!("Error occurred when making alias: {}", dataset_index))?;
+    fn search_places(&self, search: SearchRequest) -> PlacesFuture {
+        let timeout = self.timeout.map(|t| format!("{:?}", t));
+        let url = self.es_client.full_url(&format!(
+            "{}/_search?ignore_unavailable=true",
+            search.indexes.join(",")
+        ));
+        let body = search.body(timeout.as_ref().map(String::as_str));
+        let coord = search.coord;
+        with_deadline(
+            read_async_response(self.async_http_client.post(&url).json(&body))
+                .and_then(move |response: SearchResponse| response.into_places(coord.as_ref())),
+            self.timeout,
+        )
+    }
 
-        let type_index = get_main_type_index::<T>();
-        if let IndexVisibility::Public = visibility {
-            self.alias(&type_index, &vec![dataset_index.clone()], &last_indexes)
-                .with_context(|_| format!("Error occurred when making alias: {}", type_index))?;
+    /// the searches are sent to elasticsearch in only one round trip (with the _msearch api)
+    fn multi_search(&self, searches: Vec<SearchRequest>) -> MultiSearchFuture {
+        if searches.is_empty() {
+            return Box::new(future::ok(vec![]));
         }
-
-        if let IndexVisibility::Public = visibility {
-            if T::is_geo_data() {
-                self.alias("munin_geo_data", &vec![type_index.to_string()], &vec![])
-                    .context("Error occurred when making alias: munin_geo_data")?;
-                self.alias("munin", &vec!["munin_geo_data".to_string()], &vec![])
-                    .context("Error occurred when making alias: munin")?;
-            } else {
-                self.alias("munin", &vec![type_index.to_string()], &vec![])
-                    .context("Error occurred when making alias: munin")?;
-            }
+        let timeout = self.timeout.map(|t| format!("{:?}", t));
+        let mut body = String::new();
+        for search in &searches {
+            let header = serde_json::json!({
+                "index": search.indexes.join(","),
+                "ignore_unavailable": true,
+            });
+            let request = search.body(timeout.as_ref().map(String::as_str));
+            // the _msearch body is newline delimited json, each line must end with a '\n'
+            body.push_str(&format!("{}\n{}\n", header, request));
         }
 
-        for i in last_indexes {
-            self.delete_index(&i)
-                .with_context(|_| format!("Error occurred when deleting index: {}", i))?;
-        }
-        Ok(())
+        let url = self.es_client.full_url("_msearch");
+        with_deadline(
+            read_async_response(self.async_http_client.post(&url).body(body)).and_then(
+                move |responses: MultiSearchResponses| {
+                    if responses.responses.len() != searches.len() {
+                        return Err(EsError::EsError(format!(
+                            "invalid _msearch response, {} responses for {} searches",
+                            responses.responses.len(),
+                            searches.len()
+                        )));
+                    }
+                    Ok(responses
+                        .responses
+                        .into_iter()
+                        .zip(searches.iter())
+                        .map(|(response, search)| response.into_places(search.coord.as_ref()))
+                        .collect())
+                },
+            ),
+        )
     }
 
-    /// add a list of new indexes to the alias
-    /// remove a list of indexes from the alias
-    pub fn alias(&self, alias: &str, add: &[String], remove: &[String]) -> Result<(), Error> {
+    fn alias(&self, alias: &str, add: &[String], remove: &[String]) -> Result<(), Error> {
         info!(
             "for {}, adding alias {:?}, removing {:?}",
             alias, add, remove
@@ -599,20 +1149,43 @@ impl Rubber {
                 alias: alias.to_string(),
             }),
         });
-        let operations = AliasOperations {
-            actions: add_operations.chain(remove_operations).collect(),
-        };
-        let json = serde_json::to_string(&operations)?;
+        self.update_aliases(add_operations.chain(remove_operations).collect())
+            .with_context(|_| format!("failed to post aliases for {}", alias))?;
+        Ok(())
+    }
+
+    /// apply all the alias operations in one atomic request
+    fn update_aliases(&self, actions: Vec<AliasOperation>) -> Result<(), Error> {
+        let json = serde_json::to_string(&AliasOperations { actions })?;
         let res = self
             .post("_aliases", &json)
             .context("Error occurred when POSTing: _alias")?;
         match res.status() {
             StatusCode::OK => Ok(()),
-            _ => bail!("failed to post aliases for {}: {:?}", alias, res),
+            _ => bail!("failed to post aliases: {:?}", res),
+        }
+    }
+
+    fn refresh_index(&mut self, index: &str) -> Result<(), Error> {
+        self.es_client.refresh().with_indexes(&[index]).send()?;
+        Ok(())
+    }
+
+    fn count_documents(&self, index: &str) -> Result<u64, Error> {
+        let res = self
+            .get(&format!("{}/_count", index))
+            .with_context(|_| format!("Error occurred when counting the documents of {}", index))?;
+        if res.status() != StatusCode::OK {
+            bail!("cannot count the documents of {}: {:?}", index, res);
         }
+        let value: serde_json::Value = res.read_response()?;
+        value
+            .get("count")
+            .and_then(|c| c.as_u64())
+            .ok_or_else(|| format_err!("invalid count of the documents of {}: {}", index, value))
     }
 
-    pub fn delete_index(&mut self, index: &String) -> Result<(), Error> {
+    fn delete_index(&mut self, index: &str) -> Result<(), Error> {
         debug!("deleting index {}", &index);
         let res = self
             .es_client
@@ -626,7 +1199,11 @@ impl Rubber {
         }
     }
 
-    pub fn bulk_index<T, I>(
+    /// the documents are indexed with the _bulk api, by chunks serialized in parallel
+    ///
+    /// A document rejected by elasticsearch (for instance an admin whose boundary is
+    /// an invalid polygon) is logged and not counted, the other documents are indexed.
+    fn bulk_index<T, I>(
         &mut self,
         index: &TypedIndex<T>,
         iter: I,
@@ -636,117 +1213,166 @@ impl Rubber {
         I: Iterator<Item = T>,
     {
         use par_map::ParMap;
-        use rs_es::operations::bulk::Action;
         let mut nb = 0;
         let chunk_size = 1000;
-        let chunks = iter.pack(chunk_size).par_map(|v| {
-            v.into_iter()
-                .map(|v| {
-                    v.es_id()
-                        .into_iter()
-                        .fold(Action::index(v), |action, id| action.with_id(id))
-                })
-                .collect::<Vec<_>>()
-        });
-        for chunk in chunks.filter(|c| !c.is_empty()) {
-            nb += chunk.len();
-            self.es_client
-                .bulk(&chunk)
-                .with_index(&index.name)
-                .with_doc_type(T::doc_type())
+        // the mapping type is still needed for the old ES versions, their templates are typed
+        let mapping_type = if self.is_typeless()? {
+            "_doc"
+        } else {
+            T::doc_type()
+        };
+        let chunks = iter.pack(chunk_size).par_map(bulk_body::<T>);
+        let url = self
+            .es_client
+            .full_url(&format!("{}/{}/_bulk", index.name, mapping_type));
+        for body in chunks {
+            let body = body.map_err(|e| {
+                EsError::EsError(format!("impossible to index in {}: {}", index.name, e))
+            })?;
+            if body.is_empty() {
+                continue;
+            }
+            let result = self
+                .http_client
+                .post(url.as_str())
+                .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+                .body(body)
                 .send()?;
+            let response: serde_json::Value = check_response(result)?.json()?;
+            let items = response
+                .get("items")
+                .and_then(|items| items.as_array())
+                .ok_or_else(|| EsError::EsError(format!("invalid _bulk response: {}", response)))?;
+            for item in items {
+                match item.pointer("/index/status").and_then(|s| s.as_u64()) {
+                    Some(200) | Some(201) => nb += 1,
+                    _ => warn!("a document has been rejected by {}: {}", index.name, item),
+                }
+            }
         }
 
         Ok(nb)
     }
 
-    /// Shortcut to `index` for a public index
-    pub fn public_index<T, I>(
+    /// the documents are deleted with the _bulk api, by chunks
+    fn delete_documents<T>(
         &mut self,
-        dataset: &str,
-        index_settings: &IndexSettings,
-        iter: I,
-    ) -> Result<usize, Error>
+        index: &TypedIndex<T>,
+        ids: &[String],
+    ) -> Result<usize, rs_es::error::EsError>
     where
-        T: MimirObject + std::marker::Send + 'static,
-        I: Iterator<Item = T>,
+        T: MimirObject,
     {
-        self.index(dataset, IndexVisibility::Public, index_settings, iter)
+        let mut nb = 0;
+        let chunk_size = 1000;
+        let mapping_type = if self.is_typeless()? {
+            "_doc"
+        } else {
+            T::doc_type()
+        };
+        for chunk in ids.chunks(chunk_size) {
+            let mut body = String::new();
+            for id in chunk {
+                let action = serde_json::json!({
+                    "delete": { "_index": index.name, "_type": mapping_type, "_id": id }
+                });
+                // the _bulk body is newline delimited json, each line must end with a '\n'
+                body.push_str(&format!("{}\n", action));
+            }
+            let response: serde_json::Value = self.post("_bulk", &body)?.json()?;
+            let statuses = response
+                .get("items")
+                .and_then(|items| items.as_array())
+                .ok_or_else(|| EsError::EsError(format!("invalid _bulk response: {}", response)))?
+                .iter()
+                .map(|item| item.pointer("/delete/status").and_then(|s| s.as_u64()));
+            for status in statuses {
+                match status {
+                    Some(200) => nb += 1,
+                    // the document was already missing
+                    Some(404) => (),
+                    _ => {
+                        return Err(EsError::EsError(format!(
+                            "impossible to delete documents in {}: {}",
+                            index.name, response
+                        )))
+                    }
+                }
+            }
+        }
+        Ok(nb)
     }
 
-    /// Shortcut to `index` for a private index
-    pub fn private_index<T, I>(
+    fn get_all_objects_from_index<T>(
         &mut self,
-        dataset: &str,
-        index_settings: &IndexSettings,
-        iter: I,
-    ) -> Result<usize, Error>
+        index: &str,
+    ) -> Result<Vec<T>, rs_es::error::EsError>
     where
-        T: MimirObject + std::marker::Send + 'static,
-        I: Iterator<Item = T>,
+        for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug,
     {
-        self.index(dataset, IndexVisibility::Private, index_settings, iter)
+        self.scroll(index, None)
     }
 
-    /// add all the element of 'iter' into elasticsearch
-    ///
-    /// To have zero downtime:
-    /// first all the elements are added in a temporary index and when all has been indexed
-    /// the index is published and the old index is removed
-    pub fn index<T, I>(
+    fn get_objects_by_query<T>(
         &mut self,
-        dataset: &str,
-        visibility: IndexVisibility,
-        index_settings: &IndexSettings,
-        iter: I,
-    ) -> Result<usize, Error>
+        index: &str,
+        query: Query,
+    ) -> Result<Vec<T>, rs_es::error::EsError>
     where
-        T: MimirObject + std::marker::Send + 'static,
-        I: Iterator<Item = T>,
+        for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug,
     {
-        // TODO better error handling
-        let index = self
-            .make_index(dataset, index_settings)
-            .with_context(|_| format!("Error occurred when making index: {}", dataset))?;
-        let nb_elements = self.bulk_index(&index, iter)?;
-        self.publish_index(dataset, index, visibility)?;
-        Ok(nb_elements)
+        self.scroll(index, Some(query))
     }
 
-    pub fn get_all_admins(&mut self) -> Result<Vec<Admin>, rs_es::error::EsError> {
-        self.get_all_objects_from_index(&get_main_type_index::<Admin>())
-    }
-
-    pub fn get_all_objects_from_index<T>(
+    /// the pages are written with another handle on the same cluster
+    fn for_each_page<T, F>(
         &mut self,
         index: &str,
-    ) -> Result<Vec<T>, rs_es::error::EsError>
+        query: Option<Query>,
+        mut f: F,
+    ) -> Result<(), Error>
     where
         for<'de> T: MimirObject + serde::de::Deserialize<'de> + std::fmt::Debug,
+        F: FnMut(&mut Self, Vec<T>) -> Result<(), Error>,
     {
-        let mut result: Vec<T> = vec![];
-        let mut scan: ScanResult<T> = self
-            .es_client
-            .search_query()
-            .with_indexes(&[&index])
-            .with_size(1000)
-            .with_types(&[&T::doc_type()])
-            .scan(&Duration::minutes(1))?;
-        loop {
-            let page = scan.scroll(&mut self.es_client, &Duration::minutes(1))?;
-            if page.hits.hits.len() == 0 {
-                break;
-            }
-            result.extend(
-                page.hits
-                    .hits
-                    .into_iter()
-                    .filter_map(|hit| hit.source)
-                    .map(|ad| *ad),
-            );
+        let mut writer = self.clone();
+        self.scroll_objects(index, query, |page| f(&mut writer, page))
+    }
+
+    fn is_exact_field(&self, index: &str, field: &str) -> Result<bool, Error> {
+        let res = self
+            .get(&format!("{}/_mapping/field/{}", index, field))
+            .with_context(|_| format!("Error occurred when getting the mapping of {}", index))?;
+        if res.status() != StatusCode::OK {
+            bail!("cannot get the mapping of {}: {:?}", index, res);
         }
-        scan.close(&mut self.es_client)?;
-        Ok(result)
+        let mappings: serde_json::Value = res.read_response()?;
+        Ok(find_field_mapping(&mappings, field).map_or(false, is_exact_mapping))
+    }
+}
+
+/// the mapping of the field in the response of the field mapping api
+/// (`{index: {mappings: {[type:] {field: {full_name, mapping: {name: mapping}}}}}}`)
+fn find_field_mapping<'a>(
+    value: &'a serde_json::Value,
+    field: &str,
+) -> Option<&'a serde_json::Value> {
+    let object = value.as_object()?;
+    if object.get("full_name").and_then(|n| n.as_str()) == Some(field) {
+        return object
+            .get("mapping")
+            .and_then(|m| m.as_object())
+            .and_then(|m| m.values().next());
+    }
+    object.values().find_map(|v| find_field_mapping(v, field))
+}
+
+/// a field found with its exact value: a keyword (or a not analyzed string before ES 5)
+fn is_exact_mapping(mapping: &serde_json::Value) -> bool {
+    match mapping.get("type").and_then(|t| t.as_str()) {
+        Some("keyword") => true,
+        Some("string") => mapping.get("index").and_then(|i| i.as_str()) == Some("not_analyzed"),
+        _ => false,
     }
 }
 
@@ -768,6 +1394,31 @@ mod tests {
         Rubber::new("localhost");
     }
 
+    #[test]
+    fn test_is_exact_field() {
+        let mapping = |field: serde_json::Value| {
+            serde_json::json!({"munin_street_fr_20191001_120000_000": {"mappings": {"street": {
+                "administrative_regions.id": {
+                    "full_name": "administrative_regions.id",
+                    "mapping": {"id": field}
+                }
+            }}}})
+        };
+        let is_exact = |mappings: &serde_json::Value| {
+            find_field_mapping(mappings, "administrative_regions.id")
+                .map_or(false, is_exact_mapping)
+        };
+        assert!(is_exact(&mapping(serde_json::json!({"type": "keyword"}))));
+        assert!(is_exact(&mapping(
+            serde_json::json!({"type": "string", "index": "not_analyzed"})
+        )));
+        assert!(!is_exact(&mapping(serde_json::json!({"type": "string"}))));
+        // the field is not mapped
+        assert!(!is_exact(&serde_json::json!(
+            {"munin_street_fr_20191001_120000_000": {"mappings": {"street": {}}}}
+        )));
+    }
+
     #[test]
     fn test_get_indexes_impl() {
         // all_data
@@ -839,6 +1490,33 @@ mod tests {
             vec!["munin_poi", "munin_admin", "munin_street", "munin_addr"]
         );
 
+        // datasets fr and be + types public_transport:stop_area and public_transport:stop_point
+        //  => one stop point index by dataset
+        assert_eq!(
+            get_indexes(
+                false,
+                &["fr", "be"],
+                &[],
+                &["public_transport:stop_area", "public_transport:stop_point"],
+            ),
+            vec![
+                "munin_global_stops",
+                "munin_stop_point_fr",
+                "munin_stop_point_be",
+            ]
+        );
+
+        // dataset fr + types public_transport:line and public_transport:network
+        assert_eq!(
+            get_indexes(
+                false,
+                &["fr"],
+                &[],
+                &["public_transport:line", "public_transport:network"],
+            ),
+            vec!["munin_line_fr", "munin_network_fr"]
+        );
+
         // dataset fr with poi mti...
         //  => munin_poi should not be included, and munin_poi_mti is included
         assert_eq!(
@@ -851,4 +1529,32 @@ mod tests {
             vec!["munin_admin", "munin_street", "munin_addr", "munin_poi_mti"]
         );
     }
+
+    #[test]
+    fn test_get_doc_type() {
+        let source = serde_json::json!({"type": "street", "id": "bob"});
+        assert_eq!(
+            get_doc_type(Some(&source), Some("_doc")),
+            Some("street".to_string())
+        );
+        // documents indexed without the type field
+        let source = serde_json::json!({"id": "bob"});
+        assert_eq!(
+            get_doc_type(Some(&source), Some("street")),
+            Some("street".to_string())
+        );
+        assert_eq!(get_doc_type(Some(&source), None), None);
+    }
+
+    #[test]
+    fn test_document_has_type() {
+        let document = Document {
+            doc_type: "street",
+            object: serde_json::json!({"id": "bob", "name": "bob's street"}),
+        };
+        assert_eq!(
+            serde_json::to_value(&document).unwrap(),
+            serde_json::json!({"type": "street", "id": "bob", "name": "bob's street"})
+        );
+    }
 }
@@ -1,7 +1,9 @@
 use docker_wrapper::*;
+use mimir::backend::Backend;
 use serde_json::value::Value;
 use serde_json::Map;
 use slog_scope::info;
+use std::sync::Arc;
 use std::time::Duration;
 
 pub struct ElasticSearchWrapper<'a> {
@@ -15,7 +17,7 @@ impl<'a> ElasticSearchWrapper<'a> {
     }
 
     pub fn init(&mut self) {
-        self.rubber.delete_index(&"_all".to_string()).unwrap();
+        self.rubber.delete_index("_all").unwrap();
     }
 
     //    A way to watch if indexes are built might be curl http://localhost:9200/_stats
@@ -46,7 +48,7 @@ impl<'a> ElasticSearchWrapper<'a> {
 
     /// count the number of documents in the index
     /// If you want to count eg the number of POI, you would call
-    /// es_wrapper.count("_type:POI")
+    /// es_wrapper.count("type:poi")
     pub fn count<'b, T: Into<Option<&'b str>>>(&self, index: T, word: &str) -> u64 {
         let index = index.into().unwrap_or("munin");
         info!("counting documents with {}/_count?q={}", index, word);
@@ -144,10 +146,10 @@ impl<'a> ElasticSearchWrapper<'a> {
             .into_iter()
             .filter_map(|json| {
                 into_object(json).and_then(|obj| {
-                    let doc_type = obj
-                        .get("_type")
-                        .and_then(|doc_type| doc_type.as_str())
-                        .map(|doc_type| doc_type.into());
+                    let doc_type = mimir::rubber::get_doc_type(
+                        obj.get("_source"),
+                        obj.get("_type").and_then(|doc_type| doc_type.as_str()),
+                    );
 
                     doc_type.and_then(|doc_type| {
                         // The real object is contained in the _source section.
@@ -171,7 +173,16 @@ impl BragiHandler {
             connection_string: url.clone(),
             ..Default::default()
         });
+        BragiHandler::with_context(ctx)
+    }
+
+    /// a bragi searching in `backend`, without elasticsearch
+    pub fn with_backend(backend: Arc<dyn Backend>) -> BragiHandler {
+        let ctx = bragi::Context::with_backend(&bragi::Args::default(), backend);
+        BragiHandler::with_context(ctx)
+    }
 
+    fn with_context(ctx: bragi::Context) -> BragiHandler {
         let prometheus = bragi::prometheus_middleware::PrometheusMetrics::new("bragi", "/metrics");
         let srv = actix_http_test::TestServer::new(move || {
             actix_http::HttpService::new(
@@ -1,8 +1,10 @@
+use crate::checkpoint::Checkpoint;
 use crate::Error;
 use csv;
 use failure::ResultExt;
 use flate2::read::GzDecoder;
-use mimir::rubber::{IndexSettings, IndexVisibility, Rubber};
+use mimir::backend::Backend;
+use mimir::rubber::{IndexSettings, IndexVisibility};
 use mimir::Addr;
 use par_map::ParMap;
 use serde::de::DeserializeOwned;
@@ -10,86 +12,222 @@ use slog_scope::{error, info, warn};
 use std::fs::File;
 use std::io::Read;
 use std::marker::{Send, Sync};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-fn import_addresses<T, F>(
-    rubber: &mut Rubber,
+/// the address, if it is valid and has a street name
+fn valid_addr(addr: Result<Addr, Error>) -> Option<Addr> {
+    match addr {
+        Ok(a) => {
+            if a.street.name.is_empty() {
+                warn!("Address {} has no street name and has been ignored.", a.id);
+                None
+            } else {
+                Some(a)
+            }
+        }
+        Err(err) => {
+            warn!("Address Error ignored: {}", err);
+            None
+        }
+    }
+}
+
+/// the csv records of an input, each one with the byte offset in the input
+/// of the record following it, if the input can be read from an offset
+type Records<T> = Box<dyn Iterator<Item = (csv::Result<T>, Option<u64>)>>;
+
+/// an input of addresses, identified by its name in the checkpoints
+enum Input {
+    /// a csv file, gzipped if its extension is `gz`
+    File(PathBuf),
+    /// a csv stream
+    Stream(String, Box<dyn Read>),
+}
+
+impl Input {
+    fn name(&self) -> String {
+        match self {
+            Input::File(path) => path.display().to_string(),
+            Input::Stream(name, _) => name.clone(),
+        }
+    }
+
+    /// the records of the input not indexed yet
+    ///
+    /// The plain files are read from the offset of the first record not indexed,
+    /// the other inputs are read from the start and the `nb_indexed` first records are skipped.
+    fn records<T>(
+        self,
+        has_headers: bool,
+        nb_indexed: usize,
+        offset: Option<u64>,
+    ) -> Result<Records<T>, Error>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        let stream: Box<dyn Read> = match self {
+            Input::File(ref path) if !is_gzip(path) => {
+                let file = File::open(path)?;
+                let mut reader = csv_reader(file, has_headers);
+                if let Some(offset) = offset {
+                    let mut position = csv::Position::new();
+                    position.set_byte(offset);
+                    reader.seek(position)?;
+                }
+                // the progress of a previous version of the import has no offset
+                let skipped = if offset.is_some() { 0 } else { nb_indexed };
+                let mut records = reader.into_deserialize();
+                let records = std::iter::from_fn(move || {
+                    let record = records.next()?;
+                    Some((record, Some(records.reader().position().byte())))
+                });
+                return Ok(Box::new(records.skip(skipped)));
+            }
+            Input::File(path) => Box::new(GzDecoder::new(File::open(path)?)),
+            Input::Stream(_, stream) => stream,
+        };
+        let records = csv_reader(stream, has_headers)
+            .into_deserialize()
+            .skip(nb_indexed);
+        Ok(Box::new(records.map(|record| (record, None))))
+    }
+}
+
+fn csv_reader<R: Read>(stream: R, has_headers: bool) -> csv::Reader<R> {
+    csv::ReaderBuilder::new()
+        .has_headers(has_headers)
+        .from_reader(stream)
+}
+
+fn is_gzip(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext == "gz")
+        .unwrap_or(false)
+}
+
+/// import the addresses of several inputs in the addr index of the dataset,
+/// the progress of each input is saved in `checkpoint`
+fn import_addresses<B, T, F>(
+    backend: &mut B,
+    checkpoint: &mut Checkpoint,
+    has_headers: bool,
     nb_threads: usize,
     index_settings: IndexSettings,
     dataset: &str,
-    addresses: impl IntoIterator<Item = T>,
+    inputs: impl IntoIterator<Item = Input>,
     into_addr: F,
 ) -> Result<(), Error>
 where
+    B: Backend,
     F: Fn(T) -> Result<Addr, Error> + Send + Sync + 'static,
     T: DeserializeOwned + Send + 'static,
 {
-    let addr_index = rubber
-        .make_index(dataset, &index_settings)
-        .with_context(|_| format!("Error occurred when making index {}", dataset))?;
+    if checkpoint.is_published::<Addr>(dataset) {
+        info!("addresses already imported in {}", dataset);
+        return Ok(());
+    }
+    let addr_index = checkpoint.index(&*backend, dataset, &index_settings)?;
 
     info!("Add data in elasticsearch db.");
-    let iter = addresses
-        .into_iter()
-        .with_nb_threads(nb_threads)
-        .par_map(into_addr)
-        .filter_map(|ra| match ra {
-            Ok(a) => {
-                if a.street.name.is_empty() {
-                    warn!("Address {} has no street name and has been ignored.", a.id);
-                    None
-                } else {
-                    Some(a)
-                }
-            }
+    let into_addr = Arc::new(into_addr);
+    let mut nb = 0;
+    for input in inputs {
+        let name = input.name();
+        if checkpoint.is_done(&name) {
+            info!("{} already imported, skipped", name);
+            continue;
+        }
+        info!("importing {}...", name);
+        let records = input.records(
+            has_headers,
+            checkpoint.nb_indexed_records(&name),
+            checkpoint.offset(&name),
+        );
+        let records = match records {
+            Ok(records) => records,
             Err(err) => {
-                warn!("Address Error ignored: {}", err);
-                None
+                error!("Impossible to read {}, error: {}", name, err);
+                continue;
             }
-        });
-
-    let nb = rubber
-        .bulk_index(&addr_index, iter)
-        .with_context(|err| format!("failed to bulk insert: {}", err))?;
+        };
+        let into_addr = into_addr.clone();
+        let iter =
+            records
+                .with_nb_threads(nb_threads)
+                .par_map(move |(record, offset)| match record {
+                    Ok(r) => (valid_addr((*into_addr)(r)), offset),
+                    Err(err) => {
+                        warn!("Impossible to read line, error: {}", err);
+                        (None, offset)
+                    }
+                });
+        nb += checkpoint
+            .bulk_index_with_offsets(backend, &addr_index, &name, iter)
+            .with_context(|err| format!("failed to bulk insert: {}", err))?;
+    }
     info!("importing addresses: {} addresses added.", nb);
 
-    rubber
-        .publish_index(dataset, addr_index, IndexVisibility::Public)
+    checkpoint
+        .publish_index(backend, dataset, addr_index, IndexVisibility::Public)
         .context("Error while publishing the index")?;
     Ok(())
 }
 
-pub fn import_addresses_from_streams<T, F>(
-    rubber: &mut Rubber,
+/// Index the addresses in a new addr index of the dataset, then publish it.
+/// The addresses without street name are ignored.
+pub fn index_addresses<B: Backend>(
+    backend: &mut B,
+    checkpoint: &mut Checkpoint,
+    index_settings: IndexSettings,
+    dataset: &str,
+    addresses: impl Iterator<Item = Addr>,
+) -> Result<(), Error> {
+    let iter = addresses.filter_map(|a| valid_addr(Ok(a)));
+    let nb = checkpoint
+        .public_index(backend, dataset, &index_settings, "addresses", iter)
+        .with_context(|err| format!("failed to index the addresses: {}", err))?;
+    info!("importing addresses: {} addresses added.", nb);
+    Ok(())
+}
+
+/// import the addresses of csv streams, each stream is identified by its name in the checkpoints
+pub fn import_addresses_from_streams<B, T, F>(
+    backend: &mut B,
+    checkpoint: &mut Checkpoint,
     has_headers: bool,
     nb_threads: usize,
     index_settings: IndexSettings,
     dataset: &str,
-    streams: impl IntoIterator<Item = impl Read>,
+    streams: impl IntoIterator<Item = (String, impl Read + 'static)>,
     into_addr: F,
 ) -> Result<(), Error>
 where
+    B: Backend,
     F: Fn(T) -> Result<Addr, Error> + Send + Sync + 'static,
     T: DeserializeOwned + Send + 'static,
 {
-    let iter = streams
+    let inputs = streams
         .into_iter()
-        .flat_map(|stream| {
-            csv::ReaderBuilder::new()
-                .has_headers(has_headers)
-                .from_reader(stream)
-                .into_deserialize()
-        })
-        .filter_map(|line| {
-            line.map_err(|e| warn!("Impossible to read line, error: {}", e))
-                .ok()
-        });
-
-    import_addresses(rubber, nb_threads, index_settings, dataset, iter, into_addr)
+        .map(|(name, stream)| Input::Stream(name, Box::new(stream)));
+    import_addresses(
+        backend,
+        checkpoint,
+        has_headers,
+        nb_threads,
+        index_settings,
+        dataset,
+        inputs,
+        into_addr,
+    )
 }
 
-pub fn import_addresses_from_files<T, F>(
-    rubber: &mut Rubber,
+/// import the addresses of csv files (gzipped if their extension is `gz`),
+/// an interrupted import of the plain files is resumed from the first address not indexed
+pub fn import_addresses_from_files<B, T, F>(
+    backend: &mut B,
+    checkpoint: &mut Checkpoint,
     has_headers: bool,
     nb_threads: usize,
     index_settings: IndexSettings,
@@ -98,38 +236,18 @@ pub fn import_addresses_from_files<T, F>(
     into_addr: F,
 ) -> Result<(), Error>
 where
+    B: Backend,
     F: Fn(T) -> Result<Addr, Error> + Send + Sync + 'static,
     T: DeserializeOwned + Send + 'static,
 {
-    let streams = files.into_iter().filter_map(|path| {
-        info!("importing {:?}...", &path);
-
-        let with_gzip = path
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| ext == "gz")
-            .unwrap_or(false);
-
-        File::open(&path)
-            .map_err(|err| error!("Impossible to read file {:?}, error: {}", path, err))
-            .map(|file| {
-                if with_gzip {
-                    let decoder = GzDecoder::new(file);
-                    Box::new(decoder) as Box<dyn Read>
-                } else {
-                    Box::new(file) as Box<dyn Read>
-                }
-            })
-            .ok()
-    });
-
-    import_addresses_from_streams(
-        rubber,
+    import_addresses(
+        backend,
+        checkpoint,
         has_headers,
         nb_threads,
         index_settings,
         dataset,
-        streams,
+        files.into_iter().map(Input::File),
         into_addr,
     )
 }
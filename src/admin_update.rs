@@ -0,0 +1,562 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Update of the admins copied in the other objects
+//!
+//! The addresses, streets, pois and stops keep a copy of their admins
+//! (in their `administrative_regions`), made when they are imported.
+//! When the admins are imported again, the objects attached to the admins whose
+//! name, label, zip codes or parent changed are updated in their published indexes,
+//! without importing their datasets again.
+
+use crate::stops::GLOBAL_STOP_INDEX_NAME;
+use crate::{labels, utils};
+use failure::{Error, ResultExt};
+use mimir::backend::Backend;
+use mimir::rubber::{get_main_type_index, TypedIndex};
+use mimir::{Addr, Address, Admin, MimirObject, Poi, Stop, StopPoint, Street};
+use rs_es::query::Query;
+use slog_scope::{info, warn};
+use std::collections::HashMap;
+use std::mem::replace;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// The admins changed by an import of the admins
+#[derive(Debug, Default)]
+pub struct AdminChanges {
+    /// the new version of the changed admins, by id (None for a removed admin)
+    changed: HashMap<String, Option<Arc<Admin>>>,
+    /// all the new admins, by id, to rebuild the hierarchy of the objects
+    admins: HashMap<String, Arc<Admin>>,
+}
+
+fn is_changed(previous: &Admin, admin: &Admin) -> bool {
+    previous.name != admin.name
+        || previous.label != admin.label
+        || previous.zip_codes != admin.zip_codes
+        || previous.parent_id != admin.parent_id
+}
+
+impl AdminChanges {
+    /// compare the admins published before the import to the imported ones
+    pub fn new(previous_admins: Vec<Admin>, admins: Vec<Admin>) -> AdminChanges {
+        let admins: HashMap<_, _> = admins
+            .into_iter()
+            .map(|mut admin| {
+                // like in the AdminGeoFinder, the boundaries are not copied in the objects
+                admin.boundary = None;
+                (admin.id.clone(), Arc::new(admin))
+            })
+            .collect();
+        let changed = previous_admins
+            .into_iter()
+            .filter_map(|previous| match admins.get(&previous.id) {
+                None => Some((previous.id, None)),
+                Some(admin) if is_changed(&previous, admin) => {
+                    Some((previous.id, Some(admin.clone())))
+                }
+                Some(_) => None,
+            })
+            .collect();
+        AdminChanges { changed, admins }
+    }
+
+    pub fn len(&self) -> usize {
+        self.changed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty()
+    }
+
+    /// the new admins of an object, None if none of its admins changed
+    ///
+    /// If the parent of one of the admins changed, the hierarchy of the
+    /// first admin (the most precise one) is taken in the new admins.
+    fn update(&self, admins: &[Arc<Admin>]) -> Option<Vec<Arc<Admin>>> {
+        if !admins.iter().any(|a| self.changed.contains_key(&a.id)) {
+            return None;
+        }
+        let moved = admins.iter().any(|a| match self.changed.get(&a.id) {
+            Some(Some(admin)) => admin.parent_id != a.parent_id,
+            _ => false,
+        });
+        if moved {
+            if let Some(admin) = admins.iter().filter_map(|a| self.admins.get(&a.id)).next() {
+                return Some(self.hierarchy(admin));
+            }
+        }
+        Some(
+            admins
+                .iter()
+                .filter_map(|a| match self.changed.get(&a.id) {
+                    Some(admin) => admin.clone(),
+                    None => Some(a.clone()),
+                })
+                .collect(),
+        )
+    }
+
+    /// the admin and all its parents
+    fn hierarchy(&self, admin: &Arc<Admin>) -> Vec<Arc<Admin>> {
+        let mut hierarchy = vec![admin.clone()];
+        let mut parent_id = admin.parent_id.as_ref();
+        while let Some(parent) = parent_id.and_then(|id| self.admins.get(id)) {
+            if hierarchy.iter().any(|a| a.id == parent.id) {
+                break; // the hierarchy is not supposed to have a cycle, but it's safer
+            }
+            hierarchy.push(parent.clone());
+            parent_id = parent.parent_id.as_ref();
+        }
+        hierarchy
+    }
+}
+
+/// A mimir object with a copy of its admins
+pub trait UpdateAdmins {
+    /// replace the changed admins of the object and the fields computed from them,
+    /// return false if the object has none of the changed admins
+    fn update_admins(&mut self, changes: &AdminChanges) -> bool;
+
+    /// rebuild the approximate coordinates of the object (and of its address),
+    /// which are not deserialized when the object is read from its index
+    fn rebuild_approx_coord(&mut self);
+}
+
+/// update a field computed from the admins (like a label or the zip codes)
+///
+/// The field is only updated if it is the one computed from the previous admins,
+/// not a value given by the source of the object (for instance the city of a bano address).
+fn refresh<T, F>(field: &mut T, previous_admins: &[Arc<Admin>], admins: &[Arc<Admin>], compute: F)
+where
+    T: PartialEq,
+    F: Fn(&[Arc<Admin>]) -> T,
+{
+    if *field == compute(previous_admins) {
+        *field = compute(admins);
+    }
+}
+
+fn admins_iter(admins: &[Arc<Admin>]) -> impl Iterator<Item = &Admin> + Clone {
+    admins.iter().map(|a| a.deref())
+}
+
+impl UpdateAdmins for Street {
+    fn update_admins(&mut self, changes: &AdminChanges) -> bool {
+        let admins = match changes.update(&self.administrative_regions) {
+            Some(admins) => admins,
+            None => return false,
+        };
+        let previous_admins = replace(&mut self.administrative_regions, admins);
+        let (name, country_codes) = (&self.name, &self.country_codes);
+        refresh(
+            &mut self.label,
+            &previous_admins,
+            &self.administrative_regions,
+            |admins| labels::format_street_label(name, admins_iter(admins), country_codes),
+        );
+        refresh(
+            &mut self.zip_codes,
+            &previous_admins,
+            &self.administrative_regions,
+            utils::get_zip_codes_from_admins,
+        );
+        true
+    }
+
+    fn rebuild_approx_coord(&mut self) {
+        self.approx_coord = Some(self.coord.into());
+    }
+}
+
+impl UpdateAdmins for Addr {
+    fn update_admins(&mut self, changes: &AdminChanges) -> bool {
+        // the admins of an address are the ones of its street
+        let previous_admins = self.street.administrative_regions.clone();
+        if !self.street.update_admins(changes) {
+            return false;
+        }
+        let (street, house_number, country_codes) =
+            (&self.street, &self.house_number, &self.country_codes);
+        let name_and_label = |admins: &[Arc<Admin>]| {
+            labels::format_addr_name_and_label(
+                house_number,
+                &street.name,
+                admins_iter(admins),
+                country_codes,
+            )
+        };
+        let (previous_name, previous_label) = name_and_label(&previous_admins);
+        if self.name == previous_name && self.label == previous_label {
+            let (name, label) = name_and_label(&street.administrative_regions);
+            self.name = name;
+            self.label = label;
+        }
+        refresh(
+            &mut self.zip_codes,
+            &previous_admins,
+            &street.administrative_regions,
+            utils::get_zip_codes_from_admins,
+        );
+        true
+    }
+
+    fn rebuild_approx_coord(&mut self) {
+        self.approx_coord = Some(self.coord.into());
+        self.street.rebuild_approx_coord();
+    }
+}
+
+impl UpdateAdmins for Poi {
+    fn update_admins(&mut self, changes: &AdminChanges) -> bool {
+        let address_updated = match self.address {
+            Some(Address::Street(ref mut street)) => street.update_admins(changes),
+            Some(Address::Addr(ref mut addr)) => addr.update_admins(changes),
+            None => false,
+        };
+        let admins = match changes.update(&self.administrative_regions) {
+            Some(admins) => admins,
+            None => return address_updated,
+        };
+        let previous_admins = replace(&mut self.administrative_regions, admins);
+        let (name, country_codes) = (&self.name, &self.country_codes);
+        refresh(
+            &mut self.label,
+            &previous_admins,
+            &self.administrative_regions,
+            |admins| labels::format_poi_label(name, admins_iter(admins), country_codes),
+        );
+        refresh(
+            &mut self.zip_codes,
+            &previous_admins,
+            &self.administrative_regions,
+            utils::get_zip_codes_from_admins,
+        );
+        true
+    }
+
+    fn rebuild_approx_coord(&mut self) {
+        self.approx_coord = Some(self.coord.into());
+        match self.address {
+            Some(Address::Street(ref mut street)) => street.rebuild_approx_coord(),
+            Some(Address::Addr(ref mut addr)) => addr.rebuild_approx_coord(),
+            None => (),
+        }
+    }
+}
+
+impl UpdateAdmins for Stop {
+    fn update_admins(&mut self, changes: &AdminChanges) -> bool {
+        let admins = match changes.update(&self.administrative_regions) {
+            Some(admins) => admins,
+            None => return false,
+        };
+        let previous_admins = replace(&mut self.administrative_regions, admins);
+        let (name, country_codes) = (&self.name, &self.country_codes);
+        refresh(
+            &mut self.label,
+            &previous_admins,
+            &self.administrative_regions,
+            |admins| labels::format_stop_label(name, admins_iter(admins), country_codes),
+        );
+        refresh(
+            &mut self.zip_codes,
+            &previous_admins,
+            &self.administrative_regions,
+            utils::get_zip_codes_from_admins,
+        );
+        true
+    }
+
+    fn rebuild_approx_coord(&mut self) {
+        self.approx_coord = Some(self.coord.into());
+    }
+}
+
+impl UpdateAdmins for StopPoint {
+    fn update_admins(&mut self, changes: &AdminChanges) -> bool {
+        let admins = match changes.update(&self.administrative_regions) {
+            Some(admins) => admins,
+            None => return false,
+        };
+        let previous_admins = replace(&mut self.administrative_regions, admins);
+        let (name, country_codes) = (&self.name, &self.country_codes);
+        refresh(
+            &mut self.label,
+            &previous_admins,
+            &self.administrative_regions,
+            |admins| labels::format_stop_label(name, admins_iter(admins), country_codes),
+        );
+        refresh(
+            &mut self.zip_codes,
+            &previous_admins,
+            &self.administrative_regions,
+            utils::get_zip_codes_from_admins,
+        );
+        true
+    }
+
+    fn rebuild_approx_coord(&mut self) {
+        self.approx_coord = Some(self.coord.into());
+    }
+}
+
+/// the objects attached to one of the admins (the admins of an address are in its street)
+fn attached_to_admins_query(admin_ids: &[&str]) -> Query {
+    let terms = |field: &str| {
+        Query::build_terms(field)
+            .with_values(admin_ids.to_vec())
+            .build()
+    };
+    Query::build_bool()
+        .with_should(vec![
+            terms("administrative_regions.id"),
+            terms("street.administrative_regions.id"),
+        ])
+        .build()
+}
+
+/// update the objects `T` attached to the changed admins in the indexes published in `alias`
+///
+/// The updated objects replace the previous ones in the same indexes
+/// (the object ids do not change), page by page while the indexes are read.
+/// The objects are found by the ids of their admins, or, in the indexes where
+/// these ids are not indexed (the ones imported by a previous version),
+/// all the objects are read to find them.
+fn update_objects<T, B>(
+    backend: &mut B,
+    alias: &str,
+    changes: &AdminChanges,
+) -> Result<usize, Error>
+where
+    B: Backend,
+    T: MimirObject + UpdateAdmins + std::marker::Send + 'static,
+    for<'de> T: serde::de::Deserialize<'de> + std::fmt::Debug,
+{
+    let admin_ids: Vec<&str> = changes.changed.keys().map(|id| id.as_str()).collect();
+    // Note: only the published indexes are updated, not the previous generations
+    let indexes: Vec<String> = backend
+        .get_all_aliased_index(alias)?
+        .into_iter()
+        .filter(|&(_, ref aliases)| aliases.iter().any(|a| a == alias))
+        .map(|(index, _)| index)
+        .collect();
+    let mut nb_updated = 0;
+    for index in indexes {
+        let query = if backend.is_exact_field(&index, "administrative_regions.id")?
+            || backend.is_exact_field(&index, "street.administrative_regions.id")?
+        {
+            Some(attached_to_admins_query(&admin_ids))
+        } else {
+            warn!(
+                "the ids of the admins are not indexed in {}, all its objects are read",
+                index
+            );
+            None
+        };
+        let typed_index = TypedIndex::<T>::new(index.clone());
+        backend
+            .for_each_page(&index, query, |backend, objects: Vec<T>| {
+                let updated = objects.into_iter().filter_map(|mut object| {
+                    if object.update_admins(changes) {
+                        object.rebuild_approx_coord();
+                        Some(object)
+                    } else {
+                        None
+                    }
+                });
+                nb_updated += backend.bulk_index(&typed_index, updated)?;
+                Ok(())
+            })
+            .with_context(|_| format!("Error while updating the objects of {}", index))?;
+        backend.refresh_index(&index)?;
+    }
+    Ok(nb_updated)
+}
+
+/// update the admins copied in the published addresses, streets, pois, stops and stop points
+pub fn update_dependent_indexes<B: Backend>(
+    backend: &mut B,
+    changes: &AdminChanges,
+) -> Result<(), Error> {
+    if changes.is_empty() {
+        info!("no admin changed, there is nothing to update");
+        return Ok(());
+    }
+    info!(
+        "{} admins changed, updating the objects attached to them",
+        changes.len()
+    );
+    let nb_addrs = update_objects::<Addr, _>(backend, &get_main_type_index::<Addr>(), changes)?;
+    info!("{} addresses updated", nb_addrs);
+    let nb_streets =
+        update_objects::<Street, _>(backend, &get_main_type_index::<Street>(), changes)?;
+    info!("{} streets updated", nb_streets);
+    let nb_pois = update_objects::<Poi, _>(backend, &get_main_type_index::<Poi>(), changes)?;
+    info!("{} pois updated", nb_pois);
+    let nb_stops = update_objects::<Stop, _>(backend, &get_main_type_index::<Stop>(), changes)?
+        + update_objects::<Stop, _>(backend, GLOBAL_STOP_INDEX_NAME, changes)?;
+    info!("{} stops updated", nb_stops);
+    let nb_stop_points =
+        update_objects::<StopPoint, _>(backend, &get_main_type_index::<StopPoint>(), changes)?;
+    info!("{} stop points updated", nb_stop_points);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmogony::ZoneType;
+    use mimir::memory::MemoryBackend;
+    use mimir::rubber::IndexSettings;
+    use mimir::Coord;
+
+    fn admin(id: &str, name: &str, parent_id: Option<&str>, zone_type: ZoneType) -> Admin {
+        Admin {
+            id: id.to_string(),
+            name: name.to_string(),
+            label: name.to_string(),
+            zone_type: Some(zone_type),
+            parent_id: parent_id.map(|id| id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn street(name: &str, label: &str, admins: &[Admin]) -> Street {
+        Street {
+            name: name.to_string(),
+            label: label.to_string(),
+            administrative_regions: admins.iter().cloned().map(Arc::new).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn admin_ids(street: &Street) -> Vec<&str> {
+        street
+            .administrative_regions
+            .iter()
+            .map(|a| a.id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_renamed_admin() {
+        let previous = vec![
+            admin("admin:livry", "Livry", Some("admin:77"), ZoneType::City),
+            admin("admin:77", "Seine-et-Marne", None, ZoneType::StateDistrict),
+            admin("admin:old", "Old", None, ZoneType::City),
+        ];
+        let admins = vec![
+            admin(
+                "admin:livry",
+                "Livry-sur-Seine",
+                Some("admin:77"),
+                ZoneType::City,
+            ),
+            admin("admin:77", "Seine-et-Marne", None, ZoneType::StateDistrict),
+        ];
+        let changes = AdminChanges::new(previous.clone(), admins);
+        assert_eq!(changes.len(), 2);
+
+        let mut unchanged = street("Rue", "Rue (Seine-et-Marne)", &previous[1..2]);
+        assert!(!unchanged.update_admins(&changes));
+
+        let mut rue = street("Rue", "Rue (Livry)", &previous[..2]);
+        assert!(rue.update_admins(&changes));
+        assert_eq!(rue.label, "Rue (Livry-sur-Seine)");
+        assert_eq!(admin_ids(&rue), vec!["admin:livry", "admin:77"]);
+        assert_eq!(rue.administrative_regions[0].name, "Livry-sur-Seine");
+
+        // a label given by the source of the street is kept
+        let mut rue = street("Rue", "Rue (Livry les bains)", &previous[..2]);
+        assert!(rue.update_admins(&changes));
+        assert_eq!(rue.label, "Rue (Livry les bains)");
+
+        // the removed admins are removed from the streets
+        let mut rue = street("Rue", "Rue (Old)", &previous[2..]);
+        assert!(rue.update_admins(&changes));
+        assert!(rue.administrative_regions.is_empty());
+        assert_eq!(rue.label, "Rue");
+    }
+
+    #[test]
+    fn test_moved_admin() {
+        let previous = vec![
+            admin("admin:livry", "Livry", Some("admin:77"), ZoneType::City),
+            admin("admin:77", "Seine-et-Marne", None, ZoneType::StateDistrict),
+            admin("admin:91", "Essonne", None, ZoneType::StateDistrict),
+        ];
+        let admins = vec![
+            admin("admin:livry", "Livry", Some("admin:91"), ZoneType::City),
+            admin("admin:77", "Seine-et-Marne", None, ZoneType::StateDistrict),
+            admin("admin:91", "Essonne", None, ZoneType::StateDistrict),
+        ];
+        let changes = AdminChanges::new(previous.clone(), admins);
+        assert_eq!(changes.len(), 1);
+
+        // the hierarchy of the city is taken in the new admins
+        let mut rue = street("Rue", "Rue (Livry)", &previous[..2]);
+        assert!(rue.update_admins(&changes));
+        assert_eq!(admin_ids(&rue), vec!["admin:livry", "admin:91"]);
+        assert_eq!(rue.label, "Rue (Livry)");
+    }
+
+    #[test]
+    fn test_updated_objects_keep_their_approx_coord() {
+        let livry = admin("admin:livry", "Livry", None, ZoneType::City);
+        let mut rue = street("Rue", "Rue (Livry)", &[livry.clone()]);
+        rue.id = "street:1".to_string();
+        rue.coord = Coord::new(2.68, 48.51);
+        rue.approx_coord = Some(rue.coord.into());
+        let point = rue.approx_coord.clone().unwrap();
+        let mut backend = MemoryBackend::new();
+        let settings = IndexSettings {
+            nb_shards: 1,
+            nb_replicas: 0,
+        };
+        backend
+            .public_index("fr", &settings, std::iter::once(rue))
+            .unwrap();
+
+        let renamed = admin("admin:livry", "Livry-sur-Seine", None, ZoneType::City);
+        let changes = AdminChanges::new(vec![livry], vec![renamed]);
+        let nb = update_objects::<Street, _>(&mut backend, "munin_street", &changes).unwrap();
+        assert_eq!(nb, 1);
+
+        // the updated street is still found by its approximate coordinates
+        let query = Query::build_geo_shape("approx_coord")
+            .with_geojson(point)
+            .build();
+        let streets: Vec<Street> = backend.get_objects_by_query("munin_street", query).unwrap();
+        assert_eq!(streets.len(), 1);
+        assert_eq!(streets[0].label, "Rue (Livry-sur-Seine)");
+    }
+}
@@ -30,11 +30,14 @@
 
 use failure::ensure;
 use lazy_static::lazy_static;
+use mimir::backend::Backend;
 use mimir::objects::Admin;
-use mimir::rubber::{IndexSettings, Rubber};
+use mimir::rubber::IndexSettings;
 use mimirsbrunn::addr_reader::{import_addresses_from_files, import_addresses_from_streams};
 use mimirsbrunn::admin_geofinder::AdminGeoFinder;
+use mimirsbrunn::checkpoint::Checkpoint;
 use mimirsbrunn::labels;
+use mimirsbrunn::utils::{ExportArgs, PublishArgs};
 use serde::{Deserialize, Serialize};
 use slog_scope::{info, warn};
 use std::collections::BTreeMap;
@@ -130,6 +133,7 @@ impl Bano {
             zip_codes: vec![self.zip.clone()],
             coord: coord.clone(),
             approx_coord: None,
+            geometry: None,
             distance: None,
             country_codes: country_codes.clone(),
             context: None,
@@ -167,6 +171,7 @@ impl Bano {
             distance: None,
             country_codes,
             context: None,
+            interpolated: false,
         })
     }
 }
@@ -212,20 +217,45 @@ struct Args {
     /// therefore, different addresses with the same position will disappear.
     #[structopt(long = "use-old-index-format")]
     use_old_index_format: bool,
+    /// File where the progress of the import is saved, so that it can be resumed
+    /// with `--resume` if it is interrupted (the progress is not saved without it).
+    #[structopt(long = "state-file", parse(from_os_str))]
+    state_file: Option<PathBuf>,
+    /// Resume the import saved in the state file, instead of starting a new one.
+    /// The input must be the same as the one of the interrupted import.
+    #[structopt(long = "resume", requires = "state_file", conflicts_with = "output")]
+    resume: bool,
+    #[structopt(flatten)]
+    publish: PublishArgs,
+    #[structopt(flatten)]
+    export: ExportArgs,
 }
 
 fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
+    match args.export.backend(&args.connection_string)? {
+        Some(backend) => {
+            import(args, backend.clone())?;
+            backend.finish()
+        }
+        None => {
+            let rubber = args.publish.rubber(&args.connection_string)?;
+            import(args, rubber)
+        }
+    }
+}
+
+fn import<B: Backend>(args: Args, mut backend: B) -> Result<(), mimirsbrunn::Error> {
     info!("importing bano into Mimir");
 
-    let mut rubber = Rubber::new(&args.connection_string);
     let index_settings = IndexSettings {
         nb_shards: args.nb_shards,
         nb_replicas: args.nb_replicas,
     };
+    let mut checkpoint = Checkpoint::new(args.state_file.clone(), args.resume)?;
 
     // Fetch and index admins for `into_addr`
     let into_addr = {
-        let admins = rubber.get_all_admins().unwrap_or_else(|err| {
+        let admins = backend.get_all_admins().unwrap_or_else(|err| {
             warn!(
                 "Administratives regions not found in es db for dataset {}. (error: {})",
                 &args.dataset, err
@@ -248,12 +278,13 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
         move |b: Bano| b.into_addr(&admins_by_insee, &admins_geofinder, use_old_index_format)
     };
 
-    if let Some(input_path) = args.input {
+    let imported = if let Some(input_path) = args.input {
         // Import from file(s)
         if input_path.is_dir() {
             let paths: std::fs::ReadDir = fs::read_dir(&input_path)?;
             import_addresses_from_files(
-                &mut rubber,
+                &mut backend,
+                &mut checkpoint,
                 false,
                 args.nb_threads,
                 index_settings,
@@ -263,7 +294,8 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
             )
         } else {
             import_addresses_from_files(
-                &mut rubber,
+                &mut backend,
+                &mut checkpoint,
                 false,
                 args.nb_threads,
                 index_settings,
@@ -275,15 +307,18 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
     } else {
         // Import from stdin
         import_addresses_from_streams(
-            &mut rubber,
+            &mut backend,
+            &mut checkpoint,
             false,
             args.nb_threads,
             index_settings,
             &args.dataset,
-            std::iter::once(stdin()),
+            std::iter::once(("stdin".to_string(), stdin())),
             into_addr,
         )
-    }
+    };
+    imported?;
+    checkpoint.finish()
 }
 fn main() {
     mimirsbrunn::utils::launch_run(run);
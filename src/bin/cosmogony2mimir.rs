@@ -29,12 +29,15 @@
 // www.navitia.io
 
 use cosmogony::{Zone, ZoneIndex};
-use failure::Error;
+use failure::{Error, ResultExt};
+use mimir::backend::Backend;
 use mimir::objects::Admin;
-use mimir::rubber::{IndexSettings, Rubber};
+use mimir::rubber::IndexSettings;
+use mimirsbrunn::admin_update::{self, AdminChanges};
 use mimirsbrunn::osm_reader::admin;
 use mimirsbrunn::osm_reader::osm_utils;
 use mimirsbrunn::utils;
+use mimirsbrunn::utils::{ExportArgs, PublishArgs};
 use slog_scope::{info, warn};
 use std::collections::BTreeMap;
 use std::collections::HashMap;
@@ -150,12 +153,41 @@ impl IntoAdmin for Zone {
 fn send_to_es(
     admins: impl Iterator<Item = Admin>,
     cnx_string: &str,
+    export: &ExportArgs,
+    publish: &PublishArgs,
     dataset: &str,
     index_settings: IndexSettings,
+    update_dependent_indexes: bool,
 ) -> Result<(), Error> {
-    let mut rubber = Rubber::new(cnx_string);
-    rubber.initialize_templates()?;
-    let nb_admins = rubber.public_index(dataset, &index_settings, admins)?;
+    let nb_admins = match export.backend(cnx_string)? {
+        Some(mut backend) => {
+            if update_dependent_indexes {
+                warn!("the dependent indexes are not updated when the admins are exported");
+            }
+            let nb_admins = backend.public_index(dataset, &index_settings, admins)?;
+            backend.finish()?;
+            nb_admins
+        }
+        None => {
+            let mut rubber = publish.rubber(cnx_string)?;
+            rubber.initialize_templates()?;
+            let previous_admins = if update_dependent_indexes {
+                rubber.get_all_admins().unwrap_or_else(|_| {
+                    warn!("no administrative regions published before, there is nothing to update");
+                    vec![]
+                })
+            } else {
+                vec![]
+            };
+            let nb_admins = rubber.public_index(dataset, &index_settings, admins)?;
+            if update_dependent_indexes {
+                let changes = AdminChanges::new(previous_admins, rubber.get_all_admins()?);
+                admin_update::update_dependent_indexes(&mut rubber, &changes)
+                    .context("Error while updating the admins of the dependent indexes")?;
+            }
+            nb_admins
+        }
+    };
     info!("{} admins added.", nb_admins);
     Ok(())
 }
@@ -214,8 +246,11 @@ fn index_cosmogony(args: Args) -> Result<(), Error> {
     send_to_es(
         admins,
         &args.connection_string,
+        &args.export,
+        &args.publish,
         &args.dataset,
         index_settings,
+        args.update_dependent_indexes,
     )?;
 
     Ok(())
@@ -250,6 +285,14 @@ struct Args {
     /// instead of 'admin:osm:{osm_id}'
     #[structopt(long = "french-id-retrocompatibility")]
     french_id_retrocompatibility: bool,
+    /// Update the admins copied in the published addresses, streets, pois and stops
+    /// when their name, label, zip codes or parent changed, without importing them again.
+    #[structopt(long = "update-dependent-indexes")]
+    update_dependent_indexes: bool,
+    #[structopt(flatten)]
+    publish: PublishArgs,
+    #[structopt(flatten)]
+    export: ExportArgs,
 }
 
 fn main() {
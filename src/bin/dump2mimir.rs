@@ -0,0 +1,126 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use failure::ResultExt;
+use flate2::read::GzDecoder;
+use mimir::rubber::{IndexSettings, IndexVisibility};
+use mimirsbrunn::dump::load_dump;
+use mimirsbrunn::utils::{MergeArgs, PublishArgs};
+use slog_scope::info;
+use std::fs::File;
+use std::io::{stdin, BufRead, BufReader, Read};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+struct Args {
+    /// Newline delimited json files of the documents, as written by the importers with
+    /// `--output-format=ndjson` (compressed with gzip if their extension is gz).
+    /// If this is left empty, the documents are read from standard input.
+    #[structopt(short = "i", long = "input", parse(from_os_str))]
+    input: Vec<PathBuf>,
+    /// Elasticsearch parameters.
+    #[structopt(
+        short = "c",
+        long = "connection-string",
+        default_value = "http://localhost:9200/munin"
+    )]
+    connection_string: String,
+    /// Name of the dataset.
+    #[structopt(short = "d", long = "dataset", default_value = "fr")]
+    dataset: String,
+    /// Name of the dataset of the addresses (the dataset of the other documents by default).
+    /// osm2mimir imports its addresses in `osm_<dataset>` by default, so that they do not
+    /// replace the other address imports: its documents must be loaded with the same dataset.
+    #[structopt(long = "address-dataset")]
+    address_dataset: Option<String>,
+    /// Indicate if the indexes of the dataset are private
+    #[structopt(short = "p", long = "private")]
+    private: bool,
+    /// Number of shards for the es indexes
+    #[structopt(short = "s", long = "nb-shards", default_value = "1")]
+    nb_shards: usize,
+    /// Number of replicas for the es indexes
+    #[structopt(short = "r", long = "nb-replicas", default_value = "1")]
+    nb_replicas: usize,
+    #[structopt(flatten)]
+    publish: PublishArgs,
+    #[structopt(flatten)]
+    merge: MergeArgs,
+}
+
+fn open(path: &PathBuf) -> Result<Box<dyn Read>, failure::Error> {
+    let file = File::open(path).with_context(|_| format!("impossible to open {:?}", path))?;
+    if path.extension().map_or(false, |ext| ext == "gz") {
+        Ok(Box::new(GzDecoder::new(file)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+fn run(args: Args) -> Result<(), failure::Error> {
+    let visibility = if args.private {
+        IndexVisibility::Private
+    } else {
+        IndexVisibility::Public
+    };
+    let index_settings = IndexSettings {
+        nb_shards: args.nb_shards,
+        nb_replicas: args.nb_replicas,
+    };
+    let dump: Box<dyn BufRead> = if args.input.is_empty() {
+        Box::new(BufReader::new(stdin()))
+    } else {
+        let files = args.input.iter().map(open).collect::<Result<Vec<_>, _>>()?;
+        Box::new(BufReader::new(files.into_iter().fold(
+            Box::new(std::io::empty()) as Box<dyn Read>,
+            |dump, file| Box::new(dump.chain(file)),
+        )))
+    };
+
+    let address_dataset = args.address_dataset.as_ref().unwrap_or(&args.dataset);
+    let mut rubber = args.publish.rubber(&args.connection_string)?;
+    rubber.initialize_templates()?;
+    let nb = load_dump(
+        &mut rubber,
+        dump,
+        &args.dataset,
+        address_dataset,
+        &index_settings,
+        visibility,
+        &args.merge,
+    )?;
+    info!("{} documents loaded in {}", nb, args.dataset);
+    Ok(())
+}
+
+fn main() {
+    mimirsbrunn::utils::launch_run(run);
+}
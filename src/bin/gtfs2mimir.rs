@@ -0,0 +1,124 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use failure::ResultExt;
+use mimir::rubber::IndexSettings;
+use mimirsbrunn::stops::*;
+use mimirsbrunn::utils::{ExportArgs, MergeArgs, PublishArgs};
+use slog_scope::info;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct Args {
+    /// GTFS zip file or directory.
+    #[structopt(short = "i", long = "input", parse(from_os_str))]
+    input: PathBuf,
+    /// Transit_model configuration file of the feed (json with its contributor and dataset),
+    /// a default contributor is used if none is given.
+    #[structopt(long = "config", parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// Prefix added to the ids of the GTFS objects,
+    /// to not merge the stops of several feeds with the same ids.
+    #[structopt(short = "p", long = "prefix")]
+    prefix: Option<String>,
+    /// Name of the dataset.
+    #[structopt(short = "d", long = "dataset", default_value = "fr")]
+    dataset: String,
+    /// Elasticsearch parameters.
+    #[structopt(
+        short = "c",
+        long = "connection-string",
+        default_value = "http://localhost:9200/munin"
+    )]
+    connection_string: String,
+    /// Number of shards for the es index
+    #[structopt(short = "s", long = "nb-shards", default_value = "1")]
+    nb_shards: usize,
+    /// Number of replicas for the es index
+    #[structopt(short = "r", long = "nb-replicas", default_value = "1")]
+    nb_replicas: usize,
+    /// Also index the stop points, attached to their stop area.
+    #[structopt(long = "stop-points")]
+    stop_points: bool,
+    #[structopt(flatten)]
+    publish: PublishArgs,
+    #[structopt(flatten)]
+    merge: MergeArgs,
+    #[structopt(flatten)]
+    export: ExportArgs,
+}
+
+fn main() {
+    mimirsbrunn::utils::launch_run(run);
+}
+
+fn run(args: Args) -> Result<(), transit_model::Error> {
+    info!("Launching gtfs2mimir...");
+
+    let model = if args.input.is_file() {
+        transit_model::gtfs::read_from_zip(&args.input, args.config.as_ref(), args.prefix.clone())
+    } else {
+        transit_model::gtfs::read_from_path(&args.input, args.config.as_ref(), args.prefix.clone())
+    }
+    .with_context(|_| format!("Error occurred when reading the gtfs {:?}", args.input))?;
+    let objects = PtObjects {
+        stops: stops_from_transit_model(&model),
+        stop_points: if args.stop_points {
+            stop_points_from_transit_model(&model)
+        } else {
+            vec![]
+        },
+        lines: lines_from_transit_model(&model),
+        networks: networks_from_transit_model(&model),
+    };
+
+    let index_settings = IndexSettings {
+        nb_shards: args.nb_shards,
+        nb_replicas: args.nb_replicas,
+    };
+
+    import_stops(
+        objects,
+        &args.connection_string,
+        &args.export,
+        &args.publish,
+        &args.merge,
+        &args.dataset,
+        index_settings,
+    )
+    .with_context(|_| {
+        format!(
+            "Error occurred when importing stops into {} on {}",
+            args.dataset, args.connection_string
+        )
+    })?;
+    Ok(())
+}
@@ -0,0 +1,257 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use failure::{bail, ResultExt};
+use mimir::backend::Backend;
+use mimir::objects::{AliasOperation, AliasParameter};
+use mimir::rubber::Rubber;
+use mimir::{Addr, Admin, Poi, PtLine, PtNetwork, Stop, StopPoint, Street};
+use slog_scope::{info, warn};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// the snapshotted indexes are all the munin indexes
+const MUNIN_INDEXES: &str = "munin";
+
+#[derive(StructOpt, Debug)]
+struct Args {
+    /// Elasticsearch parameters.
+    #[structopt(
+        short = "c",
+        long = "connection-string",
+        default_value = "http://localhost:9200/"
+    )]
+    connection_string: String,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Snapshot all the munin indexes, and save their aliases in a local file.
+    #[structopt(name = "snapshot")]
+    Snapshot(SnapshotArgs),
+    /// Restore the munin indexes of a snapshot and switch all the aliases to them at once.
+    /// The current munin indexes are then deleted.
+    #[structopt(name = "restore")]
+    Restore(SnapshotArgs),
+    /// Move the aliases of the indexes of a dataset back to their previous generation,
+    /// kept by the importers with `--keep-previous`.
+    #[structopt(name = "rollback")]
+    Rollback(RollbackArgs),
+}
+
+#[derive(StructOpt, Debug)]
+struct SnapshotArgs {
+    /// Name of the snapshot.
+    #[structopt(short = "s", long = "snapshot")]
+    snapshot: String,
+    /// Name of the snapshot repository.
+    #[structopt(long = "repository", default_value = "mimir")]
+    repository: String,
+    /// If given, the repository is (re)registered as a shared file system repository
+    /// at this location. It has to be listed in the `path.repo` setting of all the es nodes.
+    #[structopt(long = "location")]
+    location: Option<String>,
+    /// File of the aliases of the snapshotted indexes
+    /// (`<snapshot>.aliases.json` by default).
+    #[structopt(long = "aliases", parse(from_os_str))]
+    aliases: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+struct RollbackArgs {
+    /// Name of the dataset.
+    #[structopt(short = "d", long = "dataset")]
+    dataset: String,
+    /// Types of the indexes to roll back
+    /// (admin, street, addr, poi, stop, stop_point, line or network).
+    /// If none is given, all the indexes of the dataset are rolled back.
+    #[structopt(short = "t", long = "type")]
+    types: Vec<String>,
+}
+
+impl SnapshotArgs {
+    fn aliases_path(&self) -> PathBuf {
+        self.aliases
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("{}.aliases.json", self.snapshot)))
+    }
+
+    fn register_repository(&self, rubber: &Rubber) -> Result<(), failure::Error> {
+        if let Some(ref location) = self.location {
+            info!("registering repository {} at {}", self.repository, location);
+            rubber.create_snapshot_repository(&self.repository, location)?;
+        }
+        Ok(())
+    }
+}
+
+fn snapshot(rubber: &Rubber, args: &SnapshotArgs) -> Result<(), failure::Error> {
+    args.register_repository(rubber)?;
+    let layout = rubber.get_all_aliased_index(MUNIN_INDEXES)?;
+    if layout.is_empty() {
+        bail!("there is no munin index to snapshot");
+    }
+    let indexes: Vec<String> = layout.keys().cloned().collect();
+    info!("snapshotting {:?} in {}", indexes, args.snapshot);
+    rubber.create_snapshot(&args.repository, &args.snapshot, &indexes)?;
+
+    let path = args.aliases_path();
+    let file = File::create(&path).with_context(|_| format!("impossible to create {:?}", path))?;
+    serde_json::to_writer_pretty(file, &layout)
+        .with_context(|_| format!("impossible to write the aliases in {:?}", path))?;
+    info!("aliases of the snapshot written in {:?}", path);
+    Ok(())
+}
+
+fn restore(rubber: &mut Rubber, args: &SnapshotArgs) -> Result<(), failure::Error> {
+    args.register_repository(rubber)?;
+    let path = args.aliases_path();
+    let file = File::open(&path).with_context(|_| format!("impossible to open {:?}", path))?;
+    let layout: BTreeMap<String, Vec<String>> = serde_json::from_reader(file)
+        .with_context(|_| format!("invalid aliases file {:?}", path))?;
+    if layout.is_empty() {
+        bail!("no index in the aliases file {:?}", path);
+    }
+
+    // the snapshotted indexes are restored next to the current ones,
+    // their names keep the prefix of their dataset to be replaced by the next imports
+    let suffix = chrono::Utc::now().format("%Y%m%d_%H%M%S_%f").to_string();
+    let restored_name = |index: &str| format!("{}_restored_{}", index, suffix);
+    let current = rubber.get_all_aliased_index(MUNIN_INDEXES)?;
+    let indexes: Vec<String> = layout.keys().cloned().collect();
+    info!("restoring {:?} from {}", indexes, args.snapshot);
+    rubber.restore_snapshot(
+        &args.repository,
+        &args.snapshot,
+        &indexes,
+        &restored_name("$1"),
+    )?;
+
+    // all the aliases are moved in one request, so the searches never see a partial restore
+    let remove = current.iter().flat_map(|(index, aliases)| {
+        aliases.iter().map(move |alias| AliasOperation {
+            add: None,
+            remove: Some(AliasParameter {
+                index: index.clone(),
+                alias: alias.clone(),
+            }),
+        })
+    });
+    let add = layout.iter().flat_map(|(index, aliases)| {
+        let restored = restored_name(index);
+        aliases.iter().map(move |alias| AliasOperation {
+            add: Some(AliasParameter {
+                index: restored.clone(),
+                alias: alias.clone(),
+            }),
+            remove: None,
+        })
+    });
+    rubber
+        .update_aliases(remove.chain(add).collect())
+        .context("impossible to move the aliases to the restored indexes")?;
+
+    for index in current.keys() {
+        rubber
+            .delete_index(index)
+            .with_context(|_| format!("Error occurred when deleting index: {}", index))?;
+    }
+    info!("snapshot {} restored", args.snapshot);
+    Ok(())
+}
+
+fn rollback_index(
+    rubber: &mut Rubber,
+    doc_type: &str,
+    dataset: &str,
+) -> Result<Option<String>, failure::Error> {
+    match doc_type {
+        "admin" => rubber.rollback_index::<Admin>(dataset),
+        "street" => rubber.rollback_index::<Street>(dataset),
+        "addr" => rubber.rollback_index::<Addr>(dataset),
+        "poi" => rubber.rollback_index::<Poi>(dataset),
+        "stop" => rubber.rollback_index::<Stop>(dataset),
+        "stop_point" => rubber.rollback_index::<StopPoint>(dataset),
+        "line" => rubber.rollback_index::<PtLine>(dataset),
+        "network" => rubber.rollback_index::<PtNetwork>(dataset),
+        _ => bail!("unknown index type {}", doc_type),
+    }
+}
+
+fn rollback(rubber: &mut Rubber, args: &RollbackArgs) -> Result<(), failure::Error> {
+    let types: Vec<&str> = if args.types.is_empty() {
+        vec![
+            "admin",
+            "street",
+            "addr",
+            "poi",
+            "stop",
+            "stop_point",
+            "line",
+            "network",
+        ]
+    } else {
+        args.types.iter().map(String::as_str).collect()
+    };
+    let mut nb_rolled_back = 0;
+    for doc_type in types {
+        match rollback_index(rubber, doc_type, &args.dataset)? {
+            Some(index) => {
+                info!("{} of {} rolled back to {}", doc_type, args.dataset, index);
+                nb_rolled_back += 1;
+            }
+            None => warn!(
+                "no previous {} index of {} to roll back to",
+                doc_type, args.dataset
+            ),
+        }
+    }
+    if nb_rolled_back == 0 {
+        bail!("nothing to roll back for {}", args.dataset);
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), failure::Error> {
+    let mut rubber = Rubber::new(&args.connection_string);
+    match args.command {
+        Command::Snapshot(ref snapshot_args) => snapshot(&rubber, snapshot_args),
+        Command::Restore(ref restore_args) => restore(&mut rubber, restore_args),
+        Command::Rollback(ref rollback_args) => rollback(&mut rubber, rollback_args),
+    }
+}
+
+fn main() {
+    mimirsbrunn::utils::launch_run(run);
+}
@@ -31,11 +31,10 @@
 use failure::ResultExt;
 use mimir::rubber::IndexSettings;
 use mimirsbrunn::stops::*;
+use mimirsbrunn::utils::{ExportArgs, MergeArgs, PublishArgs};
 use slog_scope::{info, warn};
 use std::path::PathBuf;
 use structopt::StructOpt;
-use transit_model::objects as navitia;
-use transit_model_collection::Idx;
 
 #[derive(Debug, StructOpt)]
 struct Args {
@@ -61,104 +60,15 @@ struct Args {
     /// Number of replicas for the es index
     #[structopt(short = "r", long = "nb-replicas", default_value = "1")]
     nb_replicas: usize,
-}
-
-fn get_lines(idx: Idx<navitia::StopArea>, navitia: &transit_model::Model) -> Vec<mimir::Line> {
-    use humanesort::HumaneSortable;
-    use mimir::FromTransitModel;
-    let mut lines: Vec<_> = navitia
-        .get_corresponding_from_idx(idx)
-        .into_iter()
-        .map(|l_idx| mimir::Line::from_transit_model(l_idx, navitia))
-        .collect();
-
-    // we want the lines to be sorted in a way where
-    // line-3 is before line-11, so be use a humane_sort
-    lines.humane_sort();
-    lines
-}
-
-fn to_mimir(
-    idx: Idx<navitia::StopArea>,
-    stop_area: &navitia::StopArea,
-    navitia: &transit_model::Model,
-) -> mimir::Stop {
-    let commercial_modes = navitia
-        .get_corresponding_from_idx(idx)
-        .into_iter()
-        .map(|cm_idx| mimir::CommercialMode {
-            id: mimir::objects::normalize_id(
-                "commercial_mode",
-                &navitia.commercial_modes[cm_idx].id,
-            ),
-            name: navitia.commercial_modes[cm_idx].name.clone(),
-        })
-        .collect();
-    let physical_modes = navitia
-        .get_corresponding_from_idx(idx)
-        .into_iter()
-        .map(|pm_idx| mimir::PhysicalMode {
-            id: mimir::objects::normalize_id("physical_mode", &navitia.physical_modes[pm_idx].id),
-            name: navitia.physical_modes[pm_idx].name.clone(),
-        })
-        .collect();
-    let comments = navitia
-        .comments
-        .iter_from(&stop_area.comment_links)
-        .map(|comment| mimir::Comment {
-            name: comment.name.clone(),
-        })
-        .collect();
-    let feed_publishers = navitia
-        .get_corresponding_from_idx(idx)
-        .into_iter()
-        .map(|contrib_idx| mimir::FeedPublisher {
-            id: navitia.contributors[contrib_idx].id.clone(),
-            name: navitia.contributors[contrib_idx].name.clone(),
-            license: navitia.contributors[contrib_idx]
-                .license
-                .clone()
-                .unwrap_or_else(|| "".into()),
-            url: navitia.contributors[contrib_idx]
-                .website
-                .clone()
-                .unwrap_or_else(|| "".into()),
-        })
-        .collect();
-    let coord = mimir::Coord::new(stop_area.coord.lon, stop_area.coord.lat);
-
-    let lines = get_lines(idx, navitia);
-
-    mimir::Stop {
-        id: mimir::objects::normalize_id("stop_area", &stop_area.id),
-        label: stop_area.name.clone(),
-        name: stop_area.name.clone(),
-        coord: coord.clone(),
-        approx_coord: Some(coord.into()),
-        commercial_modes: commercial_modes,
-        physical_modes: physical_modes,
-        lines: lines,
-        comments: comments,
-        timezone: stop_area.timezone.clone().unwrap_or(format!("")),
-        codes: stop_area
-            .codes
-            .iter()
-            .map(|&(ref t, ref v)| mimir::Code {
-                name: t.clone(),
-                value: v.clone(),
-            })
-            .collect(),
-        properties: stop_area
-            .object_properties
-            .iter()
-            .map(|&(ref k, ref v)| mimir::Property {
-                key: k.clone(),
-                value: v.clone(),
-            })
-            .collect(),
-        feed_publishers: feed_publishers,
-        ..Default::default()
-    }
+    /// Also index the stop points, attached to their stop area.
+    #[structopt(long = "stop-points")]
+    stop_points: bool,
+    #[structopt(flatten)]
+    publish: PublishArgs,
+    #[structopt(flatten)]
+    merge: MergeArgs,
+    #[structopt(flatten)]
+    export: ExportArgs,
 }
 
 fn main() {
@@ -173,23 +83,16 @@ fn run(args: Args) -> Result<(), transit_model::Error> {
     }
 
     let navitia = transit_model::ntfs::read(&args.input)?;
-    let nb_stop_points = navitia
-        .stop_areas
-        .iter()
-        .map(|(idx, sa)| {
-            let id = mimir::objects::normalize_id("stop_area", &sa.id);
-            let nb_stop_points = navitia
-                .get_corresponding_from_idx::<_, navitia::StopPoint>(idx)
-                .len();
-            (id, nb_stop_points as u32)
-        })
-        .collect();
-    let mut stops: Vec<mimir::Stop> = navitia
-        .stop_areas
-        .iter()
-        .map(|(idx, sa)| to_mimir(idx, sa, &navitia))
-        .collect();
-    initialize_weights(stops.iter_mut(), &nb_stop_points);
+    let objects = PtObjects {
+        stops: stops_from_transit_model(&navitia),
+        stop_points: if args.stop_points {
+            stop_points_from_transit_model(&navitia)
+        } else {
+            vec![]
+        },
+        lines: lines_from_transit_model(&navitia),
+        networks: networks_from_transit_model(&navitia),
+    };
 
     let index_settings = IndexSettings {
         nb_shards: args.nb_shards,
@@ -197,8 +100,11 @@ fn run(args: Args) -> Result<(), transit_model::Error> {
     };
 
     import_stops(
-        stops,
+        objects,
         &args.connection_string,
+        &args.export,
+        &args.publish,
+        &args.merge,
         &args.dataset,
         index_settings,
     )
@@ -220,6 +126,20 @@ fn test_bad_connection_string() {
         city_level: None,
         nb_replicas: 1,
         nb_shards: 1,
+        stop_points: false,
+        publish: PublishArgs {
+            keep_previous: 0,
+            max_drop_percent: None,
+            smoke_queries: None,
+        },
+        merge: MergeArgs {
+            merge_distance: None,
+            merge_name_similarity: 0.8,
+        },
+        export: ExportArgs {
+            output: None,
+            output_format: mimir::export::ExportFormat::NdJson,
+        },
     };
     let causes = run(args)
         .unwrap_err()
@@ -246,6 +166,20 @@ fn test_bad_file() {
         city_level: None,
         nb_replicas: 1,
         nb_shards: 1,
+        stop_points: false,
+        publish: PublishArgs {
+            keep_previous: 0,
+            max_drop_percent: None,
+            smoke_queries: None,
+        },
+        merge: MergeArgs {
+            merge_distance: None,
+            merge_name_similarity: 0.8,
+        },
+        export: ExportArgs {
+            output: None,
+            output_format: mimir::export::ExportFormat::NdJson,
+        },
     };
     let causes = run(args)
         .unwrap_err()
@@ -29,9 +29,12 @@
 // www.navitia.io
 
 use lazy_static::lazy_static;
-use mimir::rubber::{IndexSettings, Rubber};
+use mimir::backend::Backend;
+use mimir::rubber::IndexSettings;
 use mimirsbrunn::addr_reader::{import_addresses_from_files, import_addresses_from_streams};
 use mimirsbrunn::admin_geofinder::AdminGeoFinder;
+use mimirsbrunn::checkpoint::Checkpoint;
+use mimirsbrunn::utils::{ExportArgs, PublishArgs};
 use mimirsbrunn::{labels, utils};
 use serde::{Deserialize, Serialize};
 use slog_scope::{info, warn};
@@ -97,6 +100,7 @@ impl OpenAddress {
             zip_codes: vec![self.postcode.clone()],
             coord: coord.clone(),
             approx_coord: None,
+            geometry: None,
             distance: None,
             country_codes: country_codes.clone(),
             context: None,
@@ -135,6 +139,7 @@ impl OpenAddress {
             distance: None,
             country_codes,
             context: None,
+            interpolated: false,
         })
     }
 }
@@ -175,24 +180,49 @@ struct Args {
     /// therefore, different addresses with the same position will disappear.
     #[structopt(long = "use-old-index-format")]
     use_old_index_format: bool,
+    /// File where the progress of the import is saved, so that it can be resumed
+    /// with `--resume` if it is interrupted (the progress is not saved without it).
+    #[structopt(long = "state-file", parse(from_os_str))]
+    state_file: Option<PathBuf>,
+    /// Resume the import saved in the state file, instead of starting a new one.
+    /// The input must be the same as the one of the interrupted import.
+    #[structopt(long = "resume", requires = "state_file", conflicts_with = "output")]
+    resume: bool,
+    #[structopt(flatten)]
+    publish: PublishArgs,
+    #[structopt(flatten)]
+    export: ExportArgs,
 }
 
 fn run(args: Args) -> Result<(), failure::Error> {
+    match args.export.backend(&args.connection_string)? {
+        Some(backend) => {
+            import(args, backend.clone())?;
+            backend.finish()
+        }
+        None => {
+            let rubber = args.publish.rubber(&args.connection_string)?;
+            import(args, rubber)
+        }
+    }
+}
+
+fn import<B: Backend>(args: Args, mut backend: B) -> Result<(), failure::Error> {
     info!("importing open addresses into Mimir");
 
     if args.city_level.is_some() {
         warn!("city-level option is deprecated, it now has no effect.");
     }
 
-    let mut rubber = Rubber::new(&args.connection_string);
     let index_settings = IndexSettings {
         nb_shards: args.nb_shards,
         nb_replicas: args.nb_replicas,
     };
+    let mut checkpoint = Checkpoint::new(args.state_file.clone(), args.resume)?;
 
     // Fetch and index admins for `into_addr`
     let into_addr = {
-        let admins = rubber.get_all_admins().unwrap_or_else(|err| {
+        let admins = backend.get_all_admins().unwrap_or_else(|err| {
             warn!(
                 "Administratives regions not found in es db for dataset {}. (error: {})",
                 &args.dataset, err
@@ -205,7 +235,7 @@ fn run(args: Args) -> Result<(), failure::Error> {
         move |a: OpenAddress| a.into_addr(&admins_geofinder, use_old_index_format)
     };
 
-    if let Some(input_path) = args.input {
+    let imported = if let Some(input_path) = args.input {
         // Import from file(s)
         if input_path.is_dir() {
             let paths = walkdir::WalkDir::new(&input_path);
@@ -225,7 +255,8 @@ fn run(args: Args) -> Result<(), failure::Error> {
                 });
 
             import_addresses_from_files(
-                &mut rubber,
+                &mut backend,
+                &mut checkpoint,
                 true,
                 args.nb_threads,
                 index_settings,
@@ -235,7 +266,8 @@ fn run(args: Args) -> Result<(), failure::Error> {
             )
         } else {
             import_addresses_from_files(
-                &mut rubber,
+                &mut backend,
+                &mut checkpoint,
                 true,
                 args.nb_threads,
                 index_settings,
@@ -247,15 +279,18 @@ fn run(args: Args) -> Result<(), failure::Error> {
     } else {
         // Import from stdin
         import_addresses_from_streams(
-            &mut rubber,
+            &mut backend,
+            &mut checkpoint,
             true,
             args.nb_threads,
             index_settings,
             &args.dataset,
-            std::iter::once(stdin()),
+            std::iter::once(("stdin".to_string(), stdin())),
             into_addr,
         )
-    }
+    };
+    imported?;
+    checkpoint.finish()
 }
 
 fn main() {
@@ -28,22 +28,44 @@
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
 
-use failure::ResultExt;
-use mimir::rubber::{IndexSettings, Rubber};
+use failure::{err_msg, ResultExt};
+use mimir::backend::Backend;
+use mimir::rubber::{get_main_type_and_dataset_index, IndexSettings, Rubber, SearchRequest};
+use mimir::{Addr, Admin, Poi, Street};
+use mimirsbrunn::addr_reader::index_addresses;
 use mimirsbrunn::admin_geofinder::AdminGeoFinder;
-use mimirsbrunn::osm_reader::admin::read_administrative_regions;
+use mimirsbrunn::checkpoint::Checkpoint;
+use mimirsbrunn::osm_reader::addr::{read_addresses, read_interpolated_addresses};
+use mimirsbrunn::osm_reader::admin::{read_administrative_regions, AdminMatcher};
 use mimirsbrunn::osm_reader::make_osm_reader;
+use mimirsbrunn::osm_reader::osm_change::{merge_streets, KnownCoords, OsmChange};
 use mimirsbrunn::osm_reader::poi::{add_address, compute_poi_weight, pois, PoiConfig};
 use mimirsbrunn::osm_reader::street::{compute_street_weight, streets};
+use mimirsbrunn::utils::{ExportArgs, PublishArgs};
+use rs_es::query::Query;
 use slog_scope::{debug, info};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 struct Args {
     /// OSM PBF file.
-    #[structopt(short = "i", long = "input", parse(from_os_str))]
-    input: PathBuf,
+    #[structopt(
+        short = "i",
+        long = "input",
+        parse(from_os_str),
+        required_unless = "osc"
+    )]
+    input: Option<PathBuf>,
+    /// OSM change files (.osc or .osc.gz) to apply to the already imported dataset,
+    /// in chronological order.
+    #[structopt(
+        long = "osc",
+        parse(from_os_str),
+        conflicts_with_all = &["input", "output"]
+    )]
+    osc: Vec<PathBuf>,
     /// Admin levels to keep.
     #[structopt(short = "l", long = "level")]
     level: Vec<u32>,
@@ -66,9 +88,20 @@ struct Args {
     /// Import POIs.
     #[structopt(short = "p", long = "import-poi")]
     import_poi: bool,
+    /// Import the addresses (the osm objects with a house number and a street).
+    #[structopt(long = "import-address")]
+    import_address: bool,
+    /// Import the addresses interpolated along the address interpolation ways.
+    #[structopt(long = "import-address-interpolation")]
+    import_address_interpolation: bool,
     /// Name of the dataset.
     #[structopt(short = "d", long = "dataset", default_value = "fr")]
     dataset: String,
+    /// Name of the dataset of the imported addresses (`osm_<dataset>` by default).
+    /// A dataset has only one address index, so it must be different from the dataset of
+    /// the other address imports (bano2mimir uses `fr` by default), or they are replaced.
+    #[structopt(long = "address-dataset")]
+    address_dataset: Option<String>,
     /// POI configuration.
     #[structopt(short = "j", long = "poi-config", parse(from_os_str))]
     poi_config: Option<PathBuf>,
@@ -90,31 +123,72 @@ struct Args {
     /// Number of replicas for the es index
     #[structopt(long = "nb-poi-replicas", default_value = "1")]
     nb_poi_replicas: usize,
+    /// Number of shards for the addr es index
+    #[structopt(long = "nb-addr-shards", default_value = "1")]
+    nb_addr_shards: usize,
+    /// Number of replicas for the addr es index
+    #[structopt(long = "nb-addr-replicas", default_value = "1")]
+    nb_addr_replicas: usize,
     /// DB file.
     #[structopt(long = "db-file", parse(from_os_str))]
     db_file: Option<PathBuf>,
     /// DB buffer size.
     #[structopt(long = "db-buffer-size", default_value = "50000")]
     db_buffer_size: usize,
+    /// File where the progress of the import is saved, so that it can be resumed
+    /// with `--resume` if it is interrupted (the progress is not saved without it).
+    #[structopt(long = "state-file", parse(from_os_str))]
+    state_file: Option<PathBuf>,
+    /// Resume the import saved in the state file, instead of starting a new one.
+    /// The input must be the same as the one of the interrupted import.
+    #[structopt(long = "resume", requires = "state_file", conflicts_with_all = &["osc", "output"])]
+    resume: bool,
+    #[structopt(flatten)]
+    publish: PublishArgs,
+    #[structopt(flatten)]
+    export: ExportArgs,
 }
 
 fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
+    if !args.osc.is_empty() {
+        return apply_changes(args);
+    }
+    match args.export.backend(&args.connection_string)? {
+        Some(backend) => {
+            import(args, backend.clone())?;
+            backend.finish()
+        }
+        None => {
+            debug!("creation of indexes");
+            let rubber = args.publish.rubber(&args.connection_string)?;
+            rubber.initialize_templates()?;
+            import(args, rubber)
+        }
+    }
+}
+
+fn import<B: Backend>(args: Args, mut backend: B) -> Result<(), mimirsbrunn::Error> {
     let levels = args.level.iter().cloned().collect();
     let city_level = args.city_level;
 
-    let mut osm_reader = make_osm_reader(&args.input)?;
-    debug!("creation of indexes");
-    let mut rubber = Rubber::new(&args.connection_string);
-    rubber.initialize_templates()?;
+    let input = args
+        .input
+        .as_ref()
+        .ok_or_else(|| err_msg("no OSM PBF file given"))?;
+    let mut osm_reader = make_osm_reader(input)?;
+    let mut checkpoint = Checkpoint::new(args.state_file.clone(), args.resume)?;
 
-    info!("creating adminstrative regions");
-    let admins = if args.import_admin {
+    // the admins already published by an interrupted import are not extracted again
+    let admins = if args.import_admin && !checkpoint.is_published::<Admin>(&args.dataset) {
+        info!("creating adminstrative regions");
         read_administrative_regions(&mut osm_reader, levels, city_level)
     } else {
-        rubber.get_all_admins()?
+        backend.get_all_admins()?
     };
     let admins_geofinder = admins.into_iter().collect::<AdminGeoFinder>();
-    {
+    if args.import_way && checkpoint.is_published::<Street>(&args.dataset) {
+        info!("streets already imported, skipped");
+    } else if args.import_way {
         info!("Extracting streets from osm");
         let mut streets = streets(
             &mut osm_reader,
@@ -126,32 +200,38 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
         info!("computing street weight");
         compute_street_weight(&mut streets);
 
-        if args.import_way {
-            let street_index_settings = IndexSettings {
-                nb_shards: args.nb_street_shards,
-                nb_replicas: args.nb_street_replicas,
-            };
-            info!("importing streets into Mimir");
-            let nb_streets = rubber
-                .public_index(&args.dataset, &street_index_settings, streets.into_iter())
-                .with_context(|_| {
-                    format!(
-                        "Error occurred when requesting street number in {}",
-                        args.dataset
-                    )
-                })?;
-            info!("Nb of indexed street: {}", nb_streets);
-        }
+        let street_index_settings = IndexSettings {
+            nb_shards: args.nb_street_shards,
+            nb_replicas: args.nb_street_replicas,
+        };
+        info!("importing streets into Mimir");
+        let nb_streets = checkpoint
+            .public_index(
+                &mut backend,
+                &args.dataset,
+                &street_index_settings,
+                "streets",
+                streets.into_iter(),
+            )
+            .with_context(|_| {
+                format!(
+                    "Error occurred when requesting street number in {}",
+                    args.dataset
+                )
+            })?;
+        info!("Nb of indexed street: {}", nb_streets);
     }
     if args.import_admin {
         let admin_index_settings = IndexSettings {
             nb_shards: args.nb_admin_shards,
             nb_replicas: args.nb_admin_replicas,
         };
-        let nb_admins = rubber
+        let nb_admins = checkpoint
             .public_index(
+                &mut backend,
                 &args.dataset,
                 &admin_index_settings,
+                "admins",
                 admins_geofinder.admins(),
             )
             .with_context(|_| {
@@ -163,7 +243,45 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
         info!("Nb of indexed admin: {}", nb_admins);
     }
 
-    if args.import_poi {
+    let import_addresses = args.import_address || args.import_address_interpolation;
+    let address_dataset = args
+        .address_dataset
+        .clone()
+        .unwrap_or_else(|| format!("osm_{}", args.dataset));
+    if import_addresses && checkpoint.is_published::<Addr>(&address_dataset) {
+        info!("addresses already imported, skipped");
+    } else if import_addresses {
+        let mut addresses = vec![];
+        if args.import_address {
+            info!("Extracting addresses from osm");
+            addresses.extend(read_addresses(&mut osm_reader, &admins_geofinder));
+        }
+        if args.import_address_interpolation {
+            info!("Interpolating addresses from osm");
+            addresses.extend(read_interpolated_addresses(
+                &mut osm_reader,
+                &admins_geofinder,
+            ));
+        }
+
+        let addr_index_settings = IndexSettings {
+            nb_shards: args.nb_addr_shards,
+            nb_replicas: args.nb_addr_replicas,
+        };
+        info!("Importing {} addresses into Mimir", addresses.len());
+        index_addresses(
+            &mut backend,
+            &mut checkpoint,
+            addr_index_settings,
+            &address_dataset,
+            addresses.into_iter(),
+        )
+        .context("Importing addresses into Mimir")?;
+    }
+
+    if args.import_poi && checkpoint.is_published::<Poi>(&args.dataset) {
+        info!("pois already imported, skipped");
+    } else if args.import_poi {
         let matcher = match args.poi_config {
             None => PoiConfig::default(),
             Some(path) => {
@@ -180,22 +298,201 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
         compute_poi_weight(&mut pois);
 
         info!("Adding addresss in poi");
-        add_address(&mut pois, &mut rubber);
+        add_address(&mut pois, &mut backend);
 
         let poi_index_settings = IndexSettings {
             nb_shards: args.nb_poi_shards,
             nb_replicas: args.nb_poi_replicas,
         };
         info!("Importing pois into Mimir");
-        let nb_pois = rubber
-            .public_index(&args.dataset, &poi_index_settings, pois.into_iter())
+        let nb_pois = checkpoint
+            .public_index(
+                &mut backend,
+                &args.dataset,
+                &poi_index_settings,
+                "pois",
+                pois.into_iter(),
+            )
             .context("Importing pois into Mimir")?;
 
         info!("Nb of indexed pois: {}", nb_pois);
     }
+    checkpoint.finish()
+}
+
+/// Update the streets, admins and pois of the dataset with some OSM change files.
+///
+/// Known limitations:
+///  * the previous lines of the modified or deleted ways stay in the geometry of the streets
+///    merged by a full import (cf `merge_streets`),
+///  * the labels of the streets and pois are not updated when one of their admins changes,
+///  * the admins identified by their INSEE code are not removed.
+fn apply_changes(args: Args) -> Result<(), mimirsbrunn::Error> {
+    let mut changes = OsmChange::default();
+    for path in &args.osc {
+        changes.read(path)?;
+    }
+    info!(
+        "{} osm objects created or modified, {} deleted",
+        changes.objects.len(),
+        changes.deleted.len()
+    );
+
+    let mut rubber = Rubber::new(&args.connection_string);
+    rubber.initialize_templates()?;
+
+    let mut admins = rubber.get_all_admins()?;
+    if args.import_admin {
+        let matcher = AdminMatcher::new(args.level.iter().cloned().collect());
+        let updated = changes.admins(&matcher, args.city_level);
+        let deleted = changes.deleted_admin_ids(&matcher);
+        // the streets and pois are attached to the updated admins
+        admins.retain(|a| !deleted.contains(&a.id) && !updated.iter().any(|u| u.id == a.id));
+        admins.extend(updated.iter().cloned());
+        let (nb_upserted, nb_deleted) = rubber
+            .update_index(&args.dataset, updated.into_iter(), &deleted)
+            .with_context(|_| format!("Error occurred when updating admins in {}", args.dataset))?;
+        info!(
+            "Nb of updated admins: {}, removed: {}",
+            nb_upserted, nb_deleted
+        );
+    }
+    let admins_geofinder = admins.into_iter().collect::<AdminGeoFinder>();
+
+    let mut indexes = vec![];
+    if args.import_way {
+        indexes.push(get_main_type_and_dataset_index::<Street>(&args.dataset));
+    }
+    if args.import_poi {
+        indexes.push(get_main_type_and_dataset_index::<Poi>(&args.dataset));
+    }
+    let known_coords = get_known_coords(&rubber, indexes, &changes.located_ids())?;
+
+    if args.import_way {
+        let streets = changes.streets(&admins_geofinder, &known_coords);
+        let deleted = changes.deleted_street_ids();
+        let indexed = get_indexed_streets(&mut rubber, &args.dataset, &streets, &deleted)?;
+        let (mut streets, deleted) = merge_streets(streets, deleted, &indexed);
+        compute_street_weight(&mut streets);
+        let (nb_upserted, nb_deleted) = rubber
+            .update_index(&args.dataset, streets.into_iter(), &deleted)
+            .with_context(|_| {
+                format!("Error occurred when updating streets in {}", args.dataset)
+            })?;
+        info!(
+            "Nb of updated streets: {}, removed: {}",
+            nb_upserted, nb_deleted
+        );
+    }
+
+    if args.import_poi {
+        let matcher = match args.poi_config {
+            None => PoiConfig::default(),
+            Some(ref path) => {
+                let r = std::fs::File::open(path).with_context(|_| {
+                    format!("Error while opening configuration file {:?}", path)
+                })?;
+                PoiConfig::from_reader(r).unwrap()
+            }
+        };
+        let mut pois = changes.pois(&matcher, &admins_geofinder, &known_coords);
+        compute_poi_weight(&mut pois);
+        add_address(&mut pois, &mut rubber);
+        let deleted = changes.deleted_poi_ids(&matcher);
+        let poi_index = get_main_type_and_dataset_index::<Poi>(&args.dataset);
+        let indexed = get_known_coords(&rubber, vec![poi_index], &deleted)?;
+        let deleted: Vec<_> = deleted
+            .into_iter()
+            .filter(|id| indexed.contains_key(id))
+            .collect();
+        let (nb_upserted, nb_deleted) = rubber
+            .update_index(&args.dataset, pois.into_iter(), &deleted)
+            .context("Updating pois in Mimir")?;
+        info!(
+            "Nb of updated pois: {}, removed: {}",
+            nb_upserted, nb_deleted
+        );
+    }
     Ok(())
 }
 
+/// the coordinates of the documents `ids` already indexed in `indexes`
+fn get_known_coords(
+    rubber: &Rubber,
+    indexes: Vec<String>,
+    ids: &[String],
+) -> Result<KnownCoords, mimirsbrunn::Error> {
+    let mut known_coords = KnownCoords::new();
+    if indexes.is_empty() {
+        return Ok(known_coords);
+    }
+    for chunk in ids.chunks(1000) {
+        let ids = chunk
+            .iter()
+            .map(|id| rs_es::units::JsonVal::String(id.clone()))
+            .collect::<Vec<_>>();
+        let places = rubber
+            .search_places_blocking(SearchRequest {
+                indexes: indexes.clone(),
+                query: Query::build_ids(ids).build(),
+                from: 0,
+                size: chunk.len() as u64,
+                coord: None,
+                explain: false,
+                with_geometry: false,
+            })
+            .context("Error occurred when reading the indexed coordinates")?;
+        known_coords.extend(
+            places
+                .into_iter()
+                .map(|place| (place.id().to_string(), *place.coord())),
+        );
+    }
+    Ok(known_coords)
+}
+
+/// the indexed streets with the same names or the same ids than the changed streets,
+/// to merge the changed ways like a full import does
+fn get_indexed_streets(
+    rubber: &mut Rubber,
+    dataset: &str,
+    streets: &[Street],
+    deleted: &[String],
+) -> Result<Vec<Street>, mimirsbrunn::Error> {
+    let index = get_main_type_and_dataset_index::<Street>(dataset);
+    let names: BTreeSet<&str> = streets.iter().map(|s| s.name.as_str()).collect();
+    let names: Vec<&str> = names.into_iter().collect();
+    let ids: Vec<&String> = streets.iter().map(|s| &s.id).chain(deleted).collect();
+    let mut queries = vec![];
+    for chunk in names.chunks(100) {
+        let matches = chunk
+            .iter()
+            .map(|name| {
+                Query::build_match("name", name.to_string())
+                    .with_operator("and")
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        queries.push(Query::build_bool().with_should(matches).build());
+    }
+    for chunk in ids.chunks(1000) {
+        let ids = chunk
+            .iter()
+            .map(|id| rs_es::units::JsonVal::String(id.to_string()))
+            .collect::<Vec<_>>();
+        queries.push(Query::build_ids(ids).build());
+    }
+    let mut indexed = BTreeMap::new();
+    for query in queries {
+        let streets: Vec<Street> = rubber
+            .get_objects_by_query(&index, query)
+            .context("Error occurred when reading the indexed streets")?;
+        // the name query is not exact, the streets are compared by name in `merge_streets`
+        indexed.extend(streets.into_iter().map(|s| (s.id.clone(), s)));
+    }
+    Ok(indexed.into_iter().map(|(_, s)| s).collect())
+}
+
 fn main() {
     mimirsbrunn::utils::launch_run(run);
 }
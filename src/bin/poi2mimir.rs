@@ -32,8 +32,10 @@ use slog_scope::{error, info};
 
 use failure::format_err;
 use lazy_static::lazy_static;
+use mimir::backend::Backend;
 use mimir::objects::{Coord, I18nProperties, Poi, PoiType, Property};
-use mimir::rubber::{IndexSettings, IndexVisibility, Rubber, TypedIndex};
+use mimir::rubber::{IndexSettings, IndexVisibility, TypedIndex};
+use mimirsbrunn::utils::{ExportArgs, PublishArgs};
 use mimirsbrunn::{admin_geofinder::AdminGeoFinder, labels, utils};
 use navitia_poi_model::{Model as NavitiaModel, Poi as NavitiaPoi, PoiType as NavitiaPoiType};
 use std::collections::HashMap;
@@ -50,7 +52,7 @@ lazy_static! {
 fn into_mimir_poi(
     poi: NavitiaPoi,
     poi_types: &HashMap<String, NavitiaPoiType>,
-    rubber: &mut Rubber,
+    backend: &mut impl Backend,
     admins_geofinder: &AdminGeoFinder,
 ) -> Result<Poi, mimirsbrunn::Error> {
     let poi_type = poi_types
@@ -60,7 +62,7 @@ fn into_mimir_poi(
 
     let coord = Coord::from(&poi.coord);
 
-    let place = rubber
+    let place = backend
         .get_address(&coord) // No timeout
         .ok()
         .and_then(|addrs| addrs.into_iter().next()); // Take the first place
@@ -113,8 +115,8 @@ fn into_mimir_poi(
     Ok(poi)
 }
 
-fn import_pois(
-    rubber: &mut Rubber,
+fn import_pois<B: Backend>(
+    backend: &mut B,
     index: &TypedIndex<Poi>,
     admins_geofinder: AdminGeoFinder,
     file: &PathBuf,
@@ -131,13 +133,13 @@ where
         .pois
         .into_iter()
         .filter_map(|(id, poi)| {
-            into_mimir_poi(poi, &poi_types, rubber, &admins_geofinder)
+            into_mimir_poi(poi, &poi_types, backend, &admins_geofinder)
                 .map_err(|err| info!("Could not extract information for POI '{}': {}", id, err))
                 .ok()
         })
         .collect(); // TODO Can we get rid of collect, and chain with the following rubber...?
 
-    let count = rubber
+    let count = backend
         .bulk_index(&index, pois.into_iter())
         .map_err(|err| format_err!("Failed bulk insertion {}", err))?;
 
@@ -148,8 +150,8 @@ where
 
 /// This function initializes the ES context: It creates an index for this dataset,
 /// and then import the POIs in it.
-fn index_poi(
-    cnx_string: &str,
+fn index_poi<B: Backend>(
+    backend: &mut B,
     dataset: &str,
     file: &PathBuf,
     visibility: IndexVisibility,
@@ -158,25 +160,22 @@ fn index_poi(
 ) -> Result<(), mimirsbrunn::Error>
 where
 {
-    let mut rubber = Rubber::new(cnx_string);
-    rubber.initialize_templates()?;
-
     let settings = IndexSettings {
         nb_shards: nb_shards,
         nb_replicas: nb_replicas,
     };
 
-    let index = rubber.make_index(dataset, &settings)?;
+    let index = backend.make_index(dataset, &settings)?;
 
-    let admins = rubber.get_all_admins().map_err(|err| {
+    let admins = backend.get_all_admins().map_err(|err| {
         error!("Administratives regions not found in es db");
         err
     })?;
     let admins_geofinder = admins.into_iter().collect();
 
-    import_pois(&mut rubber, &index, admins_geofinder, file)?;
+    import_pois(backend, &index, admins_geofinder, file)?;
 
-    rubber
+    backend
         .publish_index(dataset, index, visibility)
         .map_err(|err| format_err!("Failed to publish index {}.", err))
 }
@@ -219,6 +218,12 @@ struct Args {
     /// Number of replicas for the es index
     #[structopt(short = "r", long = "nb-replicas", default_value = "1")]
     nb_replicas: usize,
+
+    #[structopt(flatten)]
+    publish: PublishArgs,
+
+    #[structopt(flatten)]
+    export: ExportArgs,
 }
 
 fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
@@ -228,14 +233,31 @@ fn run(args: Args) -> Result<(), mimirsbrunn::Error> {
         IndexVisibility::Public
     };
 
-    index_poi(
-        &args.connection_string,
-        &args.dataset,
-        &args.input,
-        visibility,
-        args.nb_shards,
-        args.nb_replicas,
-    )
+    match args.export.backend(&args.connection_string)? {
+        Some(mut backend) => {
+            index_poi(
+                &mut backend,
+                &args.dataset,
+                &args.input,
+                visibility,
+                args.nb_shards,
+                args.nb_replicas,
+            )?;
+            backend.finish()
+        }
+        None => {
+            let mut rubber = args.publish.rubber(&args.connection_string)?;
+            rubber.initialize_templates()?;
+            index_poi(
+                &mut rubber,
+                &args.dataset,
+                &args.input,
+                visibility,
+                args.nb_shards,
+                args.nb_replicas,
+            )
+        }
+    }
 }
 fn main() {
     mimirsbrunn::utils::launch_run(run);
@@ -31,6 +31,7 @@
 use failure::ResultExt;
 use mimir::rubber::IndexSettings;
 use mimirsbrunn::stops::*;
+use mimirsbrunn::utils::{ExportArgs, MergeArgs, PublishArgs};
 use serde::Deserialize;
 use slog_scope::{info, warn};
 use std::collections::HashMap;
@@ -67,6 +68,12 @@ struct Args {
     /// Number of replicas for the es index
     #[structopt(short = "r", long = "nb-replicas", default_value = "1")]
     nb_replicas: usize,
+    #[structopt(flatten)]
+    publish: PublishArgs,
+    #[structopt(flatten)]
+    merge: MergeArgs,
+    #[structopt(flatten)]
+    export: ExportArgs,
 }
 
 #[derive(Deserialize, Debug)]
@@ -166,8 +173,14 @@ fn run(args: Args) -> Result<(), failure::Error> {
     };
 
     import_stops(
-        stops,
+        PtObjects {
+            stops,
+            ..Default::default()
+        },
         &args.connection_string,
+        &args.export,
+        &args.publish,
+        &args.merge,
         &args.dataset,
         index_settings,
     )
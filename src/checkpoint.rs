@@ -0,0 +1,403 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Progress of the imports, saved in a local state file (given with `--state-file`) so that
+//! an interrupted import can be resumed (with `--resume`) instead of being restarted from scratch.
+//! Without state file, the progress is not saved.
+//!
+//! The documents are indexed by batches in a dated index that is only published at the
+//! end of the import. After each batch, the state file records the name of this index and
+//! the number of records of the input already indexed, with the byte offset of the first
+//! record not indexed yet when the input can be read from an offset (the plain csv files).
+//! When resuming, the same index is filled again from the first record not indexed yet:
+//! the inputs with an offset are read from it, the other ones (the compressed files, the
+//! standard input, the OSM objects) are read again and their first records are skipped.
+//! So the input must be the same.
+
+use crate::Error;
+use failure::{format_err, ResultExt};
+use mimir::backend::Backend;
+use mimir::rubber::{get_main_type_and_dataset_index, IndexSettings, IndexVisibility, TypedIndex};
+use mimir::MimirObject;
+use par_map::ParMap;
+use serde::{Deserialize, Serialize};
+use slog_scope::info;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+
+/// number of records indexed between two saves of the state file
+const CHECKPOINT_INTERVAL: usize = 100_000;
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+struct InputProgress {
+    /// number of records already indexed
+    nb_records: usize,
+    /// byte offset in the input of the first record not indexed yet,
+    /// if the input can be read from an offset
+    #[serde(default)]
+    offset: Option<u64>,
+    /// all the records of the input have been indexed
+    done: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+struct ImportState {
+    /// the indexes being filled, by alias of their doc type and dataset (`munin_addr_fr`)
+    indexes: BTreeMap<String, String>,
+    /// the aliases whose index has already been published
+    published: BTreeSet<String>,
+    /// the progress of each input (a file, or a kind of objects)
+    inputs: BTreeMap<String, InputProgress>,
+}
+
+pub struct Checkpoint {
+    path: Option<PathBuf>,
+    state: ImportState,
+}
+
+impl Checkpoint {
+    /// the progress of the import is saved in `path`, if any.
+    /// If `resume` is set, the import continues from the progress found in `path`,
+    /// otherwise a new import is started.
+    pub fn new(path: Option<PathBuf>, resume: bool) -> Result<Checkpoint, Error> {
+        let state = match path {
+            Some(ref path) if resume => {
+                let file = fs::File::open(&path)
+                    .with_context(|_| format!("impossible to open the state file {:?}", path))?;
+                let state: ImportState = serde_json::from_reader(file)
+                    .with_context(|_| format!("invalid state file {:?}", path))?;
+                info!("resuming the import saved in {:?}", path);
+                state
+            }
+            None if resume => {
+                return Err(format_err!("a state file is needed to resume an import"))
+            }
+            _ => ImportState::default(),
+        };
+        Ok(Checkpoint { path, state })
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        // the state is written in another file first, not to lose it if the import is killed
+        let tmp_path = path.with_extension("tmp");
+        let file = fs::File::create(&tmp_path)
+            .with_context(|_| format!("impossible to create the state file {:?}", tmp_path))?;
+        serde_json::to_writer(file, &self.state)?;
+        fs::rename(&tmp_path, path)
+            .with_context(|_| format!("impossible to write the state file {:?}", path))?;
+        Ok(())
+    }
+
+    /// the index of the objects `T` of the dataset being filled by the import,
+    /// it is created if the import has not started to fill one
+    pub fn index<T, B>(
+        &mut self,
+        backend: &B,
+        dataset: &str,
+        index_settings: &IndexSettings,
+    ) -> Result<TypedIndex<T>, Error>
+    where
+        T: MimirObject,
+        B: Backend,
+    {
+        let alias = get_main_type_and_dataset_index::<T>(dataset);
+        if let Some(name) = self.state.indexes.get(&alias) {
+            info!("resuming the import in the index {}", name);
+            return Ok(TypedIndex::new(name.clone()));
+        }
+        let index = backend
+            .make_index(dataset, index_settings)
+            .with_context(|_| format!("Error occurred when making index {}", dataset))?;
+        self.state.indexes.insert(alias, index.name().to_string());
+        self.save()?;
+        Ok(index)
+    }
+
+    /// the index of the objects `T` of the dataset has already been published by the import
+    pub fn is_published<T: MimirObject>(&self, dataset: &str) -> bool {
+        self.state
+            .published
+            .contains(&get_main_type_and_dataset_index::<T>(dataset))
+    }
+
+    /// number of records of the input already indexed,
+    /// they must be skipped before calling `bulk_index`
+    pub fn nb_indexed_records(&self, input: &str) -> usize {
+        self.state.inputs.get(input).map_or(0, |p| p.nb_records)
+    }
+
+    /// byte offset of the first record of the input not indexed yet, if the input
+    /// has been read from offsets (cf `bulk_index_with_offsets`)
+    pub fn offset(&self, input: &str) -> Option<u64> {
+        self.state.inputs.get(input).and_then(|p| p.offset)
+    }
+
+    /// all the records of the input have been indexed
+    pub fn is_done(&self, input: &str) -> bool {
+        self.state.inputs.get(input).map_or(false, |p| p.done)
+    }
+
+    /// add the records not indexed yet of the input to the index, by batches,
+    /// and save the progress after each batch.
+    ///
+    /// `records` has one element for each record of the input not indexed yet,
+    /// `None` for the records that do not give any object (invalid ones for example).
+    /// Return the number of added objects
+    pub fn bulk_index<T, B, I>(
+        &mut self,
+        backend: &mut B,
+        index: &TypedIndex<T>,
+        input: &str,
+        records: I,
+    ) -> Result<usize, Error>
+    where
+        T: MimirObject + std::marker::Send + 'static,
+        B: Backend,
+        I: Iterator<Item = Option<T>>,
+    {
+        self.bulk_index_with_offsets(backend, index, input, records.map(|r| (r, None)))
+    }
+
+    /// like `bulk_index`, but each record comes with the byte offset in the input of the
+    /// record following it, so that a resumed import can read the input from this offset
+    pub fn bulk_index_with_offsets<T, B, I>(
+        &mut self,
+        backend: &mut B,
+        index: &TypedIndex<T>,
+        input: &str,
+        records: I,
+    ) -> Result<usize, Error>
+    where
+        T: MimirObject + std::marker::Send + 'static,
+        B: Backend,
+        I: Iterator<Item = (Option<T>, Option<u64>)>,
+    {
+        let mut nb = 0;
+        for batch in records.pack(CHECKPOINT_INTERVAL) {
+            let nb_records = batch.len();
+            let offset = batch.last().and_then(|(_, offset)| *offset);
+            nb += backend.bulk_index(index, batch.into_iter().filter_map(|(r, _)| r))?;
+            let progress = self.state.inputs.entry(input.to_string()).or_default();
+            progress.nb_records += nb_records;
+            progress.offset = offset;
+            self.save()?;
+        }
+        self.state.inputs.entry(input.to_string()).or_default().done = true;
+        self.save()?;
+        Ok(nb)
+    }
+
+    /// publish the index filled by the import
+    pub fn publish_index<T, B>(
+        &mut self,
+        backend: &mut B,
+        dataset: &str,
+        index: TypedIndex<T>,
+        visibility: IndexVisibility,
+    ) -> Result<(), Error>
+    where
+        T: MimirObject,
+        B: Backend,
+    {
+        let alias = get_main_type_and_dataset_index::<T>(dataset);
+        if self.state.indexes.get(&alias).map(String::as_str) != Some(index.name()) {
+            return Err(format_err!(
+                "the index {} has not been filled by this import",
+                index.name()
+            ));
+        }
+        backend.publish_index(dataset, index, visibility)?;
+        self.state.indexes.remove(&alias);
+        self.state.published.insert(alias);
+        self.save()
+    }
+
+    /// equivalent to `Backend::public_index` with checkpoints, `input` identifies the objects.
+    ///
+    /// Nothing is done if the index has already been published by the import.
+    /// Return the number of added objects
+    pub fn public_index<T, B, I>(
+        &mut self,
+        backend: &mut B,
+        dataset: &str,
+        index_settings: &IndexSettings,
+        input: &str,
+        iter: I,
+    ) -> Result<usize, Error>
+    where
+        T: MimirObject + std::marker::Send + 'static,
+        B: Backend,
+        I: Iterator<Item = T>,
+    {
+        if self.is_published::<T>(dataset) {
+            info!("{} already imported, skipped", input);
+            return Ok(0);
+        }
+        let index = self.index(&*backend, dataset, index_settings)?;
+        let nb_indexed = self.nb_indexed_records(input);
+        let nb = if self.is_done(input) {
+            0
+        } else {
+            self.bulk_index(backend, &index, input, iter.skip(nb_indexed).map(Some))?
+        };
+        self.publish_index(backend, dataset, index, IndexVisibility::Public)?;
+        Ok(nb)
+    }
+
+    /// the import is over, the state file is removed
+    pub fn finish(self) -> Result<(), Error> {
+        match self.path {
+            Some(ref path) if path.exists() => {
+                fs::remove_file(path)
+                    .with_context(|_| format!("impossible to remove the state file {:?}", path))?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mimir::memory::MemoryBackend;
+    use mimir::objects::Coord;
+    use mimir::Street;
+
+    fn street(n: usize) -> Street {
+        Street {
+            id: format!("street:{}", n),
+            name: format!("Rue {}", n),
+            coord: Coord::new(2., 48.),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resume_import() {
+        let path = std::env::temp_dir().join(format!("checkpoint_{}.json", std::process::id()));
+        let settings = IndexSettings {
+            nb_shards: 1,
+            nb_replicas: 0,
+        };
+        let mut backend = MemoryBackend::new();
+
+        // the import is interrupted after the first 3 streets
+        let mut checkpoint = Checkpoint::new(Some(path.clone()), false).unwrap();
+        let index = checkpoint
+            .index::<Street, _>(&backend, "fr", &settings)
+            .unwrap();
+        let nb = checkpoint
+            .bulk_index(
+                &mut backend,
+                &index,
+                "streets",
+                (0..3).map(|n| Some(street(n))),
+            )
+            .unwrap();
+        assert_eq!(nb, 3);
+        checkpoint.state.inputs.get_mut("streets").unwrap().done = false;
+        checkpoint.save().unwrap();
+        drop(checkpoint);
+
+        // the resumed import only indexes the other streets, in the same index
+        let mut checkpoint = Checkpoint::new(Some(path.clone()), true).unwrap();
+        assert_eq!(checkpoint.nb_indexed_records("streets"), 3);
+        let nb = checkpoint
+            .public_index(&mut backend, "fr", &settings, "streets", (0..5).map(street))
+            .unwrap();
+        assert_eq!(nb, 2);
+        assert!(checkpoint.is_published::<Street>("fr"));
+        let streets: Vec<Street> = backend
+            .get_all_objects_from_index("munin_street_fr")
+            .unwrap();
+        assert_eq!(streets.len(), 5);
+
+        // an index already published is not imported again
+        let nb = checkpoint
+            .public_index(&mut backend, "fr", &settings, "streets", (0..5).map(street))
+            .unwrap();
+        assert_eq!(nb, 0);
+
+        checkpoint.finish().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn save_input_offset() {
+        let path = std::env::temp_dir().join(format!("offset_{}.json", std::process::id()));
+        let settings = IndexSettings {
+            nb_shards: 1,
+            nb_replicas: 0,
+        };
+        let mut backend = MemoryBackend::new();
+
+        let mut checkpoint = Checkpoint::new(Some(path.clone()), false).unwrap();
+        let index = checkpoint
+            .index::<Street, _>(&backend, "fr", &settings)
+            .unwrap();
+        // the second record gives no street
+        let records = vec![(Some(street(0)), Some(10)), (None, Some(25))];
+        let nb = checkpoint
+            .bulk_index_with_offsets(&mut backend, &index, "file.csv", records.into_iter())
+            .unwrap();
+        assert_eq!(nb, 1);
+        drop(checkpoint);
+
+        let checkpoint = Checkpoint::new(Some(path.clone()), true).unwrap();
+        assert_eq!(checkpoint.nb_indexed_records("file.csv"), 2);
+        assert_eq!(checkpoint.offset("file.csv"), Some(25));
+        assert_eq!(checkpoint.offset("other.csv"), None);
+        checkpoint.finish().unwrap();
+    }
+
+    #[test]
+    fn import_without_state_file() {
+        let settings = IndexSettings {
+            nb_shards: 1,
+            nb_replicas: 0,
+        };
+        let mut backend = MemoryBackend::new();
+        let mut checkpoint = Checkpoint::new(None, false).unwrap();
+        let nb = checkpoint
+            .public_index(&mut backend, "fr", &settings, "streets", (0..2).map(street))
+            .unwrap();
+        assert_eq!(nb, 2);
+        checkpoint.finish().unwrap();
+
+        // an import can't be resumed without its state file
+        assert!(Checkpoint::new(None, true).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Load the documents exported by the importers (cf `--output`) in a backend.
+//!
+//! The documents are read as newline delimited json, each line being a `Place`:
+//! either a document as stored in elasticsearch (with its type in the `type` field)
+//! or a serialized `Place` enum.
+//! The global stop index is not exported: it is rebuilt with the loaded stops,
+//! like the stop importers do.
+
+use crate::stops::update_global_stops;
+use crate::utils::MergeArgs;
+use crate::Error;
+use failure::{bail, ResultExt};
+use mimir::backend::Backend;
+use mimir::rubber::{get_doc_type, IndexSettings, IndexVisibility, TypedIndex};
+use mimir::{MimirObject, Place};
+use serde_json::Value;
+use slog_scope::info;
+use std::io::BufRead;
+
+/// number of documents sent to the backend at once
+const CHUNK_SIZE: usize = 1000;
+
+/// parse a line of the dump
+pub fn parse_place(line: &str) -> Result<Place, Error> {
+    let value: Value = serde_json::from_str(line)?;
+    let mut place = match get_doc_type(Some(&value), None) {
+        Some(doc_type) => match doc_type.as_str() {
+            "addr" => Place::Addr(serde_json::from_value(value)?),
+            "street" => Place::Street(serde_json::from_value(value)?),
+            "admin" => Place::Admin(serde_json::from_value(value)?),
+            "poi" => Place::Poi(serde_json::from_value(value)?),
+            "stop" => Place::Stop(serde_json::from_value(value)?),
+            "stop_point" => Place::StopPoint(serde_json::from_value(value)?),
+            "line" => Place::PtLine(serde_json::from_value(value)?),
+            "network" => Place::PtNetwork(serde_json::from_value(value)?),
+            _ => bail!("unknown document type {}", doc_type),
+        },
+        None => serde_json::from_value(value)?,
+    };
+    // the approximate coordinates are not deserialized, they are rebuilt from the coordinates
+    // (the lines and the networks have none)
+    match place {
+        Place::Admin(ref mut o) => o.approx_coord = Some(o.coord.into()),
+        Place::Street(ref mut o) => o.approx_coord = Some(o.coord.into()),
+        Place::Addr(ref mut o) => o.approx_coord = Some(o.coord.into()),
+        Place::Poi(ref mut o) => o.approx_coord = Some(o.coord.into()),
+        Place::Stop(ref mut o) => o.approx_coord = Some(o.coord.into()),
+        Place::StopPoint(ref mut o) => o.approx_coord = Some(o.coord.into()),
+        Place::PtLine(_) | Place::PtNetwork(_) => (),
+    }
+    Ok(place)
+}
+
+/// the documents of one type, sent to their index by chunks
+struct Loader<T> {
+    index: Option<TypedIndex<T>>,
+    chunk: Vec<T>,
+    nb: usize,
+}
+
+impl<T: MimirObject + Send + 'static> Loader<T> {
+    fn new() -> Self {
+        Loader {
+            index: None,
+            chunk: vec![],
+            nb: 0,
+        }
+    }
+
+    fn add<B: Backend>(
+        &mut self,
+        backend: &mut B,
+        dataset: &str,
+        index_settings: &IndexSettings,
+        object: T,
+    ) -> Result<(), Error> {
+        self.chunk.push(object);
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.flush(backend, dataset, index_settings)?;
+        }
+        Ok(())
+    }
+
+    fn flush<B: Backend>(
+        &mut self,
+        backend: &mut B,
+        dataset: &str,
+        index_settings: &IndexSettings,
+    ) -> Result<(), Error> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        if self.index.is_none() {
+            self.index = Some(backend.make_index(dataset, index_settings)?);
+        }
+        let chunk = std::mem::replace(&mut self.chunk, vec![]);
+        if let Some(ref index) = self.index {
+            self.nb += backend.bulk_index(index, chunk.into_iter())?;
+        }
+        Ok(())
+    }
+
+    /// publish the index of the documents if any, return the number of documents
+    fn publish<B: Backend>(
+        mut self,
+        backend: &mut B,
+        dataset: &str,
+        index_settings: &IndexSettings,
+        visibility: IndexVisibility,
+    ) -> Result<usize, Error> {
+        self.flush(backend, dataset, index_settings)?;
+        if let Some(index) = self.index {
+            backend
+                .publish_index(dataset, index, visibility)
+                .with_context(|_| format!("impossible to publish the {} index", T::doc_type()))?;
+            info!("{} {} documents loaded", self.nb, T::doc_type());
+        }
+        Ok(self.nb)
+    }
+}
+
+/// load all the documents of the dump in new indexes of the dataset (one by type of document,
+/// the addresses in `address_dataset`), then publish them and update the global stop index
+/// with `merge`.
+/// Return the number of loaded documents
+pub fn load_dump<B: Backend, R: BufRead>(
+    backend: &mut B,
+    dump: R,
+    dataset: &str,
+    address_dataset: &str,
+    index_settings: &IndexSettings,
+    visibility: IndexVisibility,
+    merge: &MergeArgs,
+) -> Result<usize, Error> {
+    let mut admins = Loader::new();
+    let mut streets = Loader::new();
+    let mut addrs = Loader::new();
+    let mut pois = Loader::new();
+    let mut stops = Loader::new();
+    // the stops are also kept for the global stop index
+    let mut all_stops = vec![];
+    let mut stop_points = Loader::new();
+    let mut lines = Loader::new();
+    let mut networks = Loader::new();
+    for (n, line) in dump.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let place = parse_place(&line).with_context(|_| format!("invalid line {}", n + 1))?;
+        match place {
+            Place::Admin(o) => admins.add(backend, dataset, index_settings, o)?,
+            Place::Street(o) => streets.add(backend, dataset, index_settings, o)?,
+            Place::Addr(o) => addrs.add(backend, address_dataset, index_settings, o)?,
+            Place::Poi(o) => pois.add(backend, dataset, index_settings, o)?,
+            Place::Stop(o) => {
+                all_stops.push(o.clone());
+                stops.add(backend, dataset, index_settings, o)?
+            }
+            Place::StopPoint(o) => stop_points.add(backend, dataset, index_settings, o)?,
+            Place::PtLine(o) => lines.add(backend, dataset, index_settings, o)?,
+            Place::PtNetwork(o) => networks.add(backend, dataset, index_settings, o)?,
+        }
+    }
+    // the admins first, like the importers do
+    let mut nb = admins.publish(backend, dataset, index_settings, visibility)?;
+    nb += streets.publish(backend, dataset, index_settings, visibility)?;
+    nb += addrs.publish(backend, address_dataset, index_settings, visibility)?;
+    nb += pois.publish(backend, dataset, index_settings, visibility)?;
+    nb += stops.publish(backend, dataset, index_settings, visibility)?;
+    if !all_stops.is_empty() {
+        update_global_stops(backend, all_stops.iter(), merge, dataset, index_settings)?;
+    }
+    nb += stop_points.publish(backend, dataset, index_settings, visibility)?;
+    nb += lines.publish(backend, dataset, index_settings, visibility)?;
+    nb += networks.publish(backend, dataset, index_settings, visibility)?;
+    Ok(nb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stops::GLOBAL_STOP_INDEX_NAME;
+    use mimir::memory::MemoryBackend;
+    use mimir::{Addr, Admin, Coord, Stop, Street};
+
+    #[test]
+    fn load_documents() {
+        let street = r#"{"type": "street", "id": "street:1", "name": "Rue Hector Malot",
+            "label": "Rue Hector Malot (Paris)", "administrative_regions": [], "weight": 0.0,
+            "coord": {"lon": 2.377, "lat": 48.847}, "zip_codes": []}"#
+            .replace('\n', "");
+        let admin = serde_json::to_string(&Place::Admin(Admin {
+            id: "admin:fr:75056".to_string(),
+            name: "Paris".to_string(),
+            coord: Coord::new(2.3522, 48.8566),
+            ..Default::default()
+        }))
+        .unwrap();
+        let stop = serde_json::to_string(&Place::Stop(Stop {
+            id: "stop_area:gdl".to_string(),
+            name: "Gare de Lyon".to_string(),
+            coord: Coord::new(2.3733, 48.8443),
+            coverages: vec!["fr".to_string()],
+            ..Default::default()
+        }))
+        .unwrap();
+        let addr = serde_json::to_string(&Place::Addr(Addr {
+            id: "addr:2.377;48.847:10".to_string(),
+            name: "10 Rue Hector Malot".to_string(),
+            house_number: "10".to_string(),
+            street: Street::default(),
+            label: "10 Rue Hector Malot (Paris)".to_string(),
+            coord: Coord::new(2.377, 48.847),
+            approx_coord: None,
+            weight: 0.,
+            zip_codes: vec![],
+            country_codes: vec![],
+            distance: None,
+            context: None,
+            interpolated: false,
+        }))
+        .unwrap();
+        let dump = format!("{}\n\n{}\n{}\n{}\n", street, admin, stop, addr);
+
+        let mut backend = MemoryBackend::new();
+        let settings = IndexSettings {
+            nb_shards: 1,
+            nb_replicas: 0,
+        };
+        let nb = load_dump(
+            &mut backend,
+            dump.as_bytes(),
+            "fr",
+            "osm_fr",
+            &settings,
+            IndexVisibility::Public,
+            &MergeArgs {
+                merge_distance: None,
+                merge_name_similarity: 0.8,
+            },
+        )
+        .unwrap();
+        assert_eq!(nb, 4);
+
+        let streets: Vec<Street> = backend.get_all_objects_from_index("munin_street").unwrap();
+        assert_eq!(streets.len(), 1);
+        assert_eq!(streets[0].name, "Rue Hector Malot");
+        let admins: Vec<Admin> = backend
+            .get_all_objects_from_index("munin_admin_fr")
+            .unwrap();
+        assert_eq!(admins[0].id, "admin:fr:75056");
+        // the addresses are loaded in their own dataset
+        let addrs: Vec<Addr> = backend
+            .get_all_objects_from_index("munin_addr_osm_fr")
+            .unwrap();
+        assert_eq!(addrs[0].id, "addr:2.377;48.847:10");
+        // the global stop index is rebuilt with the loaded stops
+        let global_stops: Vec<Stop> = backend
+            .get_all_objects_from_index(GLOBAL_STOP_INDEX_NAME)
+            .unwrap();
+        assert_eq!(global_stops.len(), 1);
+        assert_eq!(global_stops[0].id, "stop_area:gdl");
+    }
+
+    #[test]
+    fn invalid_document() {
+        assert!(parse_place(r#"{"type": "unknown", "id": "1"}"#).is_err());
+        assert!(parse_place(r#"{"type": "street"}"#).is_err());
+    }
+}
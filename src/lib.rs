@@ -30,6 +30,9 @@
 
 pub mod addr_reader;
 pub mod admin_geofinder;
+pub mod admin_update;
+pub mod checkpoint;
+pub mod dump;
 pub mod labels;
 pub mod osm_reader;
 pub mod stops;
@@ -0,0 +1,428 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use super::osm_utils::{get_way_coord, make_centroid};
+use super::OsmPbfReader;
+use crate::admin_geofinder::AdminGeoFinder;
+use crate::{labels, utils};
+use geo::algorithm::haversine_distance::HaversineDistance;
+use osm_boundaries_utils::build_boundary;
+use osmpbfreader::{OsmId, OsmObj};
+use slog_scope::{info, warn};
+use std::collections::BTreeMap;
+use std::ops::Deref;
+
+/// The maximum number of addresses interpolated between two house numbers,
+/// above it the house numbers are most likely wrong
+const MAX_INTERPOLATED_ADDRESSES: u32 = 1000;
+
+/// build an address from its house number, its street and its coordinate
+pub fn make_addr(
+    id: String,
+    house_number: &str,
+    street_name: &str,
+    postcode: Option<&str>,
+    coord: mimir::Coord,
+    admins_geofinder: &AdminGeoFinder,
+) -> mimir::Addr {
+    let admins = admins_geofinder.get(&coord);
+    let country_codes = utils::find_country_codes(admins.iter().map(|a| a.deref()));
+    let weight = admins.iter().find(|a| a.is_city()).map_or(0., |a| a.weight);
+    let zip_codes = match postcode {
+        Some(postcode) if !postcode.is_empty() => vec![postcode.to_string()],
+        _ => utils::get_zip_codes_from_admins(&admins),
+    };
+    let street_label = labels::format_street_label(
+        street_name,
+        admins.iter().map(|a| a.deref()),
+        &country_codes,
+    );
+    let (addr_name, addr_label) = labels::format_addr_name_and_label(
+        house_number,
+        street_name,
+        admins.iter().map(|a| a.deref()),
+        &country_codes,
+    );
+    let street = mimir::Street {
+        id: format!("street:{}", id),
+        name: street_name.to_string(),
+        label: street_label,
+        administrative_regions: admins,
+        weight,
+        zip_codes: zip_codes.clone(),
+        coord,
+        approx_coord: None,
+        geometry: None,
+        distance: None,
+        country_codes: country_codes.clone(),
+        context: None,
+    };
+    mimir::Addr {
+        id,
+        name: addr_name,
+        label: addr_label,
+        house_number: house_number.to_string(),
+        street,
+        coord,
+        approx_coord: Some(coord.into()),
+        weight,
+        zip_codes,
+        distance: None,
+        country_codes,
+        context: None,
+        interpolated: false,
+    }
+}
+
+/// For the object to be an address, it needs a house number and a street (or a place)
+pub fn is_addr(obj: &OsmObj) -> bool {
+    let tags = obj.tags();
+    tags.contains_key("addr:housenumber")
+        && (tags.contains_key("addr:street") || tags.contains_key("addr:place"))
+}
+
+/// the id of the address built from an osm object
+pub fn addr_id(osm_id: OsmId) -> String {
+    match osm_id {
+        OsmId::Node(id) => format!("addr:osm:node:{}", id.0),
+        OsmId::Way(id) => format!("addr:osm:way:{}", id.0),
+        OsmId::Relation(id) => format!("addr:osm:relation:{}", id.0),
+    }
+}
+
+/// read the addresses of the osm objects tagged with a house number
+pub fn read_addresses(
+    pbf: &mut OsmPbfReader,
+    admins_geofinder: &AdminGeoFinder,
+) -> Vec<mimir::Addr> {
+    info!("reading pbf...");
+    let objects = pbf.get_objs_and_deps(is_addr).unwrap();
+    info!("reading pbf done.");
+    objects
+        .values()
+        .filter(|obj| is_addr(obj))
+        .filter_map(|obj| parse_addr(obj, &objects, admins_geofinder))
+        .collect()
+}
+
+fn parse_addr(
+    obj: &OsmObj,
+    objects: &BTreeMap<OsmId, OsmObj>,
+    admins_geofinder: &AdminGeoFinder,
+) -> Option<mimir::Addr> {
+    let coord = match *obj {
+        OsmObj::Node(ref node) => mimir::Coord::new(node.lon(), node.lat()),
+        OsmObj::Way(ref way) => get_way_coord(objects, way),
+        OsmObj::Relation(ref relation) => make_centroid(&build_boundary(relation, objects)),
+    };
+    let id = addr_id(obj.id());
+    if coord.is_default() {
+        info!(
+            "The address {} is rejected, cause: could not compute coordinates.",
+            id
+        );
+        return None;
+    }
+    let tags = obj.tags();
+    let house_number = tags.get("addr:housenumber")?;
+    let street_name = tags.get("addr:street").or_else(|| tags.get("addr:place"))?;
+    Some(make_addr(
+        id,
+        house_number,
+        street_name,
+        tags.get("addr:postcode").map(String::as_str),
+        coord,
+        admins_geofinder,
+    ))
+}
+
+/// For the object to be an address interpolation, it needs to be a way
+/// tagged with `addr:interpolation`
+pub fn is_interpolation(obj: &OsmObj) -> bool {
+    obj.is_way() && obj.tags().contains_key("addr:interpolation")
+}
+
+/// read the addresses interpolated along the osm address interpolation ways
+pub fn read_interpolated_addresses(
+    pbf: &mut OsmPbfReader,
+    admins_geofinder: &AdminGeoFinder,
+) -> Vec<mimir::Addr> {
+    info!("reading pbf...");
+    let objects = pbf.get_objs_and_deps(is_interpolation).unwrap();
+    info!("reading pbf done.");
+    objects
+        .values()
+        .filter(|obj| is_interpolation(obj))
+        .filter_map(|obj| obj.way())
+        .flat_map(|way| interpolate_addresses(way, &objects, admins_geofinder))
+        .collect()
+}
+
+/// the interpolated addresses of an interpolation way
+///
+/// The addresses are interpolated between the nodes of the way with a house number,
+/// the house numbers of these nodes are real addresses, so they are not interpolated.
+pub fn interpolate_addresses(
+    way: &osmpbfreader::Way,
+    objects: &BTreeMap<OsmId, OsmObj>,
+    admins_geofinder: &AdminGeoFinder,
+) -> Vec<mimir::Addr> {
+    let step = match interpolation_step(&way.tags) {
+        Some(step) => step,
+        None => {
+            warn!(
+                "way/{}: unhandled addr:interpolation {:?}, skipped",
+                way.id.0,
+                way.tags.get("addr:interpolation")
+            );
+            return vec![];
+        }
+    };
+    let nodes: Vec<&osmpbfreader::Node> = way
+        .nodes
+        .iter()
+        .filter_map(|id| objects.get(&(*id).into())?.node())
+        .collect();
+    if nodes.len() != way.nodes.len() {
+        warn!(
+            "way/{}: some nodes of the interpolation are missing, skipped",
+            way.id.0
+        );
+        return vec![];
+    }
+    let get_tag = |key: &str| {
+        way.tags
+            .get(key)
+            .or_else(|| nodes.iter().filter_map(|n| n.tags.get(key)).next())
+    };
+    let street_name = match get_tag("addr:street").or_else(|| get_tag("addr:place")) {
+        Some(name) => name,
+        None => {
+            warn!("way/{}: interpolation without street, skipped", way.id.0);
+            return vec![];
+        }
+    };
+    let postcode = get_tag("addr:postcode").map(String::as_str);
+
+    let numbered: Vec<(usize, u32)> = nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, node)| {
+            let number = node.tags.get("addr:housenumber")?.trim().parse().ok()?;
+            Some((idx, number))
+        })
+        .collect();
+    let coords: Vec<geo_types::Coordinate<f64>> = nodes
+        .iter()
+        .map(|node| geo_types::Coordinate {
+            x: node.lon(),
+            y: node.lat(),
+        })
+        .collect();
+
+    let mut addresses = vec![];
+    for bounds in numbered.windows(2) {
+        let ((start_idx, start), (end_idx, end)) = (bounds[0], bounds[1]);
+        let (min, max) = (start.min(end), start.max(end));
+        if (max - min) % step != 0 || (max - min) / step > MAX_INTERPOLATED_ADDRESSES {
+            warn!(
+                "way/{}: impossible to interpolate from {} to {} with the step {}",
+                way.id.0, start, end, step
+            );
+            continue;
+        }
+        let line = &coords[start_idx..=end_idx];
+        for number in (min + step..max).step_by(step as usize) {
+            let fraction =
+                (f64::from(number) - f64::from(start)) / (f64::from(end) - f64::from(start));
+            let coord = match point_along(line, fraction) {
+                Some(coord) => mimir::Coord(coord),
+                None => continue,
+            };
+            let mut addr = make_addr(
+                format!("addr:osm:way:{}:{}", way.id.0, number),
+                &number.to_string(),
+                street_name,
+                postcode,
+                coord,
+                admins_geofinder,
+            );
+            addr.interpolated = true;
+            addresses.push(addr);
+        }
+    }
+    addresses
+}
+
+/// the step between two house numbers of an interpolation
+fn interpolation_step(tags: &osmpbfreader::Tags) -> Option<u32> {
+    match tags.get("addr:interpolation")?.as_str() {
+        "odd" | "even" => Some(2),
+        "all" => Some(1),
+        step => step.parse().ok().filter(|step| *step > 0),
+    }
+}
+
+/// the point at `fraction` of the length of the line
+fn point_along(
+    line: &[geo_types::Coordinate<f64>],
+    fraction: f64,
+) -> Option<geo_types::Coordinate<f64>> {
+    let lengths: Vec<f64> = line
+        .windows(2)
+        .map(|segment| {
+            let (start, end): (geo_types::Point<f64>, geo_types::Point<f64>) =
+                (segment[0].into(), segment[1].into());
+            start.haversine_distance(&end)
+        })
+        .collect();
+    let total: f64 = lengths.iter().sum();
+    if total == 0. {
+        return None;
+    }
+    let mut remaining = fraction * total;
+    for (segment, length) in line.windows(2).zip(lengths) {
+        if remaining <= length && length > 0. {
+            let t = remaining / length;
+            return Some(geo_types::Coordinate {
+                x: segment[0].x + t * (segment[1].x - segment[0].x),
+                y: segment[0].y + t * (segment[1].y - segment[0].y),
+            });
+        }
+        remaining -= length;
+    }
+    line.last().cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use osmpbfreader::{NodeId, WayId};
+
+    fn node(id: i64, lon: f64, lat: f64, tags: &[(&str, &str)]) -> OsmObj {
+        OsmObj::Node(osmpbfreader::Node {
+            id: NodeId(id),
+            tags: tags.iter().map(|&(k, v)| (k.into(), v.into())).collect(),
+            decimicro_lat: (lat * 1e7).round() as i32,
+            decimicro_lon: (lon * 1e7).round() as i32,
+        })
+    }
+
+    fn interpolation(
+        kind: &str,
+        numbers: (&str, &str),
+    ) -> (osmpbfreader::Way, BTreeMap<OsmId, OsmObj>) {
+        let objects: BTreeMap<_, _> = vec![
+            node(1, 2.0, 48.0, &[("addr:housenumber", numbers.0)]),
+            node(2, 2.001, 48.0, &[]),
+            node(3, 2.002, 48.0, &[("addr:housenumber", numbers.1)]),
+        ]
+        .into_iter()
+        .map(|obj| (obj.id(), obj))
+        .collect();
+        let way = osmpbfreader::Way {
+            id: WayId(10),
+            tags: vec![
+                ("addr:interpolation", kind),
+                ("addr:street", "Rue Hector Malot"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect(),
+            nodes: vec![NodeId(1), NodeId(2), NodeId(3)],
+        };
+        (way, objects)
+    }
+
+    #[test]
+    fn osm_address() {
+        let objects: BTreeMap<_, _> = vec![node(
+            1,
+            2.377,
+            48.847,
+            &[
+                ("addr:housenumber", "10"),
+                ("addr:street", "Rue Hector Malot"),
+            ],
+        )]
+        .into_iter()
+        .map(|obj| (obj.id(), obj))
+        .collect();
+        let obj = &objects[&OsmId::Node(NodeId(1))];
+        assert!(is_addr(obj));
+        let addr = parse_addr(obj, &objects, &AdminGeoFinder::default()).unwrap();
+        assert_eq!(addr.id, "addr:osm:node:1");
+        assert_eq!(addr.house_number, "10");
+        assert_eq!(addr.street.name, "Rue Hector Malot");
+        assert!(!addr.interpolated);
+
+        // no street, no address
+        assert!(!is_addr(&node(
+            2,
+            2.377,
+            48.847,
+            &[("addr:housenumber", "10")]
+        )));
+    }
+
+    #[test]
+    fn odd_interpolation() {
+        let (way, objects) = interpolation("odd", ("1", "9"));
+        let addresses = interpolate_addresses(&way, &objects, &AdminGeoFinder::default());
+        let numbers: Vec<_> = addresses.iter().map(|a| a.house_number.as_str()).collect();
+        assert_eq!(numbers, vec!["3", "5", "7"]);
+        assert!(addresses.iter().all(|a| a.interpolated));
+        assert_eq!(addresses[0].id, "addr:osm:way:10:3");
+        assert_eq!(addresses[0].street.name, "Rue Hector Malot");
+        // the numbers are regularly spread along the way
+        assert!((addresses[0].coord.lon() - 2.0005).abs() < 1e-6);
+        assert!((addresses[1].coord.lon() - 2.001).abs() < 1e-6);
+        assert!((addresses[2].coord.lon() - 2.0015).abs() < 1e-6);
+    }
+
+    #[test]
+    fn decreasing_interpolation() {
+        let (way, objects) = interpolation("even", ("8", "2"));
+        let addresses = interpolate_addresses(&way, &objects, &AdminGeoFinder::default());
+        let numbers: Vec<_> = addresses.iter().map(|a| a.house_number.as_str()).collect();
+        assert_eq!(numbers, vec!["4", "6"]);
+        // the numbers decrease along the way
+        assert!((addresses[0].coord.lon() - (2.0 + 0.002 * 2. / 3.)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn invalid_interpolation() {
+        // the bounds must have the same parity
+        let (way, objects) = interpolation("odd", ("1", "8"));
+        assert!(interpolate_addresses(&way, &objects, &AdminGeoFinder::default()).is_empty());
+        let (way, objects) = interpolation("alphabetic", ("1", "1c"));
+        assert!(interpolate_addresses(&way, &objects, &AdminGeoFinder::default()).is_empty());
+    }
+}
@@ -35,7 +35,7 @@ use geo::bounding_rect::BoundingRect;
 use itertools::Itertools;
 use osm_boundaries_utils::build_boundary;
 use slog_scope::{info, warn};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 pub type StreetsVec = Vec<mimir::Street>;
 
@@ -83,103 +83,9 @@ pub fn read_administrative_regions(
             continue;
         }
         if let osmpbfreader::OsmObj::Relation(ref relation) = *obj {
-            let level = relation
-                .tags
-                .get("admin_level")
-                .and_then(|s| s.parse().ok());
-            let level = match level {
-                None => {
-                    warn!(
-                        "relation/{} ({}): invalid admin_level: {:?}, skipped",
-                        relation.id.0,
-                        relation.tags.get("name").map_or("", String::as_str),
-                        relation.tags.get("admin_level")
-                    );
-                    continue;
-                }
-                Some(l) => l,
-            };
-            // administrative region with name ?
-            let name = match relation.tags.get("name") {
-                Some(val) => val,
-                None => {
-                    warn!(
-                        "relation/{}: adminstrative region without name, skipped",
-                        relation.id.0
-                    );
-                    continue;
-                }
-            };
-
-            // admininstrative region without coordinates
-            let coord_center = relation
-                .refs
-                .iter()
-                .find(|r| r.role == "admin_centre")
-                .and_then(|r| objects.get(&r.member))
-                .and_then(|o| o.node())
-                .map(|node| mimir::Coord::new(node.lon(), node.lat()));
-            let (admin_id, insee_id) = match read_insee(&relation.tags) {
-                Some(val) if !insee_inserted.contains(val) => {
-                    insee_inserted.insert(val.to_string());
-                    (format!("admin:fr:{}", val), val)
-                }
-                Some(val) => {
-                    let id = format!("admin:osm:relation:{}", relation.id.0);
-                    warn!(
-                        "relation/{}: have the INSEE {} that is already used, using {} as id",
-                        relation.id.0, val, id
-                    );
-                    (id, val)
-                }
-                None => (format!("admin:osm:relation:{}", relation.id.0), ""),
-            };
-
-            let zip_codes = read_zip_codes(&relation.tags);
-            let boundary = build_boundary(relation, &objects);
-            let zone_type = get_zone_type(level, city_level);
-
-            let weight = relation
-                .tags
-                .get("population")
-                .and_then(|p| p.parse().ok())
-                .or_else(|| {
-                    let rel = relation.refs.iter().find(|r| r.role == "admin_centre")?;
-                    objects
-                        .get(&rel.member)?
-                        .node()?
-                        .tags
-                        .get("population")?
-                        .parse()
-                        .ok()
-                })
-                .unwrap_or(0.);
-
-            let coord = coord_center.unwrap_or_else(|| make_centroid(&boundary));
-            let codes = get_osm_codes_from_tags(&relation.tags);
-            let admin = mimir::Admin {
-                id: admin_id,
-                insee: insee_id.to_string(),
-                level: level,
-                name: name.to_string(),
-                label: format!("{}{}", name.to_string(), format_zip_codes(&zip_codes)),
-                zip_codes: zip_codes,
-                weight: weight,
-                coord: coord.clone(),
-                approx_coord: Some(coord.into()),
-                bbox: boundary.as_ref().and_then(|b| b.bounding_rect()),
-                boundary: boundary,
-                zone_type: zone_type,
-                parent_id: None,
-                country_codes: utils::get_country_code(&codes).into_iter().collect(),
-                codes: codes,
-                names: mimir::I18nProperties::default(),
-                labels: mimir::I18nProperties::default(),
-                distance: None,
-                context: None,
-                administrative_regions: Vec::new(),
-            };
-            administrative_regions.push(admin);
+            if let Some(admin) = make_admin(relation, &objects, city_level, &mut insee_inserted) {
+                administrative_regions.push(admin);
+            }
         }
     }
 
@@ -188,6 +94,114 @@ pub fn read_administrative_regions(
     administrative_regions
 }
 
+/// build the admin of an administrative boundary relation
+///
+/// `insee_inserted` holds the INSEE codes already used as id, an admin
+/// with the same INSEE is identified by its osm id
+pub fn make_admin(
+    relation: &osmpbfreader::Relation,
+    objects: &BTreeMap<osmpbfreader::OsmId, osmpbfreader::OsmObj>,
+    city_level: u32,
+    insee_inserted: &mut BTreeSet<String>,
+) -> Option<mimir::Admin> {
+    let level = relation
+        .tags
+        .get("admin_level")
+        .and_then(|s| s.parse().ok());
+    let level = match level {
+        None => {
+            warn!(
+                "relation/{} ({}): invalid admin_level: {:?}, skipped",
+                relation.id.0,
+                relation.tags.get("name").map_or("", String::as_str),
+                relation.tags.get("admin_level")
+            );
+            return None;
+        }
+        Some(l) => l,
+    };
+    // administrative region with name ?
+    let name = match relation.tags.get("name") {
+        Some(val) => val,
+        None => {
+            warn!(
+                "relation/{}: adminstrative region without name, skipped",
+                relation.id.0
+            );
+            return None;
+        }
+    };
+
+    // admininstrative region without coordinates
+    let coord_center = relation
+        .refs
+        .iter()
+        .find(|r| r.role == "admin_centre")
+        .and_then(|r| objects.get(&r.member))
+        .and_then(|o| o.node())
+        .map(|node| mimir::Coord::new(node.lon(), node.lat()));
+    let (admin_id, insee_id) = match read_insee(&relation.tags) {
+        Some(val) if !insee_inserted.contains(val) => {
+            insee_inserted.insert(val.to_string());
+            (format!("admin:fr:{}", val), val)
+        }
+        Some(val) => {
+            let id = format!("admin:osm:relation:{}", relation.id.0);
+            warn!(
+                "relation/{}: have the INSEE {} that is already used, using {} as id",
+                relation.id.0, val, id
+            );
+            (id, val)
+        }
+        None => (format!("admin:osm:relation:{}", relation.id.0), ""),
+    };
+
+    let zip_codes = read_zip_codes(&relation.tags);
+    let boundary = build_boundary(relation, objects);
+    let zone_type = get_zone_type(level, city_level);
+
+    let weight = relation
+        .tags
+        .get("population")
+        .and_then(|p| p.parse().ok())
+        .or_else(|| {
+            let rel = relation.refs.iter().find(|r| r.role == "admin_centre")?;
+            objects
+                .get(&rel.member)?
+                .node()?
+                .tags
+                .get("population")?
+                .parse()
+                .ok()
+        })
+        .unwrap_or(0.);
+
+    let coord = coord_center.unwrap_or_else(|| make_centroid(&boundary));
+    let codes = get_osm_codes_from_tags(&relation.tags);
+    Some(mimir::Admin {
+        id: admin_id,
+        insee: insee_id.to_string(),
+        level: level,
+        name: name.to_string(),
+        label: format!("{}{}", name.to_string(), format_zip_codes(&zip_codes)),
+        zip_codes: zip_codes,
+        weight: weight,
+        coord: coord.clone(),
+        approx_coord: Some(coord.into()),
+        bbox: boundary.as_ref().and_then(|b| b.bounding_rect()),
+        boundary: boundary,
+        zone_type: zone_type,
+        parent_id: None,
+        country_codes: utils::get_country_code(&codes).into_iter().collect(),
+        codes: codes,
+        names: mimir::I18nProperties::default(),
+        labels: mimir::I18nProperties::default(),
+        distance: None,
+        context: None,
+        administrative_regions: Vec::new(),
+    })
+}
+
 fn get_zone_type(level: u32, city_lvl: u32) -> Option<ZoneType> {
     if level == city_lvl {
         Some(ZoneType::City)
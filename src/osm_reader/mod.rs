@@ -4,7 +4,9 @@ use crate::Error;
 use std::fs::File;
 use std::path::Path;
 
+pub mod addr;
 pub mod admin;
+pub mod osm_change;
 pub mod osm_utils;
 pub mod poi;
 pub mod street;
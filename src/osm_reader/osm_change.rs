@@ -0,0 +1,829 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Reading of the OSM change files (osmChange, .osc), used to update an
+//! existing dataset without reimporting the whole pbf.
+//!
+//! A change file only contains the created, modified and deleted objects,
+//! so the dependencies of an object (the nodes of a modified way for example)
+//! are often missing. In this case the coordinate of the object already
+//! indexed is used (cf. `OsmChange::located_ids`).
+
+use super::admin::{make_admin, AdminMatcher};
+use super::osm_utils::get_way_line;
+use super::poi::{make_poi, poi_id, PoiConfig};
+use super::street::{is_valid_street, make_street};
+use crate::admin_geofinder::AdminGeoFinder;
+use crate::{utils, Error};
+use failure::{format_err, ResultExt};
+use flate2::read::GzDecoder;
+use geo::centroid::Centroid;
+use geo::{LineString, MultiLineString};
+use osm_boundaries_utils::build_boundary;
+use osmpbfreader::{NodeId, OsmId, OsmObj, RelationId, WayId};
+use slog_scope::{info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+/// The coordinates of the documents already indexed, by document id
+pub type KnownCoords = BTreeMap<String, mimir::Coord>;
+
+/// The result of one or several change files
+#[derive(Debug, Default)]
+pub struct OsmChange {
+    /// the created or modified objects, in their last version
+    pub objects: BTreeMap<OsmId, OsmObj>,
+    /// the deleted objects
+    pub deleted: BTreeSet<OsmId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Create,
+    Modify,
+    Delete,
+}
+
+impl OsmChange {
+    /// read a change file (gzipped if its extension is `gz`)
+    ///
+    /// The changes are applied on top of the changes already read,
+    /// so the files must be read in chronological order.
+    pub fn read(&mut self, path: &Path) -> Result<(), Error> {
+        info!("reading change file {:?}", path);
+        let file = File::open(path).with_context(|_| format!("impossible to open {:?}", path))?;
+        let with_gzip = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext == "gz")
+            .unwrap_or(false);
+        let res = if with_gzip {
+            self.read_from(BufReader::new(GzDecoder::new(file)))
+        } else {
+            self.read_from(BufReader::new(file))
+        };
+        res.with_context(|_| format!("impossible to read the change file {:?}", path))?;
+        Ok(())
+    }
+
+    /// read an osmChange document
+    pub fn read_from<R: BufRead>(&mut self, reader: R) -> Result<(), Error> {
+        let mut action = None;
+        let mut current: Option<OsmObj> = None;
+        let mut tags = Vec::new();
+        for event in EventReader::new(reader) {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => match name.local_name.as_str() {
+                    "create" => action = Some(Action::Create),
+                    "modify" => action = Some(Action::Modify),
+                    "delete" => action = Some(Action::Delete),
+                    "node" => {
+                        let id = NodeId(parse_attribute(&attributes, "id")?);
+                        // the deleted nodes may have no coordinates
+                        let (lat, lon) = if action == Some(Action::Delete) {
+                            (0., 0.)
+                        } else {
+                            (
+                                parse_attribute(&attributes, "lat")?,
+                                parse_attribute(&attributes, "lon")?,
+                            )
+                        };
+                        current = Some(OsmObj::Node(osmpbfreader::Node {
+                            id,
+                            tags: osmpbfreader::Tags::new(),
+                            decimicro_lat: to_decimicro(lat),
+                            decimicro_lon: to_decimicro(lon),
+                        }));
+                    }
+                    "way" => {
+                        current = Some(OsmObj::Way(osmpbfreader::Way {
+                            id: WayId(parse_attribute(&attributes, "id")?),
+                            tags: osmpbfreader::Tags::new(),
+                            nodes: vec![],
+                        }));
+                    }
+                    "relation" => {
+                        current = Some(OsmObj::Relation(osmpbfreader::Relation {
+                            id: RelationId(parse_attribute(&attributes, "id")?),
+                            tags: osmpbfreader::Tags::new(),
+                            refs: vec![],
+                        }));
+                    }
+                    "tag" => tags.push((
+                        get_attribute(&attributes, "k")?.to_string(),
+                        get_attribute(&attributes, "v")?.to_string(),
+                    )),
+                    "nd" => {
+                        if let Some(OsmObj::Way(ref mut way)) = current {
+                            way.nodes.push(NodeId(parse_attribute(&attributes, "ref")?));
+                        }
+                    }
+                    "member" => {
+                        if let Some(OsmObj::Relation(ref mut rel)) = current {
+                            let id = parse_attribute(&attributes, "ref")?;
+                            let member = match get_attribute(&attributes, "type")? {
+                                "node" => OsmId::Node(NodeId(id)),
+                                "way" => OsmId::Way(WayId(id)),
+                                "relation" => OsmId::Relation(RelationId(id)),
+                                t => return Err(format_err!("invalid member type {}", t)),
+                            };
+                            rel.refs.push(osmpbfreader::Ref {
+                                member,
+                                role: get_attribute(&attributes, "role")?.into(),
+                            });
+                        }
+                    }
+                    _ => {}
+                },
+                XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                    "create" | "modify" | "delete" => action = None,
+                    "node" | "way" | "relation" => {
+                        let mut obj = current
+                            .take()
+                            .ok_or_else(|| format_err!("unexpected end of {}", name))?;
+                        let obj_tags: osmpbfreader::Tags =
+                            tags.drain(..).map(|(k, v)| (k.into(), v.into())).collect();
+                        match obj {
+                            OsmObj::Node(ref mut n) => n.tags = obj_tags,
+                            OsmObj::Way(ref mut w) => w.tags = obj_tags,
+                            OsmObj::Relation(ref mut r) => r.tags = obj_tags,
+                        }
+                        match action {
+                            Some(Action::Create) | Some(Action::Modify) => self.upsert(obj),
+                            Some(Action::Delete) => self.delete(obj.id()),
+                            None => return Err(format_err!("{:?} outside of an action", obj.id())),
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn upsert(&mut self, obj: OsmObj) {
+        self.deleted.remove(&obj.id());
+        self.objects.insert(obj.id(), obj);
+    }
+
+    fn delete(&mut self, id: OsmId) {
+        self.objects.remove(&id);
+        self.deleted.insert(id);
+    }
+
+    /// the ids of the documents that may be updated, but whose coordinate can't
+    /// be computed with the change files only
+    ///
+    /// The coordinates of these documents must be given to `streets` and `pois`
+    pub fn located_ids(&self) -> Vec<String> {
+        self.objects
+            .values()
+            .filter_map(|obj| match *obj {
+                OsmObj::Way(ref way) if self.way_coord(way).is_none() => {
+                    Some(vec![street_id(obj.id()), poi_id(obj.id())])
+                }
+                OsmObj::Relation(_) => Some(vec![street_id(obj.id()), poi_id(obj.id())]),
+                _ => None,
+            })
+            .flat_map(|ids| ids.into_iter())
+            .collect()
+    }
+
+    /// the created or modified streets
+    ///
+    /// Like for a full import, the ways of an associatedStreet relation are
+    /// not imported as streets.
+    pub fn streets(
+        &self,
+        admins_geofinder: &AdminGeoFinder,
+        known_coords: &KnownCoords,
+    ) -> Vec<mimir::Street> {
+        let street_ways: BTreeSet<OsmId> = self
+            .objects
+            .values()
+            .filter(|obj| obj.is_relation() && is_valid_street(obj))
+            .filter_map(|obj| obj.relation())
+            .flat_map(|rel| rel.refs.iter())
+            .filter(|r| r.member.is_way())
+            .map(|r| r.member)
+            .collect();
+
+        self.objects
+            .values()
+            .filter(|obj| is_valid_street(obj) && !street_ways.contains(&obj.id()))
+            .filter_map(|obj| {
+                let id = street_id(obj.id());
+                let (name, coord, geometry) = match *obj {
+                    OsmObj::Way(ref way) => (
+                        way.tags.get("name")?,
+                        self.way_coord(way),
+                        self.way_line(way).map(|line| MultiLineString(vec![line])),
+                    ),
+                    OsmObj::Relation(ref rel) => {
+                        // the name of the relation, or the name of one of its ways
+                        let way = rel
+                            .refs
+                            .iter()
+                            .filter(|r| r.member.is_way() && r.role == "street")
+                            .filter_map(|r| self.objects.get(&r.member)?.way())
+                            .next();
+                        let name = rel
+                            .tags
+                            .get("name")
+                            .or_else(|| way?.tags.get("name"))
+                            .or_else(|| {
+                                warn!("{}: no name found in the changes, skipped", id);
+                                None
+                            })?;
+                        // the geometry is kept only if all the ways are in the changes
+                        let lines: Option<Vec<_>> = rel
+                            .refs
+                            .iter()
+                            .filter(|r| r.member.is_way() && r.role == "street")
+                            .map(|r| self.way_line(self.objects.get(&r.member)?.way()?))
+                            .collect();
+                        let geometry = lines.filter(|lines| !lines.is_empty()).map(MultiLineString);
+                        (name, way.and_then(|w| self.way_coord(w)), geometry)
+                    }
+                    _ => return None,
+                };
+                let coord = coord
+                    .or_else(|| known_coords.get(&id).cloned())
+                    .or_else(|| {
+                        warn!("{}: no coordinate found, skipped", id);
+                        None
+                    })?;
+                let admins = admins_geofinder.get(&coord);
+                Some(make_street(id, name, coord, geometry, admins))
+            })
+            .collect()
+    }
+
+    /// the ids of the streets to remove: the deleted objects and the objects
+    /// that are no longer streets
+    ///
+    /// Those ids must be resolved against the indexed streets with `merge_streets`.
+    pub fn deleted_street_ids(&self) -> Vec<String> {
+        self.deleted
+            .iter()
+            .cloned()
+            .chain(
+                self.objects
+                    .values()
+                    .filter(|obj| !is_valid_street(obj))
+                    .map(|obj| obj.id()),
+            )
+            .filter(|id| !id.is_node())
+            .map(street_id)
+            .collect()
+    }
+
+    /// the created or modified pois
+    pub fn pois(
+        &self,
+        matcher: &PoiConfig,
+        admins_geofinder: &AdminGeoFinder,
+        known_coords: &KnownCoords,
+    ) -> Vec<mimir::Poi> {
+        self.objects
+            .values()
+            .filter(|obj| matcher.is_poi(obj.tags()))
+            .filter_map(|obj| {
+                let coord = match *obj {
+                    OsmObj::Node(ref node) => Some(mimir::Coord::new(node.lon(), node.lat())),
+                    OsmObj::Way(ref way) => self.way_coord(way),
+                    OsmObj::Relation(ref rel) => build_boundary(rel, &self.objects)
+                        .as_ref()
+                        .and_then(|b| b.centroid())
+                        .map(|c| mimir::Coord::new(c.x(), c.y())),
+                };
+                let id = poi_id(obj.id());
+                let coord = coord
+                    .or_else(|| known_coords.get(&id).cloned())
+                    .or_else(|| {
+                        warn!("{}: no coordinate found, skipped", id);
+                        None
+                    })?;
+                make_poi(obj, coord, matcher, admins_geofinder)
+            })
+            .collect()
+    }
+
+    /// the ids of the pois that may have to be removed: the deleted objects and the objects
+    /// that are no longer pois
+    ///
+    /// The previous version of the objects is not in the change files, so most of those
+    /// objects were not pois, only the ids already indexed must be removed.
+    pub fn deleted_poi_ids(&self, matcher: &PoiConfig) -> Vec<String> {
+        self.deleted
+            .iter()
+            .cloned()
+            .chain(
+                self.objects
+                    .values()
+                    .filter(|obj| !matcher.is_poi(obj.tags()))
+                    .map(|obj| obj.id()),
+            )
+            .map(poi_id)
+            .collect()
+    }
+
+    /// the created or modified admins
+    ///
+    /// An admin is updated only if its whole boundary is in the changes.
+    /// As there is no way to know the INSEE codes already used, two admins
+    /// with the same INSEE can get the same id.
+    pub fn admins(&self, matcher: &AdminMatcher, city_level: u32) -> Vec<mimir::Admin> {
+        let mut insee_inserted = BTreeSet::new();
+        let mut admins: Vec<_> = self
+            .objects
+            .values()
+            .filter(|obj| matcher.is_admin(obj))
+            .filter_map(|obj| obj.relation())
+            .filter_map(|rel| {
+                let admin = make_admin(rel, &self.objects, city_level, &mut insee_inserted)?;
+                if admin.boundary.is_none() {
+                    warn!(
+                        "relation/{}: the boundary is not complete in the changes, skipped",
+                        rel.id.0
+                    );
+                    return None;
+                }
+                Some(admin)
+            })
+            .collect();
+        utils::normalize_admin_weight(&mut admins);
+        admins
+    }
+
+    /// the ids of the admins to remove
+    ///
+    /// Only the admins identified by their osm id can be removed, the
+    /// admins identified by their INSEE code must be removed by a full import.
+    pub fn deleted_admin_ids(&self, matcher: &AdminMatcher) -> Vec<String> {
+        self.deleted
+            .iter()
+            .cloned()
+            .chain(
+                self.objects
+                    .values()
+                    .filter(|obj| obj.is_relation() && !matcher.is_admin(obj))
+                    .map(|obj| obj.id()),
+            )
+            .filter_map(|id| match id {
+                OsmId::Relation(id) => Some(format!("admin:osm:relation:{}", id.0)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// the line of the way, if all its nodes are in the changes
+    fn way_line(&self, way: &osmpbfreader::Way) -> Option<LineString<f64>> {
+        get_way_line(&self.objects, way).filter(|line| line.0.len() == way.nodes.len())
+    }
+
+    /// a middle node of the way is used, like for a full import
+    fn way_coord(&self, way: &osmpbfreader::Way) -> Option<mimir::Coord> {
+        let nb_nodes = way.nodes.len();
+        way.nodes
+            .iter()
+            .skip(nb_nodes / 2)
+            .filter_map(|id| self.objects.get(&(*id).into())?.node())
+            .map(|node| mimir::Coord::new(node.lon(), node.lat()))
+            .next()
+    }
+}
+
+/// the key used to merge the ways of a street: its name and its cities, like for a full import
+type StreetKey = (String, BTreeSet<String>);
+
+fn street_key(street: &mimir::Street) -> StreetKey {
+    let cities = street
+        .administrative_regions
+        .iter()
+        .filter(|admin| admin.is_city())
+        .map(|admin| admin.id.clone())
+        .collect();
+    (street.name.clone(), cities)
+}
+
+fn way_id(street: &mimir::Street) -> Option<i64> {
+    street.id.trim_start_matches("street:osm:way:").parse().ok()
+}
+
+/// a street built from several ways by a full import
+fn is_merged_street(street: &mimir::Street) -> bool {
+    way_id(street).is_some()
+        && street
+            .geometry
+            .as_ref()
+            .map(|geometry| geometry.0.len() > 1)
+            .unwrap_or(false)
+}
+
+fn add_lines(target: &mut mimir::Street, street: mimir::Street) {
+    let lines = street
+        .geometry
+        .map(|geometry| geometry.0)
+        .unwrap_or_default();
+    let geometry = target
+        .geometry
+        .get_or_insert_with(|| MultiLineString(vec![]));
+    for line in lines {
+        if !geometry.0.contains(&line) {
+            geometry.0.push(line);
+        }
+    }
+}
+
+/// Resolve the streets and the deleted street ids of the changes against the
+/// streets already indexed, `indexed` must contain the indexed streets with the same
+/// names than the changed streets, or the same ids than the changed or deleted streets
+/// (the deleted ids that are not indexed are dropped).
+///
+/// A full import merges the ways with the same name in the same cities into one street,
+/// whose id is the one of its smallest way (cf `street::streets`). So:
+///  * a way is merged into the indexed street with the same name and cities, whose id is kept,
+///    and the ways of the changes with the same name and cities are merged together,
+///  * the geometry of a merged street gets the line of the way (the previous line of a
+///    modified way can't be identified, it is only removed by the next full import),
+///  * a street that merges several ways is not removed when the way carrying its id
+///    is deleted or renamed, the other ways of the street still exist.
+///
+/// The indexed street of a renamed way that has been merged into another street is removed.
+pub fn merge_streets(
+    streets: Vec<mimir::Street>,
+    deleted: Vec<String>,
+    indexed: &[mimir::Street],
+) -> (Vec<mimir::Street>, Vec<String>) {
+    let indexed_by_id: BTreeMap<&str, &mimir::Street> =
+        indexed.iter().map(|s| (s.id.as_str(), s)).collect();
+    let mut indexed_by_key: BTreeMap<StreetKey, &mimir::Street> = BTreeMap::new();
+    for street in indexed.iter().filter(|s| way_id(s).is_some()) {
+        indexed_by_key
+            .entry(street_key(street))
+            .and_modify(|s| {
+                if way_id(street) < way_id(s) {
+                    *s = street
+                }
+            })
+            .or_insert(street);
+    }
+
+    let mut merged: BTreeMap<StreetKey, mimir::Street> = BTreeMap::new();
+    let mut result = vec![];
+    let mut way_ids = vec![];
+    for street in streets {
+        if way_id(&street).is_none() {
+            result.push(street);
+            continue;
+        }
+        way_ids.push(street.id.clone());
+        let key = street_key(&street);
+        if let Some(target) = merged.get_mut(&key) {
+            // the smallest way gives its id and its coordinate to a street that is not indexed yet
+            if !indexed_by_key.contains_key(&key) && way_id(&street) < way_id(target) {
+                target.id = street.id.clone();
+                target.coord = street.coord;
+            }
+            add_lines(target, street);
+            continue;
+        }
+        if let Some(target) = indexed_by_key.get(&key) {
+            let target = if target.id == street.id && !is_merged_street(target) {
+                street
+            } else {
+                let mut target = (*target).clone();
+                // the approximate coordinates are not deserialized with the indexed street
+                target.approx_coord = Some(target.coord.into());
+                add_lines(&mut target, street);
+                target
+            };
+            merged.insert(key, target);
+            continue;
+        }
+        match indexed_by_id.get(street.id.as_str()) {
+            Some(previous) if is_merged_street(previous) && street_key(previous) != key => {
+                warn!(
+                    "{}: the way is renamed but it carries the id of a merged street, \
+                     it is kept until the next full import",
+                    street.id
+                );
+            }
+            _ => {
+                merged.insert(key, street);
+            }
+        }
+    }
+    result.extend(merged.into_iter().map(|(_, street)| street));
+
+    // the indexed streets of the renamed ways, that are now merged into other streets
+    let renamed = way_ids.into_iter().filter(|id| {
+        indexed_by_id.contains_key(id.as_str()) && !result.iter().any(|s| &s.id == id)
+    });
+    let deleted = deleted
+        .into_iter()
+        .chain(renamed)
+        .filter(|id| match indexed_by_id.get(id.as_str()) {
+            // most of the deleted or modified ways were not streets, there is nothing to remove
+            None => false,
+            Some(previous) if is_merged_street(previous) => {
+                warn!(
+                    "{}: the street merges several ways, it is kept until the next full import",
+                    id
+                );
+                false
+            }
+            Some(_) => true,
+        })
+        .collect();
+    (result, deleted)
+}
+
+fn street_id(id: OsmId) -> String {
+    match id {
+        OsmId::Node(id) => format!("street:osm:node:{}", id.0),
+        OsmId::Way(id) => format!("street:osm:way:{}", id.0),
+        OsmId::Relation(id) => format!("street:osm:relation:{}", id.0),
+    }
+}
+
+fn to_decimicro(coord: f64) -> i32 {
+    (coord * 1e7).round() as i32
+}
+
+fn get_attribute<'a>(attributes: &'a [OwnedAttribute], name: &str) -> Result<&'a str, Error> {
+    attributes
+        .iter()
+        .find(|a| a.name.local_name == name)
+        .map(|a| a.value.as_str())
+        .ok_or_else(|| format_err!("missing attribute {}", name))
+}
+
+fn parse_attribute<T: FromStr>(attributes: &[OwnedAttribute], name: &str) -> Result<T, Error> {
+    let value = get_attribute(attributes, name)?;
+    value
+        .parse()
+        .map_err(|_| format_err!("invalid attribute {}: {}", name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANGES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osmChange version="0.6" generator="test">
+  <create>
+    <node id="1" version="1" lat="48.8469" lon="2.377"/>
+    <node id="2" version="1" lat="48.847" lon="2.378"/>
+    <node id="3" version="1" lat="48.8443" lon="2.3731">
+      <tag k="amenity" v="townhall"/>
+      <tag k="name" v="Mairie du 12e"/>
+    </node>
+    <way id="10" version="1">
+      <nd ref="1"/>
+      <nd ref="2"/>
+      <tag k="highway" v="residential"/>
+      <tag k="name" v="Rue Hector Malot"/>
+    </way>
+  </create>
+  <modify>
+    <node id="4" version="2" lat="48.84" lon="2.37">
+      <tag k="amenity" v="parking"/>
+    </node>
+    <way id="11" version="2">
+      <nd ref="100"/>
+      <nd ref="101"/>
+      <tag k="highway" v="primary"/>
+      <tag k="name" v="Avenue Daumesnil"/>
+    </way>
+    <way id="12" version="3">
+      <nd ref="102"/>
+      <tag k="name" v="Ancienne rue"/>
+    </way>
+  </modify>
+  <delete>
+    <node id="4" version="3"/>
+    <node id="5" version="2" lat="48.85" lon="2.38"/>
+    <way id="13" version="4"/>
+    <relation id="20" version="2"/>
+  </delete>
+</osmChange>"#;
+
+    fn read_changes() -> OsmChange {
+        let mut changes = OsmChange::default();
+        changes.read_from(CHANGES.as_bytes()).unwrap();
+        changes
+    }
+
+    #[test]
+    fn read_osm_change() {
+        let changes = read_changes();
+        let ids: Vec<_> = changes.objects.keys().cloned().collect();
+        assert_eq!(
+            ids,
+            vec![
+                OsmId::Node(NodeId(1)),
+                OsmId::Node(NodeId(2)),
+                OsmId::Node(NodeId(3)),
+                OsmId::Way(WayId(10)),
+                OsmId::Way(WayId(11)),
+                OsmId::Way(WayId(12)),
+            ]
+        );
+        let deleted: Vec<_> = changes.deleted.iter().cloned().collect();
+        assert_eq!(
+            deleted,
+            vec![
+                OsmId::Node(NodeId(4)),
+                OsmId::Node(NodeId(5)),
+                OsmId::Way(WayId(13)),
+                OsmId::Relation(RelationId(20)),
+            ]
+        );
+        let way = changes.objects[&OsmId::Way(WayId(10))].way().unwrap();
+        assert_eq!(way.nodes, vec![NodeId(1), NodeId(2)]);
+        assert_eq!(way.tags.get("name").unwrap(), "Rue Hector Malot");
+    }
+
+    #[test]
+    fn invalid_osm_change() {
+        let mut changes = OsmChange::default();
+        let res = changes
+            .read_from(r#"<osmChange><create><node id="1"/></create></osmChange>"#.as_bytes());
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn streets_from_osm_change() {
+        let changes = read_changes();
+        let geofinder = AdminGeoFinder::default();
+        assert_eq!(
+            changes.located_ids(),
+            vec![
+                "street:osm:way:11",
+                "poi:osm:way:11",
+                "street:osm:way:12",
+                "poi:osm:way:12",
+            ]
+        );
+
+        // without coordinate, the modified way is skipped
+        let streets = changes.streets(&geofinder, &KnownCoords::new());
+        assert_eq!(streets.len(), 1);
+        assert_eq!(streets[0].id, "street:osm:way:10");
+        assert_eq!(streets[0].label, "Rue Hector Malot");
+        assert!((streets[0].coord.lon() - 2.378).abs() < 1e-7);
+        assert_eq!(streets[0].geometry.as_ref().unwrap().0[0].0.len(), 2);
+
+        let known_coords = vec![(
+            "street:osm:way:11".to_string(),
+            mimir::Coord::new(2.39, 48.84),
+        )]
+        .into_iter()
+        .collect();
+        let streets = changes.streets(&geofinder, &known_coords);
+        let ids: Vec<_> = streets.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["street:osm:way:10", "street:osm:way:11"]);
+
+        // the way 12 is no longer a street
+        assert_eq!(
+            changes.deleted_street_ids(),
+            vec![
+                "street:osm:way:13",
+                "street:osm:relation:20",
+                "street:osm:way:12",
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_streets_with_indexed_streets() {
+        use std::sync::Arc;
+        let city = |id: &str| {
+            Arc::new(mimir::Admin {
+                id: id.to_string(),
+                zone_type: Some(cosmogony::ZoneType::City),
+                ..Default::default()
+            })
+        };
+        let line = |x: f64| LineString::from(vec![(x, 48.), (x + 0.001, 48.)]);
+        let street = |id: &str, name: &str, city_id: &str, lines: Vec<LineString<f64>>| {
+            make_street(
+                id.to_string(),
+                name,
+                mimir::Coord::new(2., 48.),
+                Some(MultiLineString(lines)),
+                vec![city(city_id)],
+            )
+        };
+        let mut indexed = vec![
+            // a street merged from the ways 10 and 12 by the full import
+            street(
+                "street:osm:way:10",
+                "Rue A",
+                "admin:1",
+                vec![line(1.), line(2.)],
+            ),
+            street("street:osm:way:20", "Rue B", "admin:1", vec![line(3.)]),
+        ];
+        // like the streets read from their index
+        for street in &mut indexed {
+            street.approx_coord = None;
+        }
+        let changes = vec![
+            // a new way of the merged street
+            street("street:osm:way:15", "Rue A", "admin:1", vec![line(4.)]),
+            // a way with the same name in another city
+            street("street:osm:way:16", "Rue A", "admin:2", vec![line(5.)]),
+            // 2 new ways of the same new street
+            street("street:osm:way:31", "Rue C", "admin:1", vec![line(6.)]),
+            street("street:osm:way:30", "Rue C", "admin:1", vec![line(7.)]),
+            // the way 20 is renamed and merged into the street 10
+            street("street:osm:way:20", "Rue A", "admin:1", vec![line(3.)]),
+        ];
+        // the way carrying the id of the merged street is deleted
+        let deleted = vec!["street:osm:way:10".to_string()];
+        let (streets, deleted) = merge_streets(changes, deleted, &indexed);
+        let ids: Vec<_> = streets.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "street:osm:way:10",
+                "street:osm:way:16",
+                "street:osm:way:30",
+            ]
+        );
+        assert_eq!(
+            streets[0].geometry.as_ref().unwrap().0,
+            vec![line(1.), line(2.), line(4.), line(3.)]
+        );
+        assert!(streets[0].approx_coord.is_some());
+        assert_eq!(streets[2].geometry.as_ref().unwrap().0.len(), 2);
+        // the merged street is kept, the renamed street is removed
+        assert_eq!(deleted, vec!["street:osm:way:20"]);
+    }
+
+    #[test]
+    fn pois_from_osm_change() {
+        let changes = read_changes();
+        let matcher = PoiConfig::default();
+        let pois = changes.pois(&matcher, &AdminGeoFinder::default(), &KnownCoords::new());
+        assert_eq!(pois.len(), 1);
+        assert_eq!(pois[0].id, "poi:osm:node:3");
+        assert_eq!(pois[0].name, "Mairie du 12e");
+        assert_eq!(pois[0].poi_type.id, "poi_type:amenity:townhall");
+
+        let deleted = changes.deleted_poi_ids(&matcher);
+        assert!(deleted.contains(&"poi:osm:node:4".to_string()));
+        assert!(deleted.contains(&"poi:osm:node:5".to_string()));
+        assert!(deleted.contains(&"poi:osm:way:12".to_string()));
+        assert!(!deleted.contains(&"poi:osm:node:3".to_string()));
+    }
+
+    #[test]
+    fn admins_from_osm_change() {
+        let changes = read_changes();
+        let matcher = AdminMatcher::new(vec![8].into_iter().collect());
+        assert!(changes.admins(&matcher, 8).is_empty());
+        assert_eq!(
+            changes.deleted_admin_ids(&matcher),
+            vec!["admin:osm:relation:20"]
+        );
+    }
+}
@@ -33,7 +33,7 @@ use osmpbfreader;
 
 use super::street::Getter;
 use geo::centroid::Centroid;
-use geo::MultiPolygon;
+use geo::{Coordinate, LineString, MultiPolygon};
 use osmpbfreader::StoreObjs;
 
 pub fn get_way_coord<T: StoreObjs + Getter>(
@@ -58,6 +58,30 @@ pub fn get_way_coord<T: StoreObjs + Getter>(
         .unwrap_or_else(mimir::Coord::default)
 }
 
+/// the line of the way, with the coordinates of the nodes found in `obj_map`
+/// (None if less than 2 nodes are found)
+pub fn get_way_line<T: StoreObjs + Getter>(
+    obj_map: &T,
+    way: &osmpbfreader::objects::Way,
+) -> Option<LineString<f64>> {
+    let coords: Vec<_> = way
+        .nodes
+        .iter()
+        .filter_map(|node_id| obj_map.get(&(*node_id).into()))
+        .filter_map(|obj| {
+            obj.node().map(|node| Coordinate {
+                x: node.lon(),
+                y: node.lat(),
+            })
+        })
+        .collect();
+    if coords.len() < 2 {
+        None
+    } else {
+        Some(LineString(coords))
+    }
+}
+
 pub fn make_centroid(boundary: &Option<MultiPolygon<f64>>) -> mimir::Coord {
     let coord = boundary
         .as_ref()
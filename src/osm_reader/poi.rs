@@ -33,7 +33,8 @@ use super::osm_utils::make_centroid;
 use super::OsmPbfReader;
 use crate::admin_geofinder::AdminGeoFinder;
 use crate::{labels, utils};
-use mimir::{rubber, Poi, PoiType};
+use mimir::backend::Backend;
+use mimir::{Poi, PoiType};
 use osm_boundaries_utils::build_boundary;
 use serde::{Deserialize, Serialize};
 use serde_json;
@@ -207,6 +208,23 @@ fn parse_poi(
     obj_map: &BTreeMap<osmpbfreader::OsmId, osmpbfreader::OsmObj>,
     matcher: &PoiConfig,
     admins_geofinder: &AdminGeoFinder,
+) -> Option<mimir::Poi> {
+    let coord = match *osmobj {
+        osmpbfreader::OsmObj::Node(ref node) => mimir::Coord::new(node.lon(), node.lat()),
+        osmpbfreader::OsmObj::Way(ref way) => get_way_coord(obj_map, way),
+        osmpbfreader::OsmObj::Relation(ref relation) => {
+            make_centroid(&build_boundary(relation, obj_map))
+        }
+    };
+    make_poi(osmobj, coord, matcher, admins_geofinder)
+}
+
+/// build the poi of an osm object located at `coord`
+pub fn make_poi(
+    osmobj: &osmpbfreader::OsmObj,
+    coord: mimir::Coord,
+    matcher: &PoiConfig,
+    admins_geofinder: &AdminGeoFinder,
 ) -> Option<mimir::Poi> {
     let poi_type = match matcher.get_poi_type(osmobj.tags()) {
         Some(poi_type) => poi_type,
@@ -218,19 +236,7 @@ fn parse_poi(
             return None;
         }
     };
-    let (id, coord) = match *osmobj {
-        osmpbfreader::OsmObj::Node(ref node) => (
-            format_poi_id("node", node.id.0),
-            mimir::Coord::new(node.lon(), node.lat()),
-        ),
-        osmpbfreader::OsmObj::Way(ref way) => {
-            (format_poi_id("way", way.id.0), get_way_coord(obj_map, way))
-        }
-        osmpbfreader::OsmObj::Relation(ref relation) => (
-            format_poi_id("relation", relation.id.0),
-            make_centroid(&build_boundary(relation, obj_map)),
-        ),
-    };
+    let id = poi_id(osmobj.id());
 
     let name = osmobj.tags().get("name").unwrap_or(&poi_type.name);
 
@@ -272,6 +278,15 @@ fn format_poi_id(osm_type: &str, id: i64) -> String {
     format!("poi:osm:{}:{}", osm_type, id)
 }
 
+/// the id of the poi built from an osm object
+pub fn poi_id(osm_id: osmpbfreader::OsmId) -> String {
+    match osm_id {
+        osmpbfreader::OsmId::Node(id) => format_poi_id("node", id.0),
+        osmpbfreader::OsmId::Way(id) => format_poi_id("way", id.0),
+        osmpbfreader::OsmId::Relation(id) => format_poi_id("relation", id.0),
+    }
+}
+
 pub fn pois(
     pbf: &mut OsmPbfReader,
     matcher: &PoiConfig,
@@ -296,9 +311,9 @@ pub fn compute_poi_weight(pois_vec: &mut [Poi]) {
     }
 }
 
-pub fn add_address(pois_vec: &mut [Poi], rubber: &mut rubber::Rubber) {
+pub fn add_address<B: Backend>(pois_vec: &mut [Poi], backend: &mut B) {
     for poi in pois_vec {
-        poi.address = rubber
+        poi.address = backend
             .get_address(&poi.coord)
             .ok()
             .and_then(|addrs| addrs.into_iter().next())
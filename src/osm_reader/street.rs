@@ -27,12 +27,13 @@
 // IRC #navitia on freenode
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
-use super::osm_utils::get_way_coord;
+use super::osm_utils::{get_way_coord, get_way_line};
 use super::OsmPbfReader;
 use crate::admin_geofinder::AdminGeoFinder;
 use crate::{labels, utils, Error};
 use bincode;
 use failure::ResultExt;
+use geo::MultiLineString;
 use osmpbfreader::{OsmId, OsmObj, StoreObjs};
 use rusqlite::{Connection, DropBehavior, ToSql, NO_PARAMS};
 use slog_scope::{error, info};
@@ -366,30 +367,9 @@ pub fn streets(
     db_file: &Option<PathBuf>,
     db_buffer_size: usize,
 ) -> Result<StreetsVec, Error> {
-    // This is the list of highway that we don't want to index
-    // See [OSM Key Highway](https://wiki.openstreetmap.org/wiki/Key:highway) for background.
-    const INVALID_HIGHWAY: &'static [&'static str] =
-        &["bus_guideway", "escape", "bus_stop", "elevator", "platform"];
-
-    // For the object to be a valid street, it needs to be an osm highway of a valid type,
-    // or a relation of type associatedStreet.
-    fn is_valid_obj(obj: &osmpbfreader::OsmObj) -> bool {
-        match *obj {
-            osmpbfreader::OsmObj::Way(ref way) => {
-                way.tags.get("highway").map_or(false, |v| {
-                    !v.is_empty() && !INVALID_HIGHWAY.iter().any(|&k| k == v)
-                }) && way.tags.get("name").map_or(false, |v| !v.is_empty())
-            }
-            osmpbfreader::OsmObj::Relation(ref rel) => rel
-                .tags
-                .get("type")
-                .map_or(false, |v| v == "associatedStreet"),
-            _ => false,
-        }
-    }
     info!("reading pbf...");
     let mut objs_map = ObjWrapper::new(db_file, db_buffer_size)?;
-    pbf.get_objs_and_deps_store(is_valid_obj, &mut objs_map)
+    pbf.get_objs_and_deps_store(is_valid_street, &mut objs_map)
         .context("Error occurred when reading pbf")?;
     info!("reading pbf done.");
     let mut street_rel: StreetWithRelationSet = BTreeSet::new();
@@ -411,26 +391,20 @@ pub fn streets(
                 let way = obj.way()?;
                 let way_name = way_name.or_else(|| way.tags.get("name"))?;
                 let admins = get_street_admin(admins_geofinder, &objs_map, way);
-                let country_codes = utils::find_country_codes(admins.iter().map(|a| a.deref()));
-                let street_label = labels::format_street_label(
-                    &way_name,
-                    admins.iter().map(|a| a.deref()),
-                    &country_codes,
+                let geometry = get_street_geometry(
+                    &objs_map,
+                    rel.refs
+                        .iter()
+                        .filter(|r| r.member.is_way() && r.role == "street")
+                        .map(|r| &r.member),
                 );
-                let coord = get_way_coord(&objs_map, way);
-                Some(mimir::Street {
-                    id: format!("street:osm:relation:{}", rel.id.0.to_string()),
-                    name: way_name.to_string(),
-                    label: street_label,
-                    weight: 0.,
-                    zip_codes: utils::get_zip_codes_from_admins(&admins),
-                    administrative_regions: admins,
-                    coord: get_way_coord(&objs_map, way),
-                    approx_coord: Some(coord.into()),
-                    distance: None,
-                    country_codes,
-                    context: None,
-                })
+                Some(make_street(
+                    format!("street:osm:relation:{}", rel.id.0.to_string()),
+                    way_name,
+                    get_way_coord(&objs_map, way),
+                    geometry,
+                    admins,
+                ))
             })
             .next()
             .map(|street| street_list.push(street));
@@ -472,32 +446,87 @@ pub fn streets(
         let min_id = way_ids.iter().min()?;
         let obj = objs_map.get(&min_id)?;
         let way = obj.way()?;
-        let name = way.tags.get("name")?.to_string();
+        let name = way.tags.get("name")?;
         let admins = get_street_admin(admins_geofinder, &objs_map, way);
-
-        let country_codes = utils::find_country_codes(admins.iter().map(|a| a.deref()));
-        let street_label =
-            labels::format_street_label(&name, admins.iter().map(|a| a.deref()), &country_codes);
-        let coord = get_way_coord(&objs_map, way);
-        Some(mimir::Street {
-            id: format!("street:osm:way:{}", way.id.0.to_string()),
-            label: street_label,
+        Some(make_street(
+            format!("street:osm:way:{}", way.id.0.to_string()),
             name,
-            weight: 0.,
-            zip_codes: utils::get_zip_codes_from_admins(&admins),
-            administrative_regions: admins,
-            coord: get_way_coord(&objs_map, way),
-            approx_coord: Some(coord.into()),
-            distance: None,
-            country_codes,
-            context: None,
-        })
+            get_way_coord(&objs_map, way),
+            get_street_geometry(&objs_map, way_ids.iter()),
+            admins,
+        ))
     });
     street_list.extend(streets);
 
     Ok(street_list)
 }
 
+/// build a street from its osm name, its coordinate, its geometry and its admins
+pub fn make_street(
+    id: String,
+    name: &str,
+    coord: mimir::Coord,
+    geometry: Option<MultiLineString<f64>>,
+    admins: Vec<Arc<mimir::Admin>>,
+) -> mimir::Street {
+    let country_codes = utils::find_country_codes(admins.iter().map(|a| a.deref()));
+    let label = labels::format_street_label(name, admins.iter().map(|a| a.deref()), &country_codes);
+    mimir::Street {
+        id,
+        label,
+        name: name.to_string(),
+        weight: 0.,
+        zip_codes: utils::get_zip_codes_from_admins(&admins),
+        administrative_regions: admins,
+        coord,
+        approx_coord: Some(coord.into()),
+        geometry,
+        distance: None,
+        country_codes,
+        context: None,
+    }
+}
+
+/// For the object to be a valid street, it needs to be an osm highway of a valid type,
+/// or a relation of type associatedStreet.
+pub fn is_valid_street(obj: &osmpbfreader::OsmObj) -> bool {
+    // This is the list of highway that we don't want to index
+    // See [OSM Key Highway](https://wiki.openstreetmap.org/wiki/Key:highway) for background.
+    const INVALID_HIGHWAY: &'static [&'static str] =
+        &["bus_guideway", "escape", "bus_stop", "elevator", "platform"];
+
+    match *obj {
+        osmpbfreader::OsmObj::Way(ref way) => {
+            way.tags.get("highway").map_or(false, |v| {
+                !v.is_empty() && !INVALID_HIGHWAY.iter().any(|&k| k == v)
+            }) && way.tags.get("name").map_or(false, |v| !v.is_empty())
+        }
+        osmpbfreader::OsmObj::Relation(ref rel) => rel
+            .tags
+            .get("type")
+            .map_or(false, |v| v == "associatedStreet"),
+        _ => false,
+    }
+}
+
+/// the geometry of a street made of the ways `way_ids`
+fn get_street_geometry<'a, T: StoreObjs + Getter>(
+    obj_map: &T,
+    way_ids: impl Iterator<Item = &'a OsmId>,
+) -> Option<MultiLineString<f64>> {
+    let lines: Vec<_> = way_ids
+        .filter_map(|id| {
+            let obj = obj_map.get(id)?;
+            get_way_line(obj_map, obj.way()?)
+        })
+        .collect();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(MultiLineString(lines))
+    }
+}
+
 fn get_street_admin<T: StoreObjs + Getter>(
     admins_geofinder: &AdminGeoFinder,
     obj_map: &T,
@@ -29,18 +29,22 @@
 // www.navitia.io
 
 use crate::admin_geofinder::AdminGeoFinder;
+use crate::utils::{ExportArgs, MergeArgs, PublishArgs};
 use crate::{labels, utils};
 use failure::format_err;
 use failure::{Error, ResultExt};
 use mimir;
-use mimir::rubber::{IndexSettings, Rubber, TypedIndex};
+use mimir::backend::Backend;
+use mimir::rubber::{IndexSettings, TypedIndex};
 use slog_scope::{info, warn};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::mem::replace;
 use std::ops::Deref;
 use std::sync::Arc;
+use transit_model::objects as navitia;
+use transit_model_collection::Idx;
 
-const GLOBAL_STOP_INDEX_NAME: &'static str = "munin_global_stops";
+pub const GLOBAL_STOP_INDEX_NAME: &'static str = "munin_global_stops";
 
 pub fn initialize_weights<'a, It>(stops: It, nb_stop_points: &HashMap<String, u32>)
 where
@@ -56,17 +60,300 @@ where
     }
 }
 
+fn get_lines(
+    lines: BTreeSet<Idx<navitia::Line>>,
+    navitia: &transit_model::Model,
+) -> Vec<mimir::Line> {
+    use humanesort::HumaneSortable;
+    use mimir::FromTransitModel;
+    let mut lines: Vec<_> = lines
+        .into_iter()
+        .map(|l_idx| mimir::Line::from_transit_model(l_idx, navitia))
+        .collect();
+
+    // we want the lines to be sorted in a way where
+    // line-3 is before line-11, so be use a humane_sort
+    lines.humane_sort();
+    lines
+}
+
+fn get_commercial_modes(
+    commercial_modes: BTreeSet<Idx<navitia::CommercialMode>>,
+    navitia: &transit_model::Model,
+) -> Vec<mimir::CommercialMode> {
+    commercial_modes
+        .into_iter()
+        .map(|cm_idx| mimir::CommercialMode {
+            id: mimir::objects::normalize_id(
+                "commercial_mode",
+                &navitia.commercial_modes[cm_idx].id,
+            ),
+            name: navitia.commercial_modes[cm_idx].name.clone(),
+        })
+        .collect()
+}
+
+fn get_physical_modes(
+    physical_modes: BTreeSet<Idx<navitia::PhysicalMode>>,
+    navitia: &transit_model::Model,
+) -> Vec<mimir::PhysicalMode> {
+    physical_modes
+        .into_iter()
+        .map(|pm_idx| mimir::PhysicalMode {
+            id: mimir::objects::normalize_id("physical_mode", &navitia.physical_modes[pm_idx].id),
+            name: navitia.physical_modes[pm_idx].name.clone(),
+        })
+        .collect()
+}
+
+fn get_feed_publishers(
+    contributors: BTreeSet<Idx<navitia::Contributor>>,
+    navitia: &transit_model::Model,
+) -> Vec<mimir::FeedPublisher> {
+    contributors
+        .into_iter()
+        .map(|contrib_idx| mimir::FeedPublisher {
+            id: navitia.contributors[contrib_idx].id.clone(),
+            name: navitia.contributors[contrib_idx].name.clone(),
+            license: navitia.contributors[contrib_idx]
+                .license
+                .clone()
+                .unwrap_or_else(|| "".into()),
+            url: navitia.contributors[contrib_idx]
+                .website
+                .clone()
+                .unwrap_or_else(|| "".into()),
+        })
+        .collect()
+}
+
+fn get_comments(
+    comment_links: &navitia::CommentLinksT,
+    navitia: &transit_model::Model,
+) -> Vec<mimir::Comment> {
+    navitia
+        .comments
+        .iter_from(comment_links)
+        .map(|comment| mimir::Comment {
+            name: comment.name.clone(),
+        })
+        .collect()
+}
+
+fn get_codes(codes: &navitia::KeysValues) -> Vec<mimir::Code> {
+    codes
+        .iter()
+        .map(|&(ref t, ref v)| mimir::Code {
+            name: t.clone(),
+            value: v.clone(),
+        })
+        .collect()
+}
+
+fn get_properties(properties: &navitia::KeysValues) -> Vec<mimir::Property> {
+    properties
+        .iter()
+        .map(|&(ref k, ref v)| mimir::Property {
+            key: k.clone(),
+            value: v.clone(),
+        })
+        .collect()
+}
+
+fn to_mimir(
+    idx: Idx<navitia::StopArea>,
+    stop_area: &navitia::StopArea,
+    navitia: &transit_model::Model,
+) -> mimir::Stop {
+    let coord = mimir::Coord::new(stop_area.coord.lon, stop_area.coord.lat);
+
+    mimir::Stop {
+        id: mimir::objects::normalize_id("stop_area", &stop_area.id),
+        label: stop_area.name.clone(),
+        name: stop_area.name.clone(),
+        coord: coord.clone(),
+        approx_coord: Some(coord.into()),
+        commercial_modes: get_commercial_modes(navitia.get_corresponding_from_idx(idx), navitia),
+        physical_modes: get_physical_modes(navitia.get_corresponding_from_idx(idx), navitia),
+        lines: get_lines(navitia.get_corresponding_from_idx(idx), navitia),
+        comments: get_comments(&stop_area.comment_links, navitia),
+        timezone: stop_area.timezone.clone().unwrap_or(format!("")),
+        codes: get_codes(&stop_area.codes),
+        properties: get_properties(&stop_area.object_properties),
+        feed_publishers: get_feed_publishers(navitia.get_corresponding_from_idx(idx), navitia),
+        ..Default::default()
+    }
+}
+
+fn stop_point_to_mimir(
+    idx: Idx<navitia::StopPoint>,
+    stop_point: &navitia::StopPoint,
+    navitia: &transit_model::Model,
+) -> mimir::StopPoint {
+    let coord = mimir::Coord::new(stop_point.coord.lon, stop_point.coord.lat);
+    let stop_area = navitia.stop_areas.get(&stop_point.stop_area_id);
+
+    mimir::StopPoint {
+        id: mimir::objects::normalize_id("stop_point", &stop_point.id),
+        label: stop_point.name.clone(),
+        name: stop_point.name.clone(),
+        coord: coord.clone(),
+        approx_coord: Some(coord.into()),
+        stop_area: mimir::ParentStopArea {
+            id: mimir::objects::normalize_id("stop_area", &stop_point.stop_area_id),
+            name: stop_area.map(|sa| sa.name.clone()).unwrap_or_default(),
+        },
+        commercial_modes: get_commercial_modes(navitia.get_corresponding_from_idx(idx), navitia),
+        physical_modes: get_physical_modes(navitia.get_corresponding_from_idx(idx), navitia),
+        lines: get_lines(navitia.get_corresponding_from_idx(idx), navitia),
+        comments: get_comments(&stop_point.comment_links, navitia),
+        // the timezone of a stop point is inherited from its stop area if not given
+        timezone: stop_point
+            .timezone
+            .clone()
+            .or_else(|| stop_area.and_then(|sa| sa.timezone.clone()))
+            .unwrap_or_default(),
+        codes: get_codes(&stop_point.codes),
+        properties: get_properties(&stop_point.object_properties),
+        feed_publishers: get_feed_publishers(navitia.get_corresponding_from_idx(idx), navitia),
+        ..Default::default()
+    }
+}
+
+/// the stops of all the stop areas of a transit model (read from a NTFS or a GTFS),
+/// weighted by their number of stop points
+pub fn stops_from_transit_model(navitia: &transit_model::Model) -> Vec<mimir::Stop> {
+    let nb_stop_points = navitia
+        .stop_areas
+        .iter()
+        .map(|(idx, sa)| {
+            let id = mimir::objects::normalize_id("stop_area", &sa.id);
+            let nb_stop_points = navitia
+                .get_corresponding_from_idx::<_, navitia::StopPoint>(idx)
+                .len();
+            (id, nb_stop_points as u32)
+        })
+        .collect();
+    let mut stops: Vec<mimir::Stop> = navitia
+        .stop_areas
+        .iter()
+        .map(|(idx, sa)| to_mimir(idx, sa, navitia))
+        .collect();
+    initialize_weights(stops.iter_mut(), &nb_stop_points);
+    stops
+}
+
+/// the stop points of a transit model, attached to their stop area
+/// (their weight is the one of their stop area, given on import)
+pub fn stop_points_from_transit_model(navitia: &transit_model::Model) -> Vec<mimir::StopPoint> {
+    navitia
+        .stop_points
+        .iter()
+        .map(|(idx, sp)| stop_point_to_mimir(idx, sp, navitia))
+        .collect()
+}
+
+/// the lines of a transit model, weighted by their number of stop areas
+pub fn lines_from_transit_model(navitia: &transit_model::Model) -> Vec<mimir::PtLine> {
+    use mimir::FromTransitModel;
+    let nb_stop_areas: Vec<_> = navitia
+        .lines
+        .iter()
+        .map(|(idx, _)| {
+            navitia
+                .get_corresponding_from_idx::<_, navitia::StopArea>(idx)
+                .len()
+        })
+        .collect();
+    // the weights are 0 rather than NaN if no line has a stop area
+    let max = nb_stop_areas.iter().cloned().max().unwrap_or(1).max(1) as f64;
+    navitia
+        .lines
+        .iter()
+        .zip(nb_stop_areas)
+        .map(|((idx, _), nb)| mimir::PtLine {
+            weight: nb as f64 / max,
+            ..mimir::PtLine::from_transit_model(idx, navitia)
+        })
+        .collect()
+}
+
+/// the networks of a transit model, weighted by their number of lines
+pub fn networks_from_transit_model(navitia: &transit_model::Model) -> Vec<mimir::PtNetwork> {
+    use mimir::FromTransitModel;
+    let nb_lines: Vec<_> = navitia
+        .networks
+        .iter()
+        .map(|(idx, _)| {
+            navitia
+                .get_corresponding_from_idx::<_, navitia::Line>(idx)
+                .len()
+        })
+        .collect();
+    let max = nb_lines.iter().cloned().max().unwrap_or(1).max(1) as f64;
+    navitia
+        .networks
+        .iter()
+        .zip(nb_lines)
+        .map(|((idx, _), nb)| mimir::PtNetwork {
+            weight: nb as f64 / max,
+            ..mimir::PtNetwork::from_transit_model(idx, navitia)
+        })
+        .collect()
+}
+
+/// The public transport objects of a dataset to import
+///
+/// Only the stops are always indexed, the other objects are indexed if some are given
+#[derive(Default)]
+pub struct PtObjects {
+    pub stops: Vec<mimir::Stop>,
+    pub stop_points: Vec<mimir::StopPoint>,
+    pub lines: Vec<mimir::PtLine>,
+    pub networks: Vec<mimir::PtNetwork>,
+}
+
+/// import the stops in elasticsearch, or in the output file of `export` if any
 pub fn import_stops(
-    mut stops: Vec<mimir::Stop>,
+    objects: PtObjects,
     connection_string: &str,
+    export: &ExportArgs,
+    publish: &PublishArgs,
+    merge: &MergeArgs,
     dataset: &str,
     index_settings: IndexSettings,
 ) -> Result<(), Error> {
-    info!("creation of indexes");
-    let mut rubber = Rubber::new(connection_string);
-    rubber.initialize_templates()?;
+    match export.backend(connection_string)? {
+        // the global stop index is not exported, dump2mimir rebuilds it from the stops
+        Some(mut backend) => {
+            index_stops(objects, &mut backend, None, dataset, index_settings)?;
+            backend.finish()
+        }
+        None => {
+            info!("creation of indexes");
+            let mut rubber = publish.rubber(connection_string)?;
+            rubber.initialize_templates()?;
+            index_stops(objects, &mut rubber, Some(merge), dataset, index_settings)
+        }
+    }
+}
 
-    attach_stops_to_admins(stops.iter_mut(), &mut rubber);
+/// index the objects of the dataset,
+/// and update the global stop index with `merge` if any
+fn index_stops<B: Backend>(
+    objects: PtObjects,
+    backend: &mut B,
+    merge: Option<&MergeArgs>,
+    dataset: &str,
+    index_settings: IndexSettings,
+) -> Result<(), Error> {
+    let PtObjects {
+        mut stops,
+        mut stop_points,
+        mut lines,
+        mut networks,
+    } = objects;
+    attach_stops_to_admins(stops.iter_mut(), backend);
 
     for stop in &mut stops {
         stop.coverages.push(dataset.to_string());
@@ -86,18 +373,92 @@ pub fn import_stops(
         stop.weight = (stop.weight + admin_weight) / 2.0;
     }
 
-    let global_index =
-        update_global_stop_index(&mut rubber, stops.iter(), dataset, &index_settings)?;
+    attach_stop_points_to_stops(stop_points.iter_mut(), &stops, dataset);
+
+    let global_index = match merge {
+        Some(merge) => Some(update_global_stop_index(
+            backend,
+            stops.iter(),
+            merge,
+            dataset,
+            &index_settings,
+        )?),
+        None => None,
+    };
 
     info!("Importing {} stops into Mimir", stops.len());
-    let nb_stops = rubber.public_index(dataset, &index_settings, stops.into_iter())?;
+    let nb_stops = backend.public_index(dataset, &index_settings, stops.into_iter())?;
     info!("Nb of indexed stops: {}", nb_stops);
 
-    publish_global_index(&mut rubber, &global_index)
-        .context("Error while publishing global index")?;
+    if let Some(global_index) = global_index {
+        publish_global_index(backend, &global_index)
+            .context("Error while publishing global index")?;
+    }
+
+    if !stop_points.is_empty() {
+        info!("Importing {} stop points into Mimir", stop_points.len());
+        let nb_stop_points =
+            backend.public_index(dataset, &index_settings, stop_points.into_iter())?;
+        info!("Nb of indexed stop points: {}", nb_stop_points);
+    } else {
+        // the stop points of a previous import of the dataset must not be found anymore
+        backend.unpublish_index::<mimir::StopPoint>(dataset)?;
+    }
+
+    if !lines.is_empty() {
+        for line in &mut lines {
+            line.coverages.push(dataset.to_string());
+        }
+        info!("Importing {} lines into Mimir", lines.len());
+        let nb_lines = backend.public_index(dataset, &index_settings, lines.into_iter())?;
+        info!("Nb of indexed lines: {}", nb_lines);
+    } else {
+        backend.unpublish_index::<mimir::PtLine>(dataset)?;
+    }
+
+    if !networks.is_empty() {
+        for network in &mut networks {
+            network.coverages.push(dataset.to_string());
+        }
+        info!("Importing {} networks into Mimir", networks.len());
+        let nb_networks = backend.public_index(dataset, &index_settings, networks.into_iter())?;
+        info!("Nb of indexed networks: {}", nb_networks);
+    } else {
+        backend.unpublish_index::<mimir::PtNetwork>(dataset)?;
+    }
     Ok(())
 }
 
+/// The stop points get the admins and the weight of their stop area
+/// (a stop point is close enough to its stop area to be in the same admins)
+fn attach_stop_points_to_stops<'a, It: Iterator<Item = &'a mut mimir::StopPoint>>(
+    stop_points: It,
+    stops: &[mimir::Stop],
+    dataset: &str,
+) {
+    let stops_by_id: HashMap<_, _> = stops.iter().map(|s| (s.id.as_str(), s)).collect();
+    for stop_point in stop_points {
+        stop_point.coverages.push(dataset.to_string());
+        let stop = match stops_by_id.get(stop_point.stop_area.id.as_str()) {
+            Some(stop) => stop,
+            None => {
+                warn!(
+                    "the stop area {} of the stop point {} is unknown",
+                    stop_point.stop_area.id, stop_point.id
+                );
+                continue;
+            }
+        };
+        let admins_iter = stop.administrative_regions.iter().map(|a| a.deref());
+        stop_point.label =
+            labels::format_stop_label(&stop_point.name, admins_iter, &stop.country_codes);
+        stop_point.zip_codes = stop.zip_codes.clone();
+        stop_point.country_codes = stop.country_codes.clone();
+        stop_point.administrative_regions = stop.administrative_regions.clone();
+        stop_point.weight = stop.weight;
+    }
+}
+
 fn attach_stop(stop: &mut mimir::Stop, admins: Vec<Arc<mimir::Admin>>) {
     let admins_iter = admins.iter().map(|a| a.deref());
     let country_codes = utils::find_country_codes(admins_iter.clone());
@@ -114,11 +475,11 @@ fn attach_stop(stop: &mut mimir::Stop, admins: Vec<Arc<mimir::Admin>>) {
 /// The admins are loaded from Elasticsearch and stored in a quadtree
 /// We attach a stop with all the admins that have a boundary containing
 /// the coordinate of the stop
-fn attach_stops_to_admins<'a, It: Iterator<Item = &'a mut mimir::Stop>>(
+fn attach_stops_to_admins<'a, It: Iterator<Item = &'a mut mimir::Stop>, B: Backend>(
     stops: It,
-    rubber: &mut Rubber,
+    backend: &mut B,
 ) {
-    let admins = rubber.get_all_admins().unwrap_or_else(|_| {
+    let admins = backend.get_all_admins().unwrap_or_else(|_| {
         warn!("Administratives regions not found in elasticsearch db");
         vec![]
     });
@@ -158,66 +519,253 @@ fn merge_collection<T: Ord>(target: &mut Vec<T>, source: Vec<T>) {
         .collect();
 }
 
+/// merge `stop` into `target`, the data of `target` is kept
+/// and only the collections of `stop` are added to it
+fn merge_stop(target: &mut mimir::Stop, stop: mimir::Stop) {
+    use humanesort::HumaneSortable;
+    merge_collection(&mut target.codes, stop.codes);
+    merge_collection(&mut target.physical_modes, stop.physical_modes);
+    merge_collection(&mut target.commercial_modes, stop.commercial_modes);
+    merge_collection(&mut target.coverages, stop.coverages);
+    merge_collection(&mut target.properties, stop.properties);
+    merge_collection(&mut target.feed_publishers, stop.feed_publishers);
+    merge_collection(&mut target.lines, stop.lines);
+    target.lines.humane_sort();
+}
+
+/// lowercase the name, remove its accents and its punctuation
+fn normalize_name(name: &str) -> String {
+    let folded: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+            'ç' => 'c',
+            'è' | 'é' | 'ê' | 'ë' => 'e',
+            'ì' | 'í' | 'î' | 'ï' => 'i',
+            'ñ' => 'n',
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' => 'o',
+            'ù' | 'ú' | 'û' | 'ü' => 'u',
+            'ý' | 'ÿ' => 'y',
+            c if c.is_alphanumeric() => c,
+            _ => ' ',
+        })
+        .collect();
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// similarity between 0 and 1 of two normalized names,
+/// based on their levenshtein distance
+fn name_similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let max_len = a.len().max(b.len());
+    if max_len == 0 {
+        return 1.0;
+    }
+    let mut distances: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut previous_diagonal = distances[0];
+        distances[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous_diagonal + if ca == cb { 0 } else { 1 };
+            previous_diagonal = distances[j + 1];
+            distances[j + 1] = substitution.min(distances[j] + 1).min(distances[j + 1] + 1);
+        }
+    }
+    1.0 - distances[b.len()] as f64 / max_len as f64
+}
+
+/// the id of the stop in each of its datasets
+fn source_codes(stop: &mimir::Stop) -> Vec<mimir::Code> {
+    stop.coverages
+        .iter()
+        .map(|coverage| mimir::Code {
+            name: coverage.clone(),
+            value: stop.id.clone(),
+        })
+        .collect()
+}
+
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// Merge of the stops by distance and name
+///
+/// The stops are put in a grid of cells of `distance` meters,
+/// so only the stops of the neighbouring cells are compared.
+struct ProximityMerger<'a> {
+    merge: &'a MergeArgs,
+    distance: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    /// the stops with their normalized name and their own source codes,
+    /// added to their codes when another stop is first merged into them
+    stops: Vec<(mimir::Stop, String, Vec<mimir::Code>)>,
+}
+
+impl<'a> ProximityMerger<'a> {
+    fn new(merge: &'a MergeArgs, distance: f64) -> Self {
+        ProximityMerger {
+            merge,
+            distance,
+            cells: HashMap::new(),
+            stops: vec![],
+        }
+    }
+
+    fn cell_size(&self) -> f64 {
+        (self.distance / METERS_PER_DEGREE).max(1e-6)
+    }
+
+    fn cell(&self, coord: &mimir::Coord) -> (i64, i64) {
+        let size = self.cell_size();
+        (
+            (coord.lat() / size).floor() as i64,
+            (coord.lon() / size).floor() as i64,
+        )
+    }
+
+    /// the index of an already added stop that `stop` should be merged into
+    fn find_similar(&self, stop: &mimir::Stop, name: &str) -> Option<usize> {
+        use geo::algorithm::haversine_distance::HaversineDistance;
+        let (lat_cell, lon_cell) = self.cell(&stop.coord);
+        // a degree of longitude is shorter far from the equator
+        let cos_lat = stop.coord.lat().to_radians().cos().abs().max(0.01);
+        let lon_span = (1.0 / cos_lat).ceil() as i64;
+        let point: geo_types::Point<f64> = stop.coord.0.into();
+        let mut candidates: Vec<usize> = (lat_cell - 1..=lat_cell + 1)
+            .flat_map(|lat| (lon_cell - lon_span..=lon_cell + lon_span).map(move |lon| (lat, lon)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flat_map(|idxs| idxs.iter().cloned())
+            .collect();
+        // the first added stops are preferred
+        candidates.sort();
+        candidates.into_iter().find(|&idx| {
+            let (ref other, ref other_name, _) = self.stops[idx];
+            let other_point: geo_types::Point<f64> = other.coord.0.into();
+            // stops of the same dataset are never merged together
+            !other.coverages.iter().any(|c| stop.coverages.contains(c))
+                && point.haversine_distance(&other_point) <= self.distance
+                && name_similarity(name, other_name) >= self.merge.merge_name_similarity
+        })
+    }
+
+    fn add(&mut self, stop: mimir::Stop) {
+        let name = normalize_name(&stop.name);
+        match self.find_similar(&stop, &name) {
+            Some(idx) => {
+                let (ref mut target, _, ref mut target_codes) = self.stops[idx];
+                // the coverages of the target already include the ones of its merged stops
+                let codes = replace(target_codes, vec![])
+                    .into_iter()
+                    .chain(source_codes(&stop))
+                    .collect();
+                merge_collection(&mut target.codes, codes);
+                merge_stop(target, stop);
+            }
+            None => {
+                let cell = self.cell(&stop.coord);
+                self.cells
+                    .entry(cell)
+                    .or_insert_with(Vec::new)
+                    .push(self.stops.len());
+                let codes = source_codes(&stop);
+                self.stops.push((stop, name, codes));
+            }
+        }
+    }
+}
+
 /// merge the stops from all the different indexes
-/// for the moment the merge is very simple and uses only the ID
-/// (and we take the data from the first stop inserted)
+///
+/// The stops with the same ID are merged, and with a `merge_distance` the stops of
+/// different datasets within this distance and with similar names are merged too,
+/// the id of each of them being kept in the codes.
+/// The data of the first stop inserted is kept.
 fn merge_stops<It: IntoIterator<Item = mimir::Stop>>(
     stops: It,
+    merge: &MergeArgs,
 ) -> impl Iterator<Item = mimir::Stop> {
-    let mut stops_by_id = HashMap::<String, mimir::Stop>::new();
-    for mut stop in stops.into_iter() {
-        let cov = replace(&mut stop.coverages, vec![]);
-        let codes = replace(&mut stop.codes, vec![]);
-        let physical_modes = replace(&mut stop.physical_modes, vec![]);
-        let commercial_modes = replace(&mut stop.commercial_modes, vec![]);
-        let properties = replace(&mut stop.properties, vec![]);
-        let feed_publishers = replace(&mut stop.feed_publishers, vec![]);
+    let mut stops_by_id = HashMap::<String, usize>::new();
+    let mut merged_stops = Vec::<mimir::Stop>::new();
+    for stop in stops.into_iter() {
+        match stops_by_id.get(&stop.id) {
+            Some(&idx) => merge_stop(&mut merged_stops[idx], stop),
+            None => {
+                stops_by_id.insert(stop.id.clone(), merged_stops.len());
+                merged_stops.push(stop);
+            }
+        }
+    }
 
-        let stop_in_map = stops_by_id.entry(stop.id.clone()).or_insert(stop);
+    let merged_stops = match merge.merge_distance {
+        Some(distance) => {
+            let mut merger = ProximityMerger::new(merge, distance);
+            for stop in merged_stops {
+                merger.add(stop);
+            }
+            info!(
+                "{} stops after the merge by distance of the stops",
+                merger.stops.len()
+            );
+            merger.stops.into_iter().map(|(stop, _, _)| stop).collect()
+        }
+        None => merged_stops,
+    };
+    merged_stops.into_iter()
+}
 
-        merge_collection(&mut stop_in_map.codes, codes);
-        merge_collection(&mut stop_in_map.physical_modes, physical_modes);
-        merge_collection(&mut stop_in_map.commercial_modes, commercial_modes);
-        merge_collection(&mut stop_in_map.coverages, cov);
-        merge_collection(&mut stop_in_map.properties, properties);
-        merge_collection(&mut stop_in_map.feed_publishers, feed_publishers);
-    }
-    stops_by_id.into_iter().map(|(_, v)| v)
+/// rebuild the global stop index with the stops of the dataset (already published or not),
+/// merged with the published stops of the other datasets, then publish it
+pub fn update_global_stops<'a, It: Iterator<Item = &'a mimir::Stop>, B: Backend>(
+    backend: &mut B,
+    stops: It,
+    merge: &MergeArgs,
+    dataset: &str,
+    index_settings: &IndexSettings,
+) -> Result<(), Error> {
+    let global_index = update_global_stop_index(backend, stops, merge, dataset, index_settings)?;
+    publish_global_index(backend, &global_index).context("Error while publishing global index")?;
+    Ok(())
 }
 
-fn get_all_stops(rubber: &mut Rubber, index: String) -> Result<Vec<mimir::Stop>, Error> {
-    rubber
+fn get_all_stops<B: Backend>(backend: &mut B, index: String) -> Result<Vec<mimir::Stop>, Error> {
+    backend
         .get_all_objects_from_index(&index)
         .map_err(|e| format_err!("Getting all stops {}", e.to_string()))
 }
 
-fn update_global_stop_index<'a, It: Iterator<Item = &'a mimir::Stop>>(
-    rubber: &mut Rubber,
+fn update_global_stop_index<'a, It: Iterator<Item = &'a mimir::Stop>, B: Backend>(
+    backend: &mut B,
     stops: It,
+    merge: &MergeArgs,
     dataset: &str,
     index_settings: &IndexSettings,
 ) -> Result<String, Error> {
     let dataset_index = mimir::rubber::get_main_type_and_dataset_index::<mimir::Stop>(dataset);
-    let stops_indexes = rubber
-        .get_all_aliased_index(&mimir::rubber::get_main_type_index::<mimir::Stop>())?
+    let type_index = mimir::rubber::get_main_type_index::<mimir::Stop>();
+    // Note: only the published stop indexes are taken,
+    // not the previous generations or the stop point indexes (also prefixed by `munin_stop`)
+    let stops_indexes = backend
+        .get_all_aliased_index(&type_index)?
         .into_iter()
+        .filter(|&(_, ref aliases)| aliases.contains(&type_index))
         .filter(|&(_, ref aliases)| !aliases.contains(&dataset_index))
         .map(|(index, _)| index);
 
     let all_es_stops = stops_indexes
-        .map(|index| get_all_stops(rubber, index))
+        .map(|index| get_all_stops(backend, index))
         .collect::<Result<Vec<_>, _>>()?
         .into_iter()
         .flat_map(|stops| stops.into_iter())
         .chain(stops.into_iter().cloned());
 
-    let all_merged_stops = merge_stops(all_es_stops);
+    let all_merged_stops = merge_stops(all_es_stops, merge);
     let es_index_name = mimir::rubber::get_date_index_name(GLOBAL_STOP_INDEX_NAME);
 
-    rubber.create_index(&es_index_name, &index_settings)?;
+    backend.create_index(&es_index_name, &index_settings)?;
     let typed_index = TypedIndex::new(es_index_name.clone());
 
-    let nb_stops_added = rubber.bulk_index(&typed_index, all_merged_stops)?;
+    let nb_stops_added = backend.bulk_index(&typed_index, all_merged_stops)?;
     info!("{} stops added in the global index", nb_stops_added);
     // create global index
     // fill structure for each stop indexes
@@ -226,21 +774,186 @@ fn update_global_stop_index<'a, It: Iterator<Item = &'a mimir::Stop>>(
 
 // publish the global stop index
 // alias the new index to the global stop alias, and remove the old index
-fn publish_global_index(rubber: &mut Rubber, new_global_index: &str) -> Result<(), Error> {
-    let last_global_indexes: Vec<_> = rubber
+fn publish_global_index<B: Backend>(backend: &mut B, new_global_index: &str) -> Result<(), Error> {
+    let last_global_indexes: Vec<_> = backend
         .get_all_aliased_index(GLOBAL_STOP_INDEX_NAME)?
         .into_iter()
         .map(|(k, _)| k)
         .filter(|k| k != new_global_index)
         .collect();
-    rubber.alias(
+    backend.alias(
         GLOBAL_STOP_INDEX_NAME,
         &vec![new_global_index.to_string()],
         &last_global_indexes,
     )?;
 
     for index in last_global_indexes {
-        rubber.delete_index(&index)?;
+        backend.delete_index(&index)?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(id: &str, name: &str, lon: f64, lat: f64, dataset: &str) -> mimir::Stop {
+        mimir::Stop {
+            id: id.to_string(),
+            name: name.to_string(),
+            coord: mimir::Coord::new(lon, lat),
+            coverages: vec![dataset.to_string()],
+            physical_modes: vec![mimir::PhysicalMode {
+                id: format!("physical_mode:{}", dataset),
+                name: dataset.to_string(),
+            }],
+            lines: vec![mimir::Line {
+                id: format!("line:{}", dataset),
+                name: dataset.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn merge_args(merge_distance: Option<f64>) -> MergeArgs {
+        MergeArgs {
+            merge_distance,
+            merge_name_similarity: 0.8,
+        }
+    }
+
+    #[test]
+    fn test_name_similarity() {
+        assert_eq!(normalize_name("Gare  de l'Est"), "gare de l est");
+        assert_eq!(normalize_name("Hôtel-de-Ville"), "hotel de ville");
+        assert!(name_similarity("hotel de ville", "hotel de ville") > 0.99);
+        assert!(name_similarity("gare de l est", "gare de lest") >= 0.8);
+        assert!(name_similarity("gare de l est", "gare du nord") < 0.8);
+    }
+
+    #[test]
+    fn test_merge_stops_by_id() {
+        let stops = vec![
+            stop("stop_area:A", "Hôtel de Ville", 2.3522, 48.8566, "d1"),
+            stop("stop_area:A", "Hôtel de Ville", 2.3522, 48.8566, "d2"),
+            stop("stop_area:B", "Hotel de ville", 2.3523, 48.8566, "d3"),
+        ];
+        let merged: Vec<_> = merge_stops(stops, &merge_args(None)).collect();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].id, "stop_area:A");
+        assert_eq!(merged[0].coverages, vec!["d1", "d2"]);
+        assert_eq!(merged[0].lines.len(), 2);
+        assert!(merged[0].codes.is_empty());
+    }
+
+    #[test]
+    fn test_merge_stops_by_distance() {
+        let stops = vec![
+            stop("stop_area:A", "Hôtel de Ville", 2.3522, 48.8566, "d1"),
+            // about 7 meters away, with a similar name
+            stop("stop_area:B", "Hotel-de-ville", 2.3523, 48.8566, "d2"),
+            // close but with another name
+            stop("stop_area:C", "Rivoli", 2.3523, 48.8567, "d2"),
+            // same name but too far
+            stop("stop_area:D", "Hotel de Ville", 2.3622, 48.8566, "d3"),
+            // close, with the same name, but in the same dataset
+            stop("stop_area:E", "Hôtel de Ville", 2.3522, 48.8566, "d1"),
+        ];
+        let merged: Vec<_> = merge_stops(stops, &merge_args(Some(50.))).collect();
+        let ids: Vec<_> = merged.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec!["stop_area:A", "stop_area:C", "stop_area:D", "stop_area:E"]
+        );
+
+        let hotel_de_ville = &merged[0];
+        assert_eq!(hotel_de_ville.name, "Hôtel de Ville");
+        assert_eq!(hotel_de_ville.coverages, vec!["d1", "d2"]);
+        assert_eq!(
+            hotel_de_ville.codes,
+            vec![
+                mimir::Code {
+                    name: "d1".to_string(),
+                    value: "stop_area:A".to_string(),
+                },
+                mimir::Code {
+                    name: "d2".to_string(),
+                    value: "stop_area:B".to_string(),
+                },
+            ]
+        );
+        let lines: Vec<_> = hotel_de_ville.lines.iter().map(|l| &l.id).collect();
+        assert_eq!(lines, vec!["line:d1", "line:d2"]);
+        assert_eq!(hotel_de_ville.physical_modes.len(), 2);
+    }
+
+    #[test]
+    fn test_merge_stops_of_three_datasets() {
+        let stops = vec![
+            stop("stop_area:A", "Gare du Nord", 2.3553, 48.8809, "d1"),
+            stop("stop_area:B", "Gare du Nord", 2.3554, 48.8809, "d2"),
+            stop("stop_area:C", "Gare du Nord", 2.3553, 48.8810, "d3"),
+        ];
+        let merged: Vec<_> = merge_stops(stops, &merge_args(Some(50.))).collect();
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].coverages, vec!["d1", "d2", "d3"]);
+        // each stop only has a code in its own dataset
+        let codes: Vec<_> = merged[0]
+            .codes
+            .iter()
+            .map(|c| (c.name.as_str(), c.value.as_str()))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                ("d1", "stop_area:A"),
+                ("d2", "stop_area:B"),
+                ("d3", "stop_area:C"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stale_indexes_are_deleted() {
+        let mut backend = mimir::memory::MemoryBackend::new();
+        let import = |backend: &mut mimir::memory::MemoryBackend, stop_points, lines| {
+            let objects = PtObjects {
+                stops: vec![stop("stop_area:A", "Gare de Lyon", 2.3733, 48.8443, "fr")],
+                stop_points,
+                lines,
+                ..Default::default()
+            };
+            let settings = IndexSettings {
+                nb_shards: 1,
+                nb_replicas: 0,
+            };
+            index_stops(objects, backend, None, "fr", settings).unwrap();
+        };
+        let nb_indexes = |backend: &mimir::memory::MemoryBackend, base_index| {
+            backend.get_all_aliased_index(base_index).unwrap().len()
+        };
+
+        let stop_point = mimir::StopPoint {
+            id: "stop_point:A1".to_string(),
+            name: "Gare de Lyon".to_string(),
+            stop_area: mimir::ParentStopArea {
+                id: "stop_area:A".to_string(),
+                name: "Gare de Lyon".to_string(),
+            },
+            ..Default::default()
+        };
+        let line = mimir::PtLine {
+            id: "line:1".to_string(),
+            name: "RER A".to_string(),
+            ..Default::default()
+        };
+        import(&mut backend, vec![stop_point], vec![line]);
+        assert_eq!(nb_indexes(&backend, "munin_stop_point_fr"), 1);
+        assert_eq!(nb_indexes(&backend, "munin_line_fr"), 1);
+        // the dataset has no stop point and no line anymore
+        import(&mut backend, vec![], vec![]);
+        assert_eq!(nb_indexes(&backend, "munin_stop_point_fr"), 0);
+        assert_eq!(nb_indexes(&backend, "munin_line_fr"), 0);
+    }
+}
@@ -30,7 +30,11 @@
 
 use crate::Error;
 use mimir;
+use mimir::export::{ExportBackend, ExportFormat};
+use mimir::quality::QualityGate;
+use mimir::rubber::Rubber;
 use slog_scope::error;
+use std::path::PathBuf;
 use std::process::exit;
 use std::sync::Arc;
 use structopt::StructOpt;
@@ -100,6 +104,91 @@ where
     }
 }
 
+/// Arguments of the importers to write their documents in a file instead of elasticsearch
+#[derive(StructOpt, Debug)]
+pub struct ExportArgs {
+    /// Write the documents in this file instead of elasticsearch
+    /// (compressed with gzip if its extension is gz).
+    /// The existing data needed by the import (the admins for example)
+    /// are still read in elasticsearch, if it is available.
+    #[structopt(long = "output", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+    /// Format of the output file: ndjson (one document by line),
+    /// bulk (elasticsearch bulk requests, for ES 2 to 6) or bulk-typeless (for ES 7).
+    /// The bulk requests do not publish the indexes: they have no alias action.
+    #[structopt(long = "output-format", default_value = "ndjson")]
+    pub output_format: ExportFormat,
+}
+
+impl ExportArgs {
+    /// the backend writing in the output file, if any,
+    /// the existing data are read in the cluster of `connection_string`
+    pub fn backend(&self, connection_string: &str) -> Result<Option<ExportBackend>, Error> {
+        match self.output {
+            Some(ref path) => Ok(Some(
+                ExportBackend::create(path, self.output_format)?
+                    .with_source(Rubber::new(connection_string)),
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Arguments of the importers on the publication of their indexes
+#[derive(StructOpt, Debug)]
+pub struct PublishArgs {
+    /// Number of previous generations of the indexes kept (without alias) when the new ones
+    /// are published, to be able to roll back the import with `mimir_admin rollback`.
+    #[structopt(long = "keep-previous", default_value = "0")]
+    pub keep_previous: usize,
+    /// Do not publish a new index if it has this percentage of documents less
+    /// than the published index of the same dataset.
+    #[structopt(long = "max-drop-percent")]
+    pub max_drop_percent: Option<f64>,
+    /// Json file of the queries that must give an expected first result in the new indexes
+    /// before they are published, for instance
+    /// `[{"type": "admin", "query": "Paris", "id": "admin:fr:75056"}]`
+    /// (a query can also be restricted to a "dataset").
+    #[structopt(long = "smoke-queries", parse(from_os_str))]
+    pub smoke_queries: Option<PathBuf>,
+}
+
+impl PublishArgs {
+    /// the rubber of `connection_string`, publishing the indexes with these arguments
+    pub fn rubber(&self, connection_string: &str) -> Result<Rubber, Error> {
+        let smoke_queries = match self.smoke_queries {
+            Some(ref path) => QualityGate::read_smoke_queries(path)?,
+            None => vec![],
+        };
+        let rubber = Rubber::new(connection_string).with_keep_previous(self.keep_previous);
+        if self.max_drop_percent.is_none() && smoke_queries.is_empty() {
+            return Ok(rubber);
+        }
+        Ok(rubber.with_quality_gate(QualityGate {
+            max_drop_percent: self.max_drop_percent,
+            smoke_queries,
+        }))
+    }
+}
+
+/// Arguments of the stop importers on the merge of the stops of all the datasets
+/// in the global stop index
+///
+/// They are not saved: the global stop index is rebuilt by each import with its own
+/// arguments, so the same ones must be given to the imports of all the datasets.
+#[derive(StructOpt, Debug, Clone)]
+pub struct MergeArgs {
+    /// In the global stop index, also merge the stops of different datasets within
+    /// this distance (in meters) of each other and with similar names.
+    /// Without it the stops are only merged by id.
+    #[structopt(long = "merge-distance")]
+    pub merge_distance: Option<f64>,
+    /// Minimum similarity (between 0 and 1) of the normalized names of two stops merged
+    /// by distance.
+    #[structopt(long = "merge-name-similarity", default_value = "0.8")]
+    pub merge_name_similarity: f64,
+}
+
 pub fn get_country_code(codes: &[mimir::Code]) -> Option<String> {
     codes
         .iter()
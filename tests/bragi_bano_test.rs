@@ -58,6 +58,8 @@ pub fn bragi_bano_test(es_wrapper: crate::ElasticSearchWrapper<'_>) {
     simple_bano_lon_lat_test(&mut bragi);
     long_bano_address_test(&mut bragi);
     reverse_bano_test(&mut bragi);
+    batch_bano_autocomplete_test(&mut bragi);
+    structured_bano_autocomplete_test(&mut bragi);
 }
 
 fn status_test(bragi: &mut BragiHandler) {
@@ -244,4 +246,86 @@ fn reverse_bano_test(bragi: &mut BragiHandler) {
         get_values(&res, "label"),
         vec!["2 Rue des Pins (Beauzelle)"]
     );
+
+    // several results can be asked, the nearest first
+    let res = bragi.get_json("/reverse?lon=2.37716&lat=48.8468&limit=3");
+    let features = res.pointer("/features").unwrap().as_array().unwrap();
+    assert_eq!(features.len(), 3);
+    assert_eq!(
+        features[0].pointer("/properties/geocoding/label"),
+        Some(&json!("20 Rue Hector Malot (Paris)"))
+    );
+    let distances: Vec<u64> = features
+        .iter()
+        .map(|f| f["distance"].as_u64().unwrap())
+        .collect();
+    assert!(distances.windows(2).all(|d| d[0] <= d[1]));
+
+    // nothing is found out of the radius
+    let res = bragi.get("/reverse?lon=2.37716&lat=48.8468&radius=1");
+    assert!(res.is_empty());
+
+    assert_eq!(
+        bragi.get_status("/reverse?lon=2.37716&lat=48.8468&radius=-10"),
+        actix_web::http::StatusCode::BAD_REQUEST
+    );
+}
+
+fn batch_bano_autocomplete_test(bragi: &mut BragiHandler) {
+    let batch = r#"[
+        {"q": "15 Rue Hector Malot (Paris)"},
+        {"q": "20 rue hector malot", "lat": 50.2, "lon": 2.0},
+        {"q": "20 rue hector malot", "lat": 50.2},
+        {"q": "rue hector malot", "zone_type": ["city"]}
+    ]"#;
+    let res = bragi.post_as_json("/autocomplete/batch", batch);
+    let res = res.as_array().expect("the batch response must be an array");
+    assert_eq!(res.len(), 4);
+
+    let labels = |r: &serde_json::Value| -> Vec<String> {
+        r.pointer("/features")
+            .and_then(|f| f.as_array())
+            .unwrap()
+            .iter()
+            .filter_map(|f| f.pointer("/properties/geocoding/label"))
+            .filter_map(|l| l.as_str())
+            .map(|l| l.to_string())
+            .collect()
+    };
+    assert_eq!(labels(&res[0]), vec!["15 Rue Hector Malot (Paris)"]);
+    assert_eq!(
+        labels(&res[1]),
+        vec![
+            "20 Rue Hector Malot (Trifouilli-les-Oies)",
+            "20 Rue Hector Malot (Paris)",
+        ]
+    );
+    // the invalid queries are reported inline
+    assert_eq!(
+        res[2].pointer("/error/short"),
+        Some(&json!("validation error"))
+    );
+    assert_eq!(
+        res[3].pointer("/error/long"),
+        Some(&json!(
+            "Invalid parameter: zone_type[] parameter requires to have 'type[]=zone'"
+        ))
+    );
+}
+
+fn structured_bano_autocomplete_test(bragi: &mut BragiHandler) {
+    // the postcode discriminates between the 2 'rue hector malot'
+    let geocodings =
+        bragi.get("/autocomplete?housenumber=20&street=rue hector malot&postcode=42042");
+    assert_eq!(geocodings.len(), 1);
+    assert_eq!(
+        get_values(&geocodings, "label"),
+        vec!["20 Rue Hector Malot (Trifouilli-les-Oies)"]
+    );
+
+    // 'q' and the structured parameters are exclusive
+    assert_eq!(
+        bragi.get_status("/autocomplete?q=rue hector malot&street=rue hector malot"),
+        actix_web::http::StatusCode::BAD_REQUEST
+    );
 }
@@ -0,0 +1,242 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use super::BragiHandler;
+use super::{get_types, get_value, get_values};
+use geo_types::{LineString, MultiLineString};
+use mimir::backend::Backend;
+use mimir::memory::MemoryBackend;
+use mimir::objects::{Addr, Admin, Coord, Stop, Street};
+use mimir::rubber::IndexSettings;
+use std::sync::Arc;
+
+/// a small dataset around the Gare de Lyon, indexed like the importers do
+fn make_backend() -> MemoryBackend {
+    let mut backend = MemoryBackend::new();
+    let settings = IndexSettings {
+        nb_shards: 1,
+        nb_replicas: 0,
+    };
+
+    let paris = Arc::new(Admin {
+        id: "admin:fr:75056".to_string(),
+        insee: "75056".to_string(),
+        level: 8,
+        label: "Paris".to_string(),
+        name: "Paris".to_string(),
+        zip_codes: vec!["75012".to_string()],
+        weight: 1.,
+        coord: Coord::new(2.3522, 48.8566),
+        zone_type: Some(cosmogony::ZoneType::City),
+        ..Default::default()
+    });
+    let france = Arc::new(Admin {
+        id: "admin:fr".to_string(),
+        level: 2,
+        label: "France".to_string(),
+        name: "France".to_string(),
+        weight: 1.,
+        coord: Coord::new(2.35, 48.85),
+        zone_type: Some(cosmogony::ZoneType::Country),
+        country_codes: vec!["fr".to_string()],
+        ..Default::default()
+    });
+    let street = Street {
+        id: "street:hector_malot".to_string(),
+        name: "Rue Hector Malot".to_string(),
+        administrative_regions: vec![paris.clone(), france.clone()],
+        label: "Rue Hector Malot (Paris)".to_string(),
+        coord: Coord::new(2.3770, 48.8469),
+        zip_codes: vec!["75012".to_string()],
+        ..Default::default()
+    };
+    // a long street, its coord is far from its ends
+    let boulevard = Street {
+        id: "street:boulevard_diderot".to_string(),
+        name: "Boulevard Diderot".to_string(),
+        administrative_regions: vec![paris.clone()],
+        label: "Boulevard Diderot (Paris)".to_string(),
+        coord: Coord::new(2.3950, 48.8440),
+        geometry: Some(MultiLineString(vec![LineString::from(vec![
+            (2.3850, 48.8440),
+            (2.3950, 48.8440),
+            (2.4050, 48.8440),
+        ])])),
+        zip_codes: vec!["75012".to_string()],
+        ..Default::default()
+    };
+    // some short streets, nearer than the coord of the long street
+    let short_streets = (0..10).map(|i| Street {
+        id: format!("street:short_{}", i),
+        name: format!("Rue Courte {}", i),
+        administrative_regions: vec![paris.clone()],
+        label: format!("Rue Courte {} (Paris)", i),
+        coord: Coord::new(2.3880 + f64::from(i) * 0.0001, 48.8452),
+        zip_codes: vec!["75012".to_string()],
+        ..Default::default()
+    });
+    let addr = Addr {
+        id: "addr:2.37716;48.8468".to_string(),
+        name: "10 Rue Hector Malot".to_string(),
+        house_number: "10".to_string(),
+        street: street.clone(),
+        label: "10 Rue Hector Malot (Paris)".to_string(),
+        coord: Coord::new(2.37716, 48.8468),
+        approx_coord: None,
+        weight: 0.,
+        zip_codes: vec!["75012".to_string()],
+        country_codes: vec!["fr".to_string()],
+        distance: None,
+        context: None,
+        interpolated: false,
+    };
+    let stop = Stop {
+        id: "stop_area:gare_de_lyon".to_string(),
+        label: "Gare de Lyon (Paris)".to_string(),
+        name: "Gare de Lyon".to_string(),
+        coord: Coord::new(2.3731, 48.8443),
+        administrative_regions: vec![paris.clone()],
+        coverages: vec!["dataset1".to_string()],
+        ..Default::default()
+    };
+
+    backend
+        .public_index(
+            "fr",
+            &settings,
+            vec![(*paris).clone(), (*france).clone()].into_iter(),
+        )
+        .unwrap();
+    backend
+        .public_index(
+            "fr",
+            &settings,
+            vec![street, boulevard].into_iter().chain(short_streets),
+        )
+        .unwrap();
+    backend
+        .public_index("fr", &settings, std::iter::once(addr))
+        .unwrap();
+    backend
+        .public_index("dataset1", &settings, std::iter::once(stop))
+        .unwrap();
+    backend
+}
+
+/// bragi on the in memory backend, no elasticsearch is needed
+pub fn bragi_memory_test() {
+    let mut bragi = BragiHandler::with_backend(Arc::new(make_backend()));
+
+    // the prefix of the label is matched
+    let res = bragi.get("/autocomplete?q=rue hect");
+    assert_eq!(get_values(&res, "label"), vec!["Rue Hector Malot (Paris)"]);
+
+    // with a house number, only the address matches
+    let res = bragi.get("/autocomplete?q=10 rue hector malot");
+    assert_eq!(get_types(&res), vec!["house"]);
+    assert_eq!(
+        get_values(&res, "label"),
+        vec!["10 Rue Hector Malot (Paris)"]
+    );
+
+    // filter by type
+    let res = bragi.get("/autocomplete?q=paris");
+    let types = get_types(&res);
+    assert!(types.contains(&"zone"));
+    assert!(types.contains(&"street"));
+    let res = bragi.get("/autocomplete?q=paris&type[]=zone");
+    assert_eq!(get_values(&res, "id"), vec!["admin:fr:75056"]);
+
+    // the stops are only found in their dataset
+    assert!(bragi.get("/autocomplete?q=gare de lyon").is_empty());
+    let res = bragi.get("/autocomplete?q=gare de lyon&pt_dataset[]=dataset1");
+    assert_eq!(get_values(&res, "id"), vec!["stop_area:gare_de_lyon"]);
+    assert!(bragi
+        .get("/autocomplete?q=gare de lyon&pt_dataset[]=dataset2")
+        .is_empty());
+
+    // reverse geocoding, the nearest place first
+    let res = bragi.get("/reverse?lon=2.37716&lat=48.8468&limit=2");
+    assert_eq!(
+        get_values(&res, "label"),
+        vec!["10 Rue Hector Malot (Paris)", "Rue Hector Malot (Paris)"]
+    );
+    let res = bragi.get("/reverse?lon=2.37716&lat=48.8468&radius=1");
+    assert_eq!(get_types(&res), vec!["house"]);
+    // the stops are searched in their dataset
+    let stop_area = "/reverse?lon=2.3731&lat=48.8443&type[]=public_transport:stop_area";
+    let res = bragi.get(&format!("{}&pt_dataset[]=dataset1", stop_area));
+    assert_eq!(get_values(&res, "id"), vec!["stop_area:gare_de_lyon"]);
+    assert!(bragi
+        .get(&format!("{}&pt_dataset[]=dataset2", stop_area))
+        .is_empty());
+
+    // the distance to a street is the distance to its geometry,
+    // the long street is found even if the coord of more than 10 streets is nearer
+    let res = bragi.get("/reverse?lon=2.3860&lat=48.8441&radius=500");
+    assert_eq!(get_values(&res, "id"), vec!["street:boulevard_diderot"]);
+    let res = bragi.get("/reverse?lon=2.3860&lat=48.8441&limit=2");
+    assert_eq!(
+        get_values(&res, "id"),
+        vec!["street:boulevard_diderot", "street:short_0"]
+    );
+
+    // features
+    let res = bragi.get("/features/street:hector_malot");
+    assert_eq!(get_value(&res[0], "name"), "Rue Hector Malot");
+    let geometry_type = |json: &serde_json::Value| {
+        json.pointer("/features/0/geometry/type")
+            .and_then(|t| t.as_str())
+            .map(str::to_string)
+    };
+    let res = bragi.get_json("/features/street:boulevard_diderot");
+    assert_eq!(geometry_type(&res), Some("Point".to_string()));
+    let res = bragi.get_json("/features/street:boulevard_diderot?full_geometry=true");
+    assert_eq!(geometry_type(&res), Some("MultiLineString".to_string()));
+
+    // structured geocoding, the admins of an address are the ones of its street
+    let address = "/autocomplete?housenumber=10&street=rue hector malot";
+    let res = bragi.get(&format!("{}&city=paris", address));
+    assert_eq!(get_values(&res, "id"), vec!["addr:2.37716;48.8468"]);
+    // the country is given by its ISO code or by its name
+    let res = bragi.get(&format!("{}&country=FR", address));
+    assert_eq!(get_values(&res, "id"), vec!["addr:2.37716;48.8468"]);
+    let res = bragi.get(&format!("{}&country=France", address));
+    assert_eq!(get_values(&res, "id"), vec!["addr:2.37716;48.8468"]);
+    assert!(bragi
+        .get(&format!("{}&country=Germany", address))
+        .is_empty());
+    // without house number, only the streets are searched
+    let res = bragi.get("/autocomplete?street=rue hector malot&country=France");
+    assert_eq!(get_values(&res, "id"), vec!["street:hector_malot"]);
+    let res = bragi.get("/autocomplete?street=rue hector malot&postcode=75012");
+    assert_eq!(get_values(&res, "id")[0], "street:hector_malot");
+    assert!(get_types(&res).iter().all(|t| *t == "street"));
+}
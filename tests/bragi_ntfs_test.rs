@@ -49,6 +49,7 @@ pub fn bragi_ntfs_test(es_wrapper: crate::ElasticSearchWrapper<'_>) {
     );
 
     gare_de_lyon(&mut bragi);
+    lines_and_networks(&mut bragi);
 
     let ntfs2mimir = out_dir.join("../../../ntfs2mimir").display().to_string();
     crate::launch_and_assert(
@@ -62,6 +63,19 @@ pub fn bragi_ntfs_test(es_wrapper: crate::ElasticSearchWrapper<'_>) {
     );
 
     gare_de_lyon_with_two_datasets(&mut bragi);
+
+    crate::launch_and_assert(
+        &ntfs2mimir,
+        &[
+            "--input=./tests/fixtures/ntfs/".into(),
+            "--dataset=dataset1".into(),
+            "--stop-points".into(),
+            format!("--connection-string={}", es_wrapper.host()),
+        ],
+        &es_wrapper,
+    );
+
+    gare_de_lyon_stop_points(&mut bragi);
 }
 
 fn gare_de_lyon(bragi: &mut BragiHandler) {
@@ -228,3 +242,101 @@ fn gare_de_lyon_with_two_datasets(bragi: &mut BragiHandler) {
         ])
     );
 }
+
+fn gare_de_lyon_stop_points(bragi: &mut BragiHandler) {
+    // the stop points are not returned by default
+    let response = bragi.get("/autocomplete?q=gare de lyon&pt_dataset[]=dataset1");
+    assert_eq!(response.len(), 1);
+    assert_eq!(
+        get_value(response.first().unwrap(), "type"),
+        "public_transport:stop_area"
+    );
+
+    let response = bragi.get(
+        "/autocomplete?q=gare de lyon&pt_dataset[]=dataset1&type[]=public_transport:stop_point",
+    );
+    let mut ids: Vec<_> = response.iter().map(|r| get_value(r, "id")).collect();
+    ids.sort();
+    assert_eq!(
+        ids,
+        vec!["stop_point:GDLB", "stop_point:GDLM", "stop_point:GDLR"]
+    );
+    for stop_point in &response {
+        assert_eq!(get_value(stop_point, "type"), "public_transport:stop_point");
+        assert_eq!(
+            stop_point.get("stop_area").unwrap(),
+            &json!({"id": "stop_area:GDL", "name": "Gare de Lyon"})
+        );
+    }
+
+    let rer = bragi.get("/features/stop_point:GDLR?pt_dataset[]=dataset1");
+    assert_eq!(rer.len(), 1);
+    let rer = rer.first().unwrap();
+    assert_eq!(get_value(rer, "name"), "Gare de Lyon (RER)");
+    assert_eq!(get_value(rer, "timezone"), "Europe/Paris");
+    assert_eq!(
+        rer.get("lines")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|l| l["id"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["line:RERA"]
+    );
+
+    // the stop points are not in the other datasets
+    let response = bragi.get(
+        "/autocomplete?q=gare de lyon&pt_dataset[]=dataset2&type[]=public_transport:stop_point",
+    );
+    assert!(response.is_empty());
+}
+
+fn lines_and_networks(bragi: &mut BragiHandler) {
+    // the lines are not returned by default
+    let response = bragi.get("/autocomplete?q=rer a&pt_dataset[]=dataset1");
+    assert!(response
+        .iter()
+        .all(|r| get_value(r, "type") != "public_transport:line"));
+
+    let response =
+        bragi.get("/autocomplete?q=rer a&pt_dataset[]=dataset1&type[]=public_transport:line");
+    let rer = response.first().unwrap();
+    assert_eq!(get_value(rer, "type"), "public_transport:line");
+    assert_eq!(get_value(rer, "id"), "line:RERA");
+    assert_eq!(get_value(rer, "label"), "RER A");
+    assert_eq!(
+        rer.get("commercial_modes").unwrap(),
+        &json!([
+            {"id": "commercial_mode:RER", "name": "Réseau Express Régional (RER)"}
+        ])
+    );
+    assert_eq!(
+        rer.get("network").unwrap(),
+        &json!({"id": "network:TGN", "name": "The Great Network"})
+    );
+
+    let response =
+        bragi.get("/autocomplete?q=bus 42&pt_dataset[]=dataset1&type[]=public_transport:line");
+    assert_eq!(get_value(response.first().unwrap(), "id"), "line:B42");
+
+    let response =
+        bragi.get("/autocomplete?q=metro 1&pt_dataset[]=dataset1&type[]=public_transport:line");
+    let metro = response.first().unwrap();
+    assert_eq!(get_value(metro, "id"), "line:M1");
+    assert_eq!(get_value(metro, "color"), "7D36F5");
+    assert_eq!(get_value(metro, "text_color"), "FFFFFF");
+
+    let response = bragi
+        .get("/autocomplete?q=great network&pt_dataset[]=dataset1&type[]=public_transport:network");
+    assert_eq!(response.len(), 1);
+    let network = response.first().unwrap();
+    assert_eq!(get_value(network, "type"), "public_transport:network");
+    assert_eq!(get_value(network, "id"), "network:TGN");
+    assert_eq!(get_value(network, "name"), "The Great Network");
+
+    // the lines can also be fetched by id
+    let response = bragi.get("/features/line:B5?pt_dataset[]=dataset1");
+    assert_eq!(response.len(), 1);
+    assert_eq!(get_value(response.first().unwrap(), "label"), "Bus 5");
+}
@@ -64,6 +64,7 @@ pub fn bragi_osm_test(es_wrapper: crate::ElasticSearchWrapper<'_>) {
     bbox_admin_test(&mut bragi);
     city_admin_test(&mut bragi);
     administrative_region_test(&mut bragi);
+    reverse_admin_fallback_test(&mut bragi);
 }
 
 fn zip_code_test(bragi: &mut BragiHandler) {
@@ -160,3 +161,27 @@ fn administrative_region_test(bragi: &mut BragiHandler) {
         .count();
     assert_eq!(admin_region, 1);
 }
+
+fn reverse_admin_fallback_test(bragi: &mut BragiHandler) {
+    // no address nor street in a 1 meter radius, we get the city containing the point
+    let res = bragi.get_json("/reverse?lon=2.698&lat=48.524&radius=1");
+    assert_eq!(
+        res.pointer("/geocoding/admin_fallback"),
+        Some(&serde_json::json!(true))
+    );
+    let features = res.pointer("/features").unwrap().as_array().unwrap();
+    assert_eq!(features.len(), 1);
+    let geocoding = features[0].pointer("/properties/geocoding").unwrap();
+    assert_eq!(geocoding["type"], "zone");
+    assert_eq!(geocoding["name"], "Vaux-le-Pénil");
+
+    // with a normal match, there is no fallback flag
+    let res = bragi.get_json("/reverse?lon=2.698&lat=48.524&radius=5000");
+    assert_eq!(res.pointer("/geocoding/admin_fallback"), None);
+
+    // no admin contains the point either, there is no result and no fallback flag
+    let res = bragi.get_json("/reverse?lon=-30.&lat=10.&radius=1");
+    assert_eq!(res.pointer("/geocoding/admin_fallback"), None);
+    let features = res.pointer("/features").unwrap().as_array().unwrap();
+    assert!(features.is_empty());
+}
@@ -35,6 +35,7 @@ use super::get_types;
 use super::get_value;
 use super::get_values;
 use super::BragiHandler;
+use mimir::backend::Backend;
 use mimir::{MimirObject, Poi};
 use serde_json::json;
 use std::path::Path;
@@ -182,3 +182,73 @@ pub fn cosmogony2mimir_test(es_wrapper: crate::ElasticSearchWrapper<'_>) {
         _ => panic!("should be an admin"),
     }
 }
+
+/// import a cosmogony file and some addresses, then import again the cosmogony file
+/// with a renamed city: the admins of the addresses are updated without importing them again
+pub fn cosmogony2mimir_update_dependent_indexes_test(es_wrapper: crate::ElasticSearchWrapper<'_>) {
+    let out_dir = Path::new(env!("OUT_DIR"));
+    let cosmogony2mimir = out_dir
+        .join("../../../cosmogony2mimir")
+        .display()
+        .to_string();
+    crate::launch_and_assert(
+        &cosmogony2mimir,
+        &[
+            "--input=./tests/fixtures/cosmogony.json".into(),
+            format!("--connection-string={}", es_wrapper.host()),
+        ],
+        &es_wrapper,
+    );
+    let bano2mimir = out_dir.join("../../../bano2mimir").display().to_string();
+    crate::launch_and_assert(
+        &bano2mimir,
+        &[
+            "--input=./tests/fixtures/bano-three_cities.csv".into(),
+            format!("--connection-string={}", es_wrapper.host()),
+        ],
+        &es_wrapper,
+    );
+
+    let renamed_cosmogony = out_dir.join("cosmogony_renamed.json");
+    let cosmogony = std::fs::read_to_string("./tests/fixtures/cosmogony.json")
+        .unwrap()
+        .replace(
+            r#""name": "Livry-sur-Seine""#,
+            r#""name": "Livry-sur-Seine-la-Neuve""#,
+        );
+    std::fs::write(&renamed_cosmogony, cosmogony).unwrap();
+    crate::launch_and_assert(
+        &cosmogony2mimir,
+        &[
+            format!("--input={}", renamed_cosmogony.display()),
+            "--update-dependent-indexes".into(),
+            format!("--connection-string={}", es_wrapper.host()),
+        ],
+        &es_wrapper,
+    );
+
+    let res: Vec<_> = es_wrapper
+        .search_and_filter("label:Chaux", |p| p.is_addr())
+        .collect();
+    assert_eq!(res.len(), 1);
+    match &res[0] {
+        &mimir::Place::Addr(ref addr) => {
+            assert_eq!(
+                addr.label,
+                "3 Rue du Four à Chaux (Livry-sur-Seine-la-Neuve)"
+            );
+            assert_eq!(
+                addr.street.label,
+                "Rue du Four à Chaux (Livry-sur-Seine-la-Neuve)"
+            );
+            let livry = addr
+                .street
+                .administrative_regions
+                .iter()
+                .find(|a| a.id == "admin:osm:relation:215390")
+                .expect("the address should still be in Livry-sur-Seine");
+            assert_eq!(livry.name, "Livry-sur-Seine-la-Neuve");
+        }
+        _ => panic!("should be an address"),
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::Command;
+
+/// An import written in a file needs no elasticsearch: the admins are just not found
+pub fn bano2mimir_export_test() {
+    let bano2mimir = Path::new(env!("OUT_DIR"))
+        .join("../../../bano2mimir")
+        .display()
+        .to_string();
+    let output = std::env::temp_dir().join("bano2mimir_export_test.bulk.gz");
+    let status = Command::new(&bano2mimir)
+        .args(&[
+            "--input=./tests/fixtures/sample-bano.csv".to_string(),
+            // nothing listens on this port
+            "--connection-string=http://localhost:1".to_string(),
+            format!("--output={}", output.display()),
+            "--output-format=bulk".to_string(),
+        ])
+        .status()
+        .unwrap();
+    assert!(status.success(), "`{}` failed {}", bano2mimir, &status);
+
+    // the gzip stream must be complete to be read to the end
+    let lines: Vec<serde_json::Value> =
+        BufReader::new(GzDecoder::new(File::open(&output).unwrap()))
+            .lines()
+            .map(|l| serde_json::from_str(&l.unwrap()).unwrap())
+            .collect();
+    std::fs::remove_file(&output).unwrap();
+
+    // an index action and a document by address, and no alias action
+    assert_eq!(lines.len(), 2 * 36);
+    for pair in lines.chunks(2) {
+        let action = &pair[0]["index"];
+        assert!(action["_index"]
+            .as_str()
+            .unwrap()
+            .starts_with("munin_addr_fr_"));
+        assert_eq!(action["_type"], "addr");
+        assert_eq!(action["_id"], pair[1]["id"]);
+        assert_eq!(pair[1]["type"], "addr");
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use mimir;
+use std::path::Path;
+
+/// Import of a small GTFS feed with gtfs2mimir
+/// Checks that the stop areas are imported with their lines, modes and weights
+pub fn gtfs2mimir_sample_test(es_wrapper: crate::ElasticSearchWrapper<'_>) {
+    let gtfs2mimir = Path::new(env!("OUT_DIR"))
+        .join("../../../gtfs2mimir")
+        .display()
+        .to_string();
+    crate::launch_and_assert(
+        &gtfs2mimir,
+        &[
+            "--input=./tests/fixtures/gtfs/".into(),
+            format!("--connection-string={}", es_wrapper.host()),
+            "--dataset=dataset1".into(),
+        ],
+        &es_wrapper,
+    );
+
+    // 2 stop areas and the stop area generated for the lonely stop point
+    let stops: Vec<_> = es_wrapper
+        .search_and_filter("*", |p| p.is_stop())
+        .filter_map(|p| match p {
+            mimir::Place::Stop(stop) => Some(stop),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(stops.len(), 3);
+    assert!(stops.iter().all(|s| s.coverages == vec!["dataset1"]));
+    assert!(stops.iter().all(|s| !s.feed_publishers.is_empty()));
+
+    let gare = stops.iter().find(|s| s.id == "stop_area:GARE").unwrap();
+    assert_eq!(gare.label, "Gare Centrale");
+    assert_eq!(gare.lines.len(), 2);
+    assert_eq!(
+        gare.physical_modes
+            .iter()
+            .map(|m| m.id.as_str())
+            .collect::<Vec<_>>(),
+        vec!["physical_mode:Bus", "physical_mode:Tramway"]
+    );
+    assert_eq!(gare.commercial_modes.len(), 2);
+
+    let darcy = stops.iter().find(|s| s.id == "stop_area:DARCY").unwrap();
+    assert_eq!(darcy.lines.len(), 1);
+    assert_eq!(
+        darcy
+            .physical_modes
+            .iter()
+            .map(|m| m.id.as_str())
+            .collect::<Vec<_>>(),
+        vec!["physical_mode:Tramway"]
+    );
+
+    // the stop area with 2 stop points is heavier than the one with only 1
+    assert!(gare.weight > darcy.weight);
+
+    let port = stops.iter().find(|s| s.label == "Port Fluvial").unwrap();
+    assert_eq!(port.lines.len(), 1);
+    assert_eq!(port.physical_modes[0].id, "physical_mode:Bus");
+
+    // the lines of the feed (one by route here) are also imported
+    let nb_lines = es_wrapper
+        .search_and_filter("*", |p| p.is_pt_line())
+        .count();
+    assert_eq!(nb_lines, 2);
+}
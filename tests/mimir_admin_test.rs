@@ -0,0 +1,129 @@
+// Copyright © 2016, Canal TP and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Canal TP (www.canaltp.fr).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+use docker_wrapper::SNAPSHOT_LOCATION;
+use mimir::backend::Backend;
+use mimir::rubber::IndexSettings;
+use mimir::{Coord, Street};
+use serde_json::Value;
+use std::path::Path;
+
+fn street(id: &str) -> Street {
+    let coord = Coord::new(2.68326290f64, 48.5110722f64);
+    Street {
+        id: id.to_string(),
+        name: format!("{}'s street", id),
+        label: format!("{}'s street", id),
+        weight: 0.42,
+        coord: coord.clone(),
+        approx_coord: Some(coord.into()),
+        ..Default::default()
+    }
+}
+
+fn get_street_ids(es: &crate::ElasticSearchWrapper<'_>) -> Vec<String> {
+    let search = es.search("*:*");
+    search
+        .pointer("/hits/hits")
+        .and_then(Value::as_array)
+        .unwrap()
+        .iter()
+        .filter_map(|hit| hit.pointer("/_source/id").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect()
+}
+
+/// snapshot a dataset, import a new version of it, and restore the snapshot
+pub fn mimir_admin_snapshot_restore_test(mut es: crate::ElasticSearchWrapper<'_>) {
+    info!("running mimir_admin_snapshot_restore_test");
+    let mimir_admin = Path::new(env!("OUT_DIR"))
+        .join("../../../mimir_admin")
+        .display()
+        .to_string();
+    let aliases = Path::new(env!("OUT_DIR"))
+        .join("mimir_admin_test.aliases.json")
+        .display()
+        .to_string();
+    let index_settings = IndexSettings {
+        nb_shards: 1,
+        nb_replicas: 0,
+    };
+
+    let result = es
+        .rubber
+        .public_index("fr", &index_settings, std::iter::once(street("bob")));
+    assert_eq!(result.unwrap(), 1);
+    es.refresh();
+    assert_eq!(get_street_ids(&es), vec!["bob"]);
+
+    crate::launch_and_assert(
+        &mimir_admin,
+        &[
+            format!("--connection-string={}", es.host()),
+            "snapshot".into(),
+            "--snapshot=before_bobette".into(),
+            format!("--location={}", SNAPSHOT_LOCATION),
+            format!("--aliases={}", aliases),
+        ],
+        &es,
+    );
+
+    // the new import replaces bob
+    let result = es
+        .rubber
+        .public_index("fr", &index_settings, std::iter::once(street("bobette")));
+    assert_eq!(result.unwrap(), 1);
+    es.refresh();
+    assert_eq!(get_street_ids(&es), vec!["bobette"]);
+
+    crate::launch_and_assert(
+        &mimir_admin,
+        &[
+            format!("--connection-string={}", es.host()),
+            "restore".into(),
+            "--snapshot=before_bobette".into(),
+            format!("--aliases={}", aliases),
+        ],
+        &es,
+    );
+
+    // bob is back, in a restored index with all the aliases of the snapshotted one
+    assert_eq!(get_street_ids(&es), vec!["bob"]);
+    let layout = es.rubber.get_all_aliased_index("munin").unwrap();
+    assert_eq!(layout.len(), 1);
+    let (index, mut aliases) = layout.into_iter().next().unwrap();
+    assert!(index.starts_with("munin_street_fr_"));
+    assert!(index.contains("_restored_"));
+    aliases.sort();
+    assert_eq!(
+        aliases,
+        vec!["munin", "munin_geo_data", "munin_street", "munin_street_fr"]
+    );
+}
@@ -134,7 +134,7 @@ pub fn poi2mimir_sample_test(es_wrapper: crate::ElasticSearchWrapper<'_>) {
 
     // Now that we're sure we're hitting the munin_poi_mti index, count how many documents we have
     // in there. This should be the same number of POI as in the test.poi file we inserted.
-    assert_eq!(es_wrapper.count("munin_poi_mti", "_type:poi"), 4);
+    assert_eq!(es_wrapper.count("munin_poi_mti", "type:poi"), 4);
 
     // Ok, now check that we can get a POI on that index
     let agence_du_four = es_wrapper
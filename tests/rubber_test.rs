@@ -31,6 +31,7 @@
 use cosmogony::ZoneType;
 use geo;
 use geo::prelude::BoundingRect;
+use mimir::backend::Backend;
 use mimir::rubber::{self, IndexSettings, Rubber};
 use mimir::{Admin, Coord, MimirObject, Street};
 use reqwest;
@@ -47,7 +48,10 @@ fn check_has_elt<F: FnMut(&Value)>(es: &crate::ElasticSearchWrapper<'_>, mut fun
 fn check_has_bob(es: &crate::ElasticSearchWrapper<'_>) {
     let check_is_bob = |es_elt: &Value| {
         assert_eq!(
-            es_elt.pointer("/_type").and_then(|t| t.as_str()).unwrap(),
+            es_elt
+                .pointer("/_source/type")
+                .and_then(|t| t.as_str())
+                .unwrap(),
             "street"
         );
         let es_bob = es_elt.pointer("/_source").unwrap();
@@ -128,7 +132,10 @@ pub fn rubber_zero_downtime_test(mut es: crate::ElasticSearchWrapper<'_>) {
     // then we should have our bobette
     let check_is_bobette = |es_elt: &Value| {
         assert_eq!(
-            es_elt.pointer("/_type").and_then(|t| t.as_str()).unwrap(),
+            es_elt
+                .pointer("/_source/type")
+                .and_then(|t| t.as_str())
+                .unwrap(),
             "street"
         );
         let es_bob = es_elt.pointer("/_source").unwrap();
@@ -191,7 +198,10 @@ pub fn rubber_custom_id(mut es: crate::ElasticSearchWrapper<'_>) {
 
     let check_admin = |es_elt: &Value| {
         assert_eq!(
-            es_elt.pointer("/_type").and_then(|t| t.as_str()).unwrap(),
+            es_elt
+                .pointer("/_source/type")
+                .and_then(|t| t.as_str())
+                .unwrap(),
             Admin::doc_type()
         );
         let es_source = es_elt.pointer("/_source").unwrap();
@@ -326,3 +336,38 @@ pub fn rubber_empty_bulk(mut es: crate::ElasticSearchWrapper<'_>) {
     assert!(result.is_ok());
     assert_eq!(result.unwrap(), 0); // we have indexed nothing, but it's ok
 }
+
+pub fn rubber_rejected_document(mut es: crate::ElasticSearchWrapper<'_>) {
+    // a document rejected by ES is skipped, the other documents are indexed
+    info!("running rubber_rejected_document");
+    let admin = |id: &str, ring: Vec<(f64, f64)>| Admin {
+        id: id.to_string(),
+        name: id.to_string(),
+        label: id.to_string(),
+        boundary: Some(geo::MultiPolygon(vec![geo::Polygon::new(
+            geo::LineString(ring.into_iter().map(|c| c.into()).collect()),
+            vec![],
+        )])),
+        zone_type: Some(ZoneType::City),
+        ..Default::default()
+    };
+    let valid = admin(
+        "admin:valid",
+        vec![(2., 48.), (2., 49.), (3., 49.), (3., 48.), (2., 48.)],
+    );
+    // a ring needs at least 4 points
+    let invalid = admin("admin:invalid", vec![(2., 48.), (3., 49.), (2., 48.)]);
+    let index_settings = IndexSettings {
+        nb_shards: 1,
+        nb_replicas: 0,
+    };
+    let result = es
+        .rubber
+        .public_index("fr", &index_settings, vec![invalid, valid].into_iter());
+    assert_eq!(result.unwrap(), 1);
+
+    es.refresh();
+    check_has_elt(&es, |es_elt| {
+        assert_eq!(es_elt.pointer("/_id"), Some(&json!("admin:valid")));
+    });
+}
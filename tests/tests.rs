@@ -39,6 +39,7 @@ extern crate assert_float_eq;
 mod bano2mimir_test;
 mod bragi_bano_test;
 mod bragi_filter_types_test;
+mod bragi_memory_test;
 mod bragi_ntfs_test;
 mod bragi_osm_test;
 mod bragi_poi_test;
@@ -47,6 +48,9 @@ mod bragi_synonyms_test;
 mod bragi_three_cities_test;
 mod canonical_import_process_test;
 mod cosmogony2mimir_test;
+mod export_test;
+mod gtfs2mimir_test;
+mod mimir_admin_test;
 mod openaddresses2mimir_test;
 mod osm2mimir_bano2mimir_test;
 mod osm2mimir_test;
@@ -133,6 +137,7 @@ fn all_tests() {
     osm2mimir_test::osm2mimir_sample_test(ElasticSearchWrapper::new(&docker_wrapper));
     osm2mimir_test::osm2mimir_sample_test_sqlite(ElasticSearchWrapper::new(&docker_wrapper));
     stops2mimir_test::stops2mimir_sample_test(ElasticSearchWrapper::new(&docker_wrapper));
+    gtfs2mimir_test::gtfs2mimir_sample_test(ElasticSearchWrapper::new(&docker_wrapper));
     osm2mimir_bano2mimir_test::osm2mimir_bano2mimir_test(ElasticSearchWrapper::new(
         &docker_wrapper,
     ));
@@ -141,6 +146,7 @@ fn all_tests() {
     rubber_test::rubber_custom_id(ElasticSearchWrapper::new(&docker_wrapper));
     rubber_test::rubber_ghost_index_cleanup(ElasticSearchWrapper::new(&docker_wrapper));
     rubber_test::rubber_empty_bulk(ElasticSearchWrapper::new(&docker_wrapper));
+    rubber_test::rubber_rejected_document(ElasticSearchWrapper::new(&docker_wrapper));
     bragi_bano_test::bragi_bano_test(ElasticSearchWrapper::new(&docker_wrapper));
     bragi_osm_test::bragi_osm_test(ElasticSearchWrapper::new(&docker_wrapper));
     bragi_poi_test::test_i18n_poi(ElasticSearchWrapper::new(&docker_wrapper));
@@ -153,10 +159,22 @@ fn all_tests() {
     bragi_synonyms_test::bragi_synonyms_test(ElasticSearchWrapper::new(&docker_wrapper));
     openaddresses2mimir_test::oa2mimir_simple_test(ElasticSearchWrapper::new(&docker_wrapper));
     cosmogony2mimir_test::cosmogony2mimir_test(ElasticSearchWrapper::new(&docker_wrapper));
+    cosmogony2mimir_test::cosmogony2mimir_update_dependent_indexes_test(ElasticSearchWrapper::new(
+        &docker_wrapper,
+    ));
     canonical_import_process_test::canonical_import_process_test(ElasticSearchWrapper::new(
         &docker_wrapper,
     ));
     canonical_import_process_test::bragi_invalid_es_test(ElasticSearchWrapper::new(
         &docker_wrapper,
     ));
+    mimir_admin_test::mimir_admin_snapshot_restore_test(ElasticSearchWrapper::new(&docker_wrapper));
+}
+
+/// The tests without elasticsearch (on the in memory backend, or exporting to a file)
+/// Note: the logger is initialized by `all_tests`, it can only be done once
+#[test]
+fn memory_tests() {
+    bragi_memory_test::bragi_memory_test();
+    export_test::bano2mimir_export_test();
 }